@@ -0,0 +1,73 @@
+//! Parses a batch of short log lines with a fresh parser each time, and with one
+//! [`ParserSession`] for all of them, counting allocations and timing both.
+//!
+//! Run with `cargo bench --bench session`.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use cfg_toy::recognizer::ParserSession;
+
+struct CountingAlloc;
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn measure(name: &str, lines: &[Vec<u8>], mut parse: impl FnMut(&[u8])) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for line in lines {
+        parse(line);
+    }
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    println!(
+        "{name:>8}: {:>8.2} allocations/line, {:>8.2?}/line",
+        allocations as f64 / lines.len() as f64,
+        elapsed / lines.len() as u32,
+    );
+}
+
+fn main() {
+    let (cfg, _) = cfg_toy::cfg! {
+        line level words word letter;
+
+        line ::= "[" level "] " words .
+        level ::= "INFO" .
+        level ::= "WARN" .
+        level ::= "ERROR" .
+        words ::= word " " words .
+        words ::= word .
+        word ::= letter word .
+        word ::= letter .
+        letter ::= "a" . letter ::= "c" . letter ::= "d" . letter ::= "e" . letter ::= "i" .
+        letter ::= "l" . letter ::= "n" . letter ::= "o" . letter ::= "r" . letter ::= "s" .
+        letter ::= "t" . letter ::= "u" .
+    };
+    let messages = ["started", "connection closed", "reload started", "no action needed"];
+    let levels = ["INFO", "WARN", "ERROR"];
+    let lines = (0..30_000)
+        .map(|i| format!("[{}] {}", levels[i % levels.len()], messages[i % messages.len()]).into_bytes())
+        .collect::<Vec<_>>();
+
+    measure("fresh", &lines, |line| {
+        cfg_toy::parse_earley(&cfg, line, 256, ());
+    });
+    let mut session = ParserSession::new(&cfg);
+    measure("session", &lines, |line| {
+        session.parse(line, 256, ());
+    });
+}
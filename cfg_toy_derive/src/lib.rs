@@ -0,0 +1,351 @@
+//! `#[derive(Grammar)]` for `cfg_toy`. See `cfg_toy::derive` for how types map to grammars.
+//!
+//! There's no `syn` here, the items are parsed straight from the token stream. We only
+//! need the shape of the type: its name, its variants, and its fields' names and types.
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+
+#[proc_macro_derive(Grammar, attributes(grammar))]
+pub fn derive_grammar(input: TokenStream) -> TokenStream {
+    match parse_item(input).and_then(|item| generate(&item)) {
+        Ok(code) => code.parse().unwrap(),
+        Err(Error(span, message)) => compile_error(span, &message),
+    }
+}
+
+struct Error(Span, String);
+type Result<T> = std::result::Result<T, Error>;
+
+fn compile_error(span: Span, message: &str) -> TokenStream {
+    let tokens = [
+        TokenTree::Ident(Ident::new("compile_error", span)),
+        TokenTree::Punct(Punct::new('!', Spacing::Alone)),
+        TokenTree::Group(Group::new(
+            Delimiter::Parenthesis,
+            TokenTree::Literal(Literal::string(message)).into(),
+        )),
+        TokenTree::Punct(Punct::new(';', Spacing::Alone)),
+    ];
+    tokens
+        .into_iter()
+        .map(|mut token| {
+            token.set_span(span);
+            token
+        })
+        .collect()
+}
+
+struct Item {
+    name: Ident,
+    is_enum: bool,
+    rules: Vec<Variant>,
+}
+/// A struct or a variant of an enum, which both become a single rule.
+struct Variant {
+    name: Ident,
+    attr: Option<Group>,
+    shape: Shape,
+    fields: Vec<Field>,
+}
+#[derive(PartialEq)]
+enum Shape {
+    Unit,
+    Tuple,
+    Named,
+}
+struct Field {
+    name: Option<Ident>,
+    ty: String,
+}
+/// A part of a rule, as written in the `grammar` attribute.
+enum Part {
+    Literal(Literal),
+    Field(usize),
+}
+
+fn parse_item(input: TokenStream) -> Result<Item> {
+    let mut tokens = input.into_iter().peekable();
+    let attr = parse_attrs(&mut tokens)?;
+    skip_visibility(&mut tokens);
+    let keyword = expect_ident(tokens.next(), "expected `struct` or `enum`")?;
+    let is_enum = match &*keyword.to_string() {
+        "struct" => false,
+        "enum" => true,
+        _ => return Err(Error(keyword.span(), "Grammar can only be derived for structs and enums".into())),
+    };
+    let name = expect_ident(tokens.next(), "expected the type's name")?;
+    let rules = match tokens.next() {
+        Some(TokenTree::Punct(p)) if p.as_char() == '<' => {
+            return Err(Error(p.span(), "Grammar can't be derived for generic types".into()));
+        }
+        Some(TokenTree::Ident(i)) if i.to_string() == "where" => {
+            return Err(Error(i.span(), "Grammar can't be derived for generic types".into()));
+        }
+        Some(TokenTree::Group(body)) if is_enum => {
+            if let Some(attr) = attr {
+                return Err(Error(attr.span(), "put `grammar` attributes on the enum's variants".into()));
+            }
+            split_commas(body.stream())
+                .into_iter()
+                .map(parse_variant)
+                .collect::<Result<_>>()?
+        }
+        fields => {
+            let (shape, fields) = parse_fields(fields)?;
+            vec![Variant {
+                name: name.clone(),
+                attr,
+                shape,
+                fields,
+            }]
+        }
+    };
+    Ok(Item { name, is_enum, rules })
+}
+
+type Tokens = std::iter::Peekable<std::vec::IntoIter<TokenTree>>;
+
+// Returns the `grammar` attribute's arguments, if there is one
+fn parse_attrs(tokens: &mut std::iter::Peekable<impl Iterator<Item = TokenTree>>) -> Result<Option<Group>> {
+    let mut grammar = None;
+    while let Some(TokenTree::Punct(p)) = tokens.peek() {
+        if p.as_char() != '#' {
+            break;
+        }
+        tokens.next();
+        let Some(TokenTree::Group(attr)) = tokens.next() else {
+            unreachable!("attributes are always bracketed");
+        };
+        let mut inner = attr.stream().into_iter();
+        if let Some(TokenTree::Ident(path)) = inner.next()
+            && path.to_string() == "grammar"
+        {
+            match inner.next() {
+                Some(TokenTree::Group(args)) if args.delimiter() == Delimiter::Parenthesis => {
+                    if grammar.replace(args).is_some() {
+                        return Err(Error(path.span(), "only one `grammar` attribute is allowed".into()));
+                    }
+                }
+                _ => return Err(Error(path.span(), "expected `#[grammar(...)]`".into())),
+            }
+        }
+    }
+    Ok(grammar)
+}
+
+fn skip_visibility(tokens: &mut std::iter::Peekable<impl Iterator<Item = TokenTree>>) {
+    if let Some(TokenTree::Ident(i)) = tokens.peek()
+        && i.to_string() == "pub"
+    {
+        tokens.next();
+        if let Some(TokenTree::Group(g)) = tokens.peek()
+            && g.delimiter() == Delimiter::Parenthesis
+        {
+            tokens.next();
+        }
+    }
+}
+
+fn expect_ident(token: Option<TokenTree>, message: &str) -> Result<Ident> {
+    match token {
+        Some(TokenTree::Ident(i)) => Ok(i),
+        Some(other) => Err(Error(other.span(), message.into())),
+        None => Err(Error(Span::call_site(), message.into())),
+    }
+}
+
+// Groups are single tokens, so only the angle brackets of generic types need counting
+fn split_commas(stream: TokenStream) -> Vec<Tokens> {
+    let mut items = vec![];
+    let mut current = vec![];
+    let mut depth = 0usize;
+    // Whether the last token was the `-` of `->`
+    let mut arrow = false;
+    for token in stream {
+        let after_arrow = std::mem::replace(
+            &mut arrow,
+            matches!(&token, TokenTree::Punct(p) if p.as_char() == '-' && p.spacing() == Spacing::Joint),
+        );
+        if let TokenTree::Punct(p) = &token {
+            match p.as_char() {
+                '<' => depth += 1,
+                // The return type of a function type, like `fn() -> u8`
+                '>' if after_arrow => {}
+                '>' => depth = depth.saturating_sub(1),
+                ',' if depth == 0 => {
+                    items.push(std::mem::take(&mut current).into_iter().peekable());
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        current.push(token);
+    }
+    if !current.is_empty() {
+        items.push(current.into_iter().peekable());
+    }
+    items
+}
+
+fn parse_variant(mut tokens: Tokens) -> Result<Variant> {
+    let attr = parse_attrs(&mut tokens)?;
+    let name = expect_ident(tokens.next(), "expected a variant")?;
+    let (shape, fields) = parse_fields(tokens.next())?;
+    Ok(Variant {
+        name,
+        attr,
+        shape,
+        fields,
+    })
+}
+
+fn parse_fields(token: Option<TokenTree>) -> Result<(Shape, Vec<Field>)> {
+    let group = match token {
+        Some(TokenTree::Group(group)) => group,
+        // Unit structs end with `;`, and unit variants can have a discriminant
+        _ => return Ok((Shape::Unit, vec![])),
+    };
+    let named = group.delimiter() == Delimiter::Brace;
+    let mut fields = vec![];
+    for mut tokens in split_commas(group.stream()) {
+        parse_attrs(&mut tokens)?;
+        skip_visibility(&mut tokens);
+        let name = if named {
+            let name = expect_ident(tokens.next(), "expected a field")?;
+            tokens.next(); // :
+            Some(name)
+        } else {
+            None
+        };
+        let ty = tokens.collect::<TokenStream>().to_string();
+        fields.push(Field { name, ty });
+    }
+    Ok((if named { Shape::Named } else { Shape::Tuple }, fields))
+}
+
+impl Variant {
+    fn parts(&self) -> Result<Vec<Part>> {
+        let Some(attr) = &self.attr else {
+            return Ok((0..self.fields.len()).map(Part::Field).collect());
+        };
+        let mut parts = vec![];
+        let mut used = vec![false; self.fields.len()];
+        let mut next_positional = 0;
+        for token in attr.stream() {
+            let field = match token {
+                TokenTree::Literal(literal) => {
+                    let text = literal.to_string();
+                    if !text.starts_with('"') && !text.starts_with('r') {
+                        return Err(Error(literal.span(), "literals in the grammar must be strings".into()));
+                    }
+                    parts.push(Part::Literal(literal));
+                    continue;
+                }
+                TokenTree::Ident(ident) if ident.to_string() == "_" => {
+                    while used.get(next_positional) == Some(&true) {
+                        next_positional += 1;
+                    }
+                    if next_positional == self.fields.len() {
+                        return Err(Error(ident.span(), "there are no more fields for `_`".into()));
+                    }
+                    next_positional
+                }
+                TokenTree::Ident(ident) => {
+                    let name = ident.to_string();
+                    let Some(idx) = self
+                        .fields
+                        .iter()
+                        .position(|field| field.name.as_ref().is_some_and(|n| n.to_string() == name))
+                    else {
+                        return Err(Error(ident.span(), format!("there's no field named `{name}`")));
+                    };
+                    if used[idx] {
+                        return Err(Error(ident.span(), format!("the field `{name}` is already in the rule")));
+                    }
+                    idx
+                }
+                other => {
+                    return Err(Error(
+                        other.span(),
+                        "expected a string literal, `_` or a field name".into(),
+                    ));
+                }
+            };
+            used[field] = true;
+            parts.push(Part::Field(field));
+        }
+        if let Some(missing) = used.iter().position(|used| !used) {
+            let field = match &self.fields[missing].name {
+                Some(name) => format!("`{name}`"),
+                None => format!("{missing}"),
+            };
+            return Err(Error(attr.span(), format!("field {field} isn't in the rule")));
+        }
+        Ok(parts)
+    }
+}
+
+fn generate(item: &Item) -> Result<String> {
+    let name = &item.name;
+    let mut add_rules = String::new();
+    let mut arms = String::new();
+    for (alternative, variant) in item.rules.iter().enumerate() {
+        let parts = variant.parts()?;
+        add_rules.push_str("{ let mut rule = ::cfg_toy::grammar::Rule::new(nt, vec![]);");
+        let mut bindings = String::new();
+        for part in &parts {
+            match part {
+                Part::Literal(literal) => {
+                    add_rules.push_str(&format!("rule.parts.extend(::cfg_toy::derive::literal({literal}));"));
+                }
+                Part::Field(idx) => {
+                    let Field { name, ty } = &variant.fields[*idx];
+                    if let Some(name) = name {
+                        add_rules.push_str(&format!("rule.fields.push((\"{name}\", rule.parts.len()));"));
+                    }
+                    add_rules.push_str(&format!(
+                        "rule.parts.push(<{ty} as ::cfg_toy::derive::Grammar>::nonterminal(builder));"
+                    ));
+                    // The children come in the order of the rule, not the fields
+                    bindings.push_str(&format!(
+                        "let field_{idx} = <{ty} as ::cfg_toy::derive::Grammar>::from_tree(children.next().unwrap());"
+                    ));
+                }
+            }
+        }
+        add_rules.push_str("builder.add_rule(rule); }");
+
+        let path = if item.is_enum {
+            format!("Self::{}", variant.name)
+        } else {
+            "Self".to_string()
+        };
+        let value = match variant.shape {
+            Shape::Unit => path,
+            Shape::Tuple => {
+                let fields = (0..variant.fields.len()).map(|i| format!("field_{i}"));
+                format!("{path}({})", fields.collect::<Vec<_>>().join(", "))
+            }
+            Shape::Named => {
+                let fields = variant.fields.iter().enumerate().map(|(i, field)| {
+                    format!("{}: field_{i}", field.name.as_ref().unwrap())
+                });
+                format!("{path} {{ {} }}", fields.collect::<Vec<_>>().join(", "))
+            }
+        };
+        arms.push_str(&format!("{alternative} => {{ {bindings} {value} }}"));
+    }
+    Ok(format!(
+        "impl ::cfg_toy::derive::Grammar for {name} {{
+            fn name() -> String {{ \"{name}\".to_string() }}
+            fn add_rules(builder: &mut ::cfg_toy::derive::GrammarBuilder, nt: u32) {{ {add_rules} }}
+            fn from_tree(tree: ::cfg_toy::derive::Tree<'_, '_>) -> Self {{
+                #[allow(unused_mut, unused_variables)]
+                let mut children = tree.children();
+                match tree.alternative() {{
+                    {arms}
+                    alternative => unreachable!(\"`{name}` has no rule {{alternative}}\"),
+                }}
+            }}
+        }}"
+    ))
+}
@@ -0,0 +1,72 @@
+//! Semantic actions, yacc style.
+//!
+//! An action computes the value of a rule from the values of its nonterminal children.
+//! They're written in the grammar after the rule's parts:
+//!
+//! ```text
+//! expr ::= and_expr or expr => |l, _, r| l || r .
+//! ```
+//!
+//! The `cfg!` macro keeps them in an [`Actions`] table alongside the `Cfg`,
+//! when it's given the type of the values (`expr and_expr or => bool; ...`).
+use crate::grammar::{Cfg, ReshapedError, Rule};
+use crate::{CfgSymbol, Node};
+
+pub type Action<T> = Box<dyn Fn(Vec<T>) -> T>;
+
+/// The actions for each rule of a grammar, indexed like `Cfg::rules`.
+pub struct Actions<T> {
+    pub by_rule: Vec<Option<Action<T>>>,
+}
+impl<T> Actions<T> {
+    /// Build the grammar and its action table together, so the actions follow their
+    /// rules when `Cfg::new` orders them.
+    pub fn with_cfg<Symbol: CfgSymbol>(
+        rules: Vec<Rule<Symbol>>,
+        actions: Vec<Option<Action<T>>>,
+    ) -> (Cfg<Symbol>, Self) {
+        assert_eq!(rules.len(), actions.len());
+        let mut pairs = rules.into_iter().zip(actions).collect::<Vec<_>>();
+        // This is the same (stable) ordering that `Cfg::new` uses
+        pairs.sort_by_key(|(rule, _)| rule.for_nt);
+        let (rules, by_rule) = pairs.into_iter().unzip();
+        (Cfg::new(rules), Self { by_rule })
+    }
+}
+impl<T> std::fmt::Debug for Actions<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.by_rule.iter().map(|action| action.as_ref().map(|_| "action")))
+            .finish()
+    }
+}
+
+/// Used by `cfg_rules!` to give the closure its argument type from the table.
+#[doc(hidden)]
+pub fn action<T>(_table: &[Option<Action<T>>], f: impl Fn(Vec<T>) -> T + 'static) -> Option<Action<T>> {
+    Some(Box::new(f))
+}
+
+/// Run the actions over the tree produced by `trace_to_ast`, bottom up.
+///
+/// Rules without an action take the value of their first child, like yacc's `$$ = $1`.
+/// If they don't have any children, the value is `T::default()`.
+///
+/// The actions get a value for each nonterminal in their rule, so this fails for trees
+/// with nodes that have been reshaped by the grammar's [`Shape`](crate::grammar::Shape)s.
+pub fn evaluate<'c, Symbol: CfgSymbol, T: Default>(
+    cfg: &Cfg<Symbol>,
+    ast: &[Node<'c, Symbol>],
+    actions: &Actions<T>,
+) -> Result<T, ReshapedError> {
+    let end = ast[0].transitive_children + 1;
+    if let Some(node) = ast[..end].iter().position(|node| cfg.rule_is_reshaped(node.rule)) {
+        return Err(ReshapedError { node });
+    }
+    Ok(crate::visit::fold(ast, |node: &Node<'c, Symbol>, children: Vec<T>| {
+        match &actions.by_rule[node.rule] {
+            Some(action) => action(children),
+            None => children.into_iter().next().unwrap_or_default(),
+        }
+    }))
+}
@@ -41,38 +41,38 @@ fn main() {
     };
     println!("{:?}", mycfg);
     mycfg.rules.sort_by_key(|rule| rule.for_nt);
-    let mut trace = vec![];
     let src = "true or false and not false or bb".as_bytes();
     let completions = cfg_toy::parse_earley(
         &mycfg,
         src,
         256,
-        &mut trace,
+        (),
     );
-    trace.sort_by_key(|m| (m.1, -(m.2 as i32)));
     struct PrintRemainingList<'a, Symbol>(
-        &'a [(u32, (usize, u32, &'a [Symbol], cfg_toy::completions::Remaining<'a, Symbol>))],
-        &'a [cfg_toy::recognizer::State<'a, Symbol>],
+        &'a cfg_toy::completions::Completions<'a, Symbol>,
+        std::ops::Range<usize>,
     );
     impl<Symbol: core::fmt::Debug> core::fmt::Debug for PrintRemainingList<'_, Symbol> {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let cfg = self.0.cfg;
             let mut list = f.debug_list();
-            for &(ntsym, (back_ref, sym_here, rule, ref rem)) in self.0 {
-                match rem {
-                    cfg_toy::completions::Remaining::EmptyAndForwardingTo(start, end) => {
+            for c in &self.0.completions[self.1.clone()] {
+                let (back_ref, sym_here, rule) = (c.state.origin, c.state.sym(cfg), c.state.parts(cfg));
+                match c.forwarded {
+                    Some(record) => {
                         list.entry(&format_args!(
-                            "\n  ({}, ({}, {}, {:?}, bypass[{}..{}]({:?})))",
-                            ntsym,
+                            "\n  ({}, ({}, {}, {:?}, bypass[{}]({:?})))",
+                            c.awaited,
                             back_ref,
                             sym_here,
                             rule,
-                            start,
-                            end,
-                            &self.1[*start..*end]
+                            record,
+                            &self.0.forwarding_records[record as usize]
                         ));
                     }
-                    cfg_toy::completions::Remaining::More(syms) => {
-                        list.entry(&format_args!("\n  {:?}", (ntsym, (back_ref, sym_here, rule, syms))));
+                    None => {
+                        let syms = c.state.remaining(cfg);
+                        list.entry(&format_args!("\n  {:?}", (c.awaited, (back_ref, sym_here, rule, syms))));
                     }
                 }
             }
@@ -80,14 +80,11 @@ fn main() {
         }
     }
     for (i, window) in completions.completion_index.windows(2).enumerate() {
-        println!("{i:02} {:?}", PrintRemainingList(
-            &completions.completions[window[0]..window[1]],
-            &completions.forwarding_records,
-        ));
+        println!("{i:02} {:?}", PrintRemainingList(&completions, window[0]..window[1]));
         // println!("{i:02} {:?}", &completions.completions[window[0]..window[1]]);
+        println!("   completed {:?}", completions.completed_at(i));
     }
-    println!("{trace:?}");
-    let ast = cfg_toy::trace_to_ast(&mycfg, src, &trace, &completions, &256);
+    let ast = cfg_toy::trace_to_ast(&mycfg, src, &completions, &256);
     cfg_toy::print_ast(&ast, 0);
 
     let (right_assoc_cfg, _) = cfg_toy::cfg! {
@@ -102,29 +99,25 @@ fn main() {
     // let src = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaab".as_bytes();
     let src = "aaaaaaaaaaab".as_bytes();
     // let src = "aaaaaaaa".as_bytes();
-    let mut trace = vec![];
-    let completions = cfg_toy::parse_earley(&right_assoc_cfg, src, 256, &mut trace);
+    let completions = cfg_toy::parse_earley(&right_assoc_cfg, src, 256, ());
     for (i, window) in completions.completion_index.windows(2).enumerate() {
-        println!("{i:02} {:?}", PrintRemainingList(
-            &completions.completions[window[0]..window[1]],
-            &completions.forwarding_records,
-        ));
+        println!("{i:02} {:?}", PrintRemainingList(&completions, window[0]..window[1]));
         // println!("{i:02} {:?}", &completions.completions[window[0]..window[1]]);
+        println!("   completed {:?}", completions.completed_at(i));
     }
-    println!("{trace:?}");
-    let ast = cfg_toy::trace_to_ast(&right_assoc_cfg, src, &trace, &completions, &256);
+    let ast = cfg_toy::trace_to_ast(&right_assoc_cfg, src, &completions, &256);
     cfg_toy::print_ast(&ast, 0);
     // panic!();
     cfg_toy::parse_earley(&mycfg, "true then".as_bytes(), 256, ());
     let src = "true then".as_bytes();
     let init_sym = 256;
-    let mut trace = vec![];
-    let completions = cfg_toy::parse_earley(&mycfg, src, init_sym, &mut trace);
-    for &(start, end, state, rule) in &trace {
-        println!("{} {:?} {rule:?}", state_names[state as usize - 256], start..end);
+    let completions = cfg_toy::parse_earley(&mycfg, src, init_sym, ());
+    for end in 0..=src.len() {
+        for &(state, start, rule) in completions.completed_at(end) {
+            println!("{} {:?} {:?}", state_names[state as usize - 256], start..end, mycfg.rules[rule as usize].parts);
+        }
     }
-    trace.sort_by_key(|m| (m.1, m.2));
-    let ast = cfg_toy::trace_to_ast(&mycfg, src, &trace, &completions, &init_sym);
+    let ast = cfg_toy::trace_to_ast(&mycfg, src, &completions, &init_sym);
     println!("{ast:?}");
 
     let (json_cfg, state_names) = cfg_toy::cfg! {
@@ -300,14 +293,11 @@ r#"[{"name": "tala","strapped": "somewhat"}, {"compiler":"rustc","version": 7.27
         symbol: 256,
         label: "json",
     };
-    let mut trace = vec![];
     let src = cfg_toy::cast_buf(&src);
     // panic!("{:?} {:?}", &src[215..220], &src[220..]);
-    let completions = cfg_toy::parse_earley(&json_cfg, src, init_sym.symbol, &mut trace);
-    // trace.sort_by_key(|m| (m.1, m.2));
-    let ast = cfg_toy::trace_to_ast(&json_cfg, src, &trace, &completions, &init_sym);
+    let completions = cfg_toy::parse_earley(&json_cfg, src, init_sym.symbol, ());
+    let ast = cfg_toy::trace_to_ast(&json_cfg, src, &completions, &init_sym);
     cfg_toy::print_ast(&ast, 0);
-    println!("{trace:?}");
     // panic!();
     // println!("{:?}", json_cfg.nt_nullable.iter().enumerate().collect::<Vec<_>>());
     // // println!("{:?}", json_cfg.nt_to_nullable_rules_index.iter().enumerate().collect::<Vec<_>>());
@@ -451,13 +441,10 @@ r#"[{"name": "tala","strapped": "somewhat"}, {"compiler":"rustc","version": 7.27
         alpha ::= "z" .
     "#;
     let src = cfg_toy::cast_buf(src_bytes);
-    let mut trace = vec![];
-    let completions = cfg_toy::parse_earley(&bnf_grammar, src, 256, &mut trace);
-    trace.sort_by_key(|m| (m.1, m.2, (m.0 as isize)));
+    let completions = cfg_toy::parse_earley(&bnf_grammar, src, 256, ());
     let ast = cfg_toy::trace_to_ast(
         &bnf_grammar,
         src,
-        &trace,
         &completions,
         &cfg_toy::LabelledSymbol {
             symbol: 256,
@@ -473,20 +460,13 @@ r#"[{"name": "tala","strapped": "somewhat"}, {"compiler":"rustc","version": 7.27
             while bench_content.len() < target_length {
                 bench_content.extend_from_slice(src_bytes);
             }
-            let mut trace = vec![];
             println!("testing {n}");
             let start = std::time::Instant::now();
-            let completions =
-                cfg_toy::parse_earley(bnf_grammar_u32, &bench_content, 256, &mut trace);
-            // println!("{trace:?}");
-            let built_trace = start.elapsed().as_secs_f64();
-            // println!("now tracing {:?} {:?}", trace.len(), bench_content.len());
-            trace.sort_by_key(|m| (m.1, m.2, (m.0 as isize)));
-            let sorted_trace = start.elapsed().as_secs_f64();
-            _ = cfg_toy::trace_to_ast(bnf_grammar_u32, &bench_content, &trace, &completions, &256);
+            let completions = cfg_toy::parse_earley(bnf_grammar_u32, &bench_content, 256, ());
+            let built_chart = start.elapsed().as_secs_f64();
+            _ = cfg_toy::trace_to_ast(bnf_grammar_u32, &bench_content, &completions, &256);
             data.push((
-                built_trace,
-                sorted_trace,
+                built_chart,
                 start.elapsed().as_secs_f64(),
                 target_length,
             ));
@@ -576,20 +556,17 @@ r#"[{"name": "tala","strapped": "somewhat"}, {"compiler":"rustc","version": 7.27
                 .unwrap_or("terminal"),
         }
     });
-    let mut trace = vec![];
     let src = b"a<b>(c)";
     let src = cfg_toy::cast_buf(src);
-    let completions = cfg_toy::parse_earley(&ambiguous_grammar, src, 256, &mut trace);
-    trace.sort_by_key(|m| (m.1, m.2, m.0));
-    trace.dedup(); // empty rules get duplicated in the trace
-    for &(start, end, state, rule) in &trace {
-        println!("{} {:?} {rule:?}", state_names[state as usize - 256], start..end);
+    let completions = cfg_toy::parse_earley(&ambiguous_grammar, src, 256, ());
+    for end in 0..=src.len() {
+        for &(state, start, rule) in completions.completed_at(end) {
+            println!("{} {:?} {:?}", state_names[state as usize - 256], start..end, ambiguous_grammar.rules[rule as usize].parts);
+        }
     }
-    trace.sort_by_key(|m| (m.1, m.2, (m.0 as isize)));
     let ast = cfg_toy::trace_to_ast(
         &ambiguous_grammar,
         src,
-        &trace,
         &completions,
         &cfg_toy::LabelledSymbol {
             symbol: 256,
@@ -680,18 +657,17 @@ r#"[{"name": "tala","strapped": "somewhat"}, {"compiler":"rustc","version": 7.27
             .map(|idx| state_names[idx as usize])
             .unwrap_or("terminal"),
     });
-    let mut trace = vec![];
     let src = b"aab";
     let src = cfg_toy::cast_buf(src);
-    let completions = cfg_toy::parse_earley(&grammar, src, 256, &mut trace);
-    for &(start, end, state, rule) in &trace {
-        println!("{} {:?} {rule:?}  ", state_names[state as usize - 256], start..end);
+    let completions = cfg_toy::parse_earley(&grammar, src, 256, ());
+    for end in 0..=src.len() {
+        for &(state, start, rule) in completions.completed_at(end) {
+            println!("{} {:?} {:?}  ", state_names[state as usize - 256], start..end, grammar.rules[rule as usize].parts);
+        }
     }
-    trace.sort_by_key(|m| (m.1, m.2, -(m.0 as isize)));
     let ast = cfg_toy::trace_to_ast(
         &grammar,
         src,
-        &trace,
         &completions,
         &cfg_toy::LabelledSymbol {
             symbol: 256,
@@ -702,8 +678,6 @@ r#"[{"name": "tala","strapped": "somewhat"}, {"compiler":"rustc","version": 7.27
 }
 
 fn parse_succeeds(grammar: &cfg_toy::grammar::Cfg<u32>, src: &[u8], init_sym: u32) {
-    let mut trace = vec![];
-    let completions = cfg_toy::parse_earley(grammar, src, init_sym, &mut trace);
-    trace.sort_by_key(|m| (m.1, m.2, (m.0 as isize)));
-    cfg_toy::trace_to_ast(grammar, src, &trace, &completions, &init_sym);
+    let completions = cfg_toy::parse_earley(grammar, src, init_sym, ());
+    cfg_toy::trace_to_ast(grammar, src, &completions, &init_sym);
 }
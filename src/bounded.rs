@@ -0,0 +1,345 @@
+//! Recognizing without keeping the chart around.
+//!
+//! [`try_parse_earley`](crate::try_parse_earley) keeps the items waiting at every position
+//! so that trees can be built afterwards. When only a yes or no is needed, most of them can
+//! go as soon as no state refers back to their position anymore. A [`Recognizer`] keeps
+//! the positions in a small pool of groups, and every so often frees the ones that can't
+//! be reached from the current states.
+//!
+//! Deterministic reduction paths are followed as soon as a position is done, as in Leo's
+//! optimisation, so the positions in the middle of a right recursion aren't kept alive by
+//! the items waiting above them. With a grammar that's mostly regular, the memory then
+//! stays proportional to how deeply the input nests rather than to how long it is.
+//!
+//! ```
+//! let (cfg, _) = cfg_toy::cfg! {
+//!     lines line;
+//!     lines ::= line "\n" lines .
+//!     lines ::= line .
+//!     line ::= "a" line .
+//!     line ::= .
+//! };
+//! let src = "aaa\n\na\n".repeat(10_000) + "a";
+//! assert!(cfg_toy::bounded::recognize(&cfg, src.bytes(), 256).is_ok());
+//! assert_eq!(cfg_toy::bounded::recognize(&cfg, "a\nb".bytes(), 256).err().map(|e| e.position), Some(2));
+//! ```
+use std::borrow::Borrow;
+
+use crate::buffer_pair::{BufferPair, Transfer};
+use crate::grammar::Cfg;
+use crate::recognizer::{NtSymbol, ParseError, State};
+use crate::set_buffers::{StateSet, grow_set, sorted_set};
+use crate::{CfgSymbol, Either};
+
+/// Recognize `src` as `init_sym`, see the [module docs](self).
+pub fn recognize<Symbol: CfgSymbol + Ord, T: Borrow<Symbol::Terminal>>(
+    cfg: &Cfg<Symbol>,
+    src: impl IntoIterator<Item = T>,
+    init_sym: u32,
+) -> Result<(), ParseError> {
+    let mut recognizer = Recognizer::new(cfg, init_sym);
+    for symbol in src {
+        recognizer.push(symbol.borrow())?;
+    }
+    recognizer.finish()
+}
+
+/// The items waiting at a position.
+#[derive(Debug, Default)]
+struct Group {
+    position: usize,
+    // Sorted by the awaited nonterminal once the position is done. A deterministic
+    // item is replaced by the top of its reduction path.
+    waiting: Vec<(NtSymbol, State)>,
+    marked: bool,
+}
+
+/// A recognizer that's fed the input one symbol at a time, see the [module docs](self).
+///
+/// The origins of the states are indices into the pool of groups rather than
+/// positions, so they stay small however long the input gets.
+#[derive(Debug)]
+pub struct Recognizer<'c, Symbol> {
+    cfg: &'c Cfg<Symbol>,
+    init_sym: NtSymbol,
+    position: usize,
+    // The states at `position`, which haven't been expanded yet
+    states: Vec<State>,
+    next_states: Vec<State>,
+    new_states: Vec<State>,
+    seen: StateSet<State>,
+    groups: Vec<Group>,
+    free: Vec<u32>,
+    // The group for position 0, which the start symbol has to complete from
+    start: u32,
+    // The group for `position`
+    current: u32,
+    // Collect the groups once this many are in use
+    collect_at: usize,
+    error: Option<ParseError>,
+}
+impl<'c, Symbol: CfgSymbol + Ord> Recognizer<'c, Symbol> {
+    pub fn new(cfg: &'c Cfg<Symbol>, init_sym: u32) -> Self {
+        assert!(cfg.rules.len() <= u32::MAX as usize, "the grammar has too many rules");
+        assert!(cfg.rules.iter().all(|rule| rule.parts.len() < u16::MAX as usize), "a rule is too long");
+        let mut this = Self {
+            cfg,
+            init_sym,
+            position: 0,
+            states: vec![],
+            next_states: vec![],
+            new_states: vec![],
+            seen: StateSet::default(),
+            groups: vec![],
+            free: vec![],
+            start: 0,
+            current: 0,
+            collect_at: 64,
+            error: None,
+        };
+        this.open_group();
+        this.start = this.current;
+        this.states.extend(
+            cfg.query_nt(init_sym)
+                .unwrap()
+                .filter(|&i| !cfg.rules[i].parts.is_empty())
+                .map(|i| State::new(i, 0, this.start as usize)),
+        );
+        this
+    }
+    /// How many symbols were pushed so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+    /// How many positions still have their waiting items kept around.
+    pub fn live_positions(&self) -> usize {
+        self.groups.len() - self.free.len()
+    }
+    /// Go on with the next input symbol. Once this returns an error, the input can't be
+    /// recognized anymore, and every later call returns the same error.
+    pub fn push(&mut self, symbol: &Symbol::Terminal) -> Result<(), ParseError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        let cfg = self.cfg;
+        let cursor = self.current;
+        // Only the states that can go on with this symbol are worth expanding
+        self.states.retain(|state| state.can_continue_with(cfg, symbol));
+        if self.states.is_empty() {
+            return Err(*self.error.insert(ParseError { position: self.position }));
+        }
+        let mut step = Step {
+            cfg,
+            input_symbol: Some(symbol),
+            cursor,
+            groups: &mut self.groups,
+            next_states: std::mem::take(&mut self.next_states),
+            completed: false,
+            init_sym: self.init_sym,
+            start: self.start,
+        };
+        let mut transfer = Transfer {
+            states: &self.states,
+            new_states: std::mem::take(&mut self.new_states),
+        };
+        step.expand_states(&mut transfer);
+        self.new_states = transfer.new_states;
+        grow_set(&mut self.new_states, &mut self.seen, |states| {
+            step.expand_states(states);
+        });
+        self.new_states.clear();
+        sorted_set(&mut step.next_states);
+        self.next_states = step.next_states;
+        self.close_group(cursor);
+
+        std::mem::swap(&mut self.states, &mut self.next_states);
+        self.next_states.clear();
+        if self.states.is_empty() {
+            return Err(*self.error.insert(ParseError { position: self.position }));
+        }
+        self.position += 1;
+        self.open_group();
+        if self.live_positions() >= self.collect_at {
+            self.collect();
+            self.collect_at = self.collect_at.max(2 * self.live_positions());
+        }
+        Ok(())
+    }
+    /// Whether the input pushed so far is an `init_sym`.
+    pub fn finish(mut self) -> Result<(), ParseError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        let cfg = self.cfg;
+        if self.position == 0 && cfg.nt_is_nullable(self.init_sym) {
+            return Ok(());
+        }
+        let cursor = self.current;
+        let mut step = Step {
+            cfg,
+            input_symbol: None,
+            cursor,
+            groups: &mut self.groups,
+            next_states: vec![],
+            completed: false,
+            init_sym: self.init_sym,
+            start: self.start,
+        };
+        // Nothing is left to scan, so the nonterminals that can match nothing are skipped
+        grow_set(&mut self.states, &mut self.seen, |mut states| {
+            for i in 0..states.read().len() {
+                let state = states.read()[i];
+                let Some(sym) = state.remaining(cfg).first() else {
+                    step.complete(state, states.write());
+                    continue;
+                };
+                if let Either::Err(nt) = sym.as_part()
+                    && cfg.nt_is_nullable(nt)
+                {
+                    states.write().push(state.advance());
+                }
+            }
+        });
+        if !step.completed {
+            return Err(ParseError { position: self.position });
+        }
+        Ok(())
+    }
+
+    // Open the group for `position`, once the previous one is done
+    fn open_group(&mut self) {
+        let slot = match self.free.pop() {
+            Some(slot) => slot,
+            None => {
+                assert!(self.groups.len() < u32::MAX as usize, "the input nests too deeply");
+                self.groups.push(Group::default());
+                self.groups.len() as u32 - 1
+            }
+        };
+        let group = &mut self.groups[slot as usize];
+        group.position = self.position;
+        group.waiting.clear();
+        self.current = slot;
+    }
+    // Sort the items waiting at the position that's done, and forward the deterministic ones
+    // to the top of their reduction paths.
+    fn close_group(&mut self, slot: u32) {
+        let cfg = self.cfg;
+        let mut waiting = std::mem::take(&mut self.groups[slot as usize].waiting);
+        sorted_set(&mut waiting);
+        let position = self.groups[slot as usize].position;
+        for entry in waiting.chunk_by_mut(|a, b| a.0 == b.0) {
+            let [(_, state)] = entry else {
+                continue;
+            };
+            if !state.remaining(cfg).is_empty() {
+                continue;
+            }
+            // Completing the awaited nonterminal completes `state`, and then everything
+            // waiting for that. Within the same position, that's left to the completion.
+            let above = &self.groups[state.origin as usize];
+            if above.position == position {
+                continue;
+            }
+            if let [(_, top)] = query(above, state.sym(cfg))
+                && top.remaining(cfg).is_empty()
+            {
+                *state = *top;
+            }
+        }
+        self.groups[slot as usize].waiting = waiting;
+    }
+    // Free the groups that can't be reached from the current states anymore
+    fn collect(&mut self) {
+        for group in &mut self.groups {
+            group.marked = false;
+        }
+        // The group that was just opened has nothing waiting yet, but it's in use
+        let mut todo = vec![self.start, self.current];
+        todo.extend(self.states.iter().map(|state| state.origin));
+        while let Some(slot) = todo.pop() {
+            let group = &mut self.groups[slot as usize];
+            if std::mem::replace(&mut group.marked, true) {
+                continue;
+            }
+            todo.extend(group.waiting.iter().map(|(_, state)| state.origin));
+        }
+        self.free.clear();
+        for (slot, group) in self.groups.iter_mut().enumerate() {
+            if !group.marked {
+                group.waiting.clear();
+                self.free.push(slot as u32);
+            }
+        }
+    }
+}
+
+// The items waiting on `sym` in a group that's done
+fn query(group: &Group, sym: NtSymbol) -> &[(NtSymbol, State)] {
+    let start = group.waiting.partition_point(|entry| entry.0 < sym);
+    let end = start + group.waiting[start..].partition_point(|entry| entry.0 <= sym);
+    &group.waiting[start..end]
+}
+
+struct Step<'c, 'g, 's, Symbol: CfgSymbol> {
+    cfg: &'c Cfg<Symbol>,
+    // `None` past the end of the input
+    input_symbol: Option<&'s Symbol::Terminal>,
+    cursor: u32,
+    groups: &'g mut Vec<Group>,
+    next_states: Vec<State>,
+    // Whether the start symbol was recognized from the start up to here
+    completed: bool,
+    init_sym: NtSymbol,
+    start: u32,
+}
+impl<Symbol: CfgSymbol + Ord> Step<'_, '_, '_, Symbol> {
+    fn expand_states(&mut self, mut transfer: impl BufferPair<State>) {
+        for i in 0..transfer.read().len() {
+            let state = transfer.read()[i];
+            self.expand_state(state, transfer.write());
+        }
+    }
+    fn complete(&mut self, state: State, new: &mut Vec<State>) {
+        let sym = state.sym(self.cfg);
+        if sym == self.init_sym && state.origin == self.start {
+            self.completed = true;
+        }
+        new.extend(query(&self.groups[state.origin as usize], sym).iter().map(|&(_, state)| state));
+    }
+    // Like `EarleyStep::expand_state`, without recording what was completed
+    fn expand_state(&mut self, state: State, new: &mut Vec<State>) {
+        let cfg = self.cfg;
+        let Some(sym) = state.remaining(cfg).first() else {
+            self.complete(state, new);
+            return;
+        };
+        match sym.as_part() {
+            Either::Ok(sym) => {
+                if self.input_symbol == Some(sym.borrow()) {
+                    self.next_states.push(state.advance());
+                }
+            }
+            Either::Err(nt) => {
+                self.groups[self.cursor as usize].waiting.push((nt, state.advance()));
+                // Nullable nonterminals are skipped straight away, see `EarleyStep::expand_state`.
+                // Completing the rest of a rule that started here would look at the items waiting
+                // here, which aren't all known yet.
+                if cfg.nt_is_nullable(nt) && (state.remaining(cfg).len() != 1 || state.origin != self.cursor) {
+                    self.expand_state(state.advance(), new);
+                }
+                let Some(input_symbol) = self.input_symbol else {
+                    return;
+                };
+                for rule_idx in cfg.query_nt(nt).unwrap() {
+                    if cfg.rules[rule_idx].parts.is_empty()
+                        || (!cfg.rule_nullable[rule_idx] && !cfg.rule_can_start_with(rule_idx, input_symbol))
+                    {
+                        continue;
+                    }
+                    new.push(State::new(rule_idx, 0, self.cursor as usize));
+                }
+            }
+        }
+    }
+}
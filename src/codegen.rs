@@ -0,0 +1,230 @@
+//! Generate typed Rust definitions for the trees of a grammar.
+//!
+//! Each nonterminal becomes an enum with a variant for each of its rules, and the
+//! variants hold the (boxed) values of the rule's nonterminal children, in order.
+//! The generated code also converts the untyped trees from `trace_to_ast` into them, failing
+//! with a [`ConvertError`] when a node wasn't made with one of the expected rules:
+//!
+//! ```ignore
+//! let ast = cfg_toy::trace_to_ast(&cfg, src, &completions, &256);
+//! let expr = Expr::try_from(&ast[..])?;
+//! ```
+//!
+//! The conversions refer to rules by their index, so they're only valid for trees of
+//! the exact `Cfg` the code was generated from. This is meant to be run from a `build.rs`:
+//!
+//! ```ignore
+//! let (cfg, names) = cfg_toy::cfg! { ... };
+//! let out = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("ast.rs");
+//! std::fs::write(out, cfg_toy::codegen::generate_ast_types(&cfg, &names)).unwrap();
+//! ```
+use std::fmt::Write;
+
+use crate::CfgSymbol;
+use crate::grammar::Cfg;
+
+/// Emit the type definitions for every nonterminal.
+///
+/// `names` are the names of the nonterminals from 256 upwards, as returned by `cfg!`.
+/// A nonterminal without any rules can't be in a tree, so its type is an empty enum.
+pub fn generate_ast_types<Symbol: CfgSymbol>(cfg: &Cfg<Symbol>, names: &[&str]) -> String {
+    let mut out = String::new();
+    writeln!(out, "// Generated by cfg_toy::codegen, do not edit.").unwrap();
+    for (i, name) in names.iter().enumerate() {
+        let nt = 256 + i as u32;
+        let rules = cfg.query_nt(nt).unwrap_or(0..0);
+        let type_name = type_name(name);
+        let variants = rules
+            .clone()
+            .map(|idx| variant_name(names, &cfg.rules[idx].parts))
+            .collect::<Vec<_>>();
+        let variants = deduplicate(variants);
+
+        writeln!(out).unwrap();
+        if rules.is_empty() {
+            writeln!(out, "/// `{name}` has no rules, so there's no tree for it.").unwrap();
+        }
+        writeln!(out, "#[derive(Debug, Clone, PartialEq, Eq)]").unwrap();
+        // The variants are named after their rules, so they often repeat the nonterminal's name
+        writeln!(out, "#[allow(clippy::enum_variant_names)]").unwrap();
+        writeln!(out, "pub enum {type_name} {{").unwrap();
+        for (idx, variant) in rules.clone().zip(&variants) {
+            let fields = child_types(names, &cfg.rules[idx].parts);
+            writeln!(out, "    /// `{}`", describe_rule(cfg, names, idx)).unwrap();
+            if fields.is_empty() {
+                writeln!(out, "    {variant},").unwrap();
+            } else {
+                writeln!(out, "    {variant}({}),", fields.join(", ")).unwrap();
+            }
+        }
+        writeln!(out, "}}").unwrap();
+
+        writeln!(out).unwrap();
+        // The paths are absolute, since a nonterminal like `result` shadows `Result`
+        writeln!(out, "impl<S> ::core::convert::TryFrom<&[::cfg_toy::Node<'_, S>]> for {type_name} {{").unwrap();
+        writeln!(out, "    type Error = ::cfg_toy::codegen::ConvertError;").unwrap();
+        writeln!(out, "    fn try_from(ast: &[::cfg_toy::Node<'_, S>]) -> ::core::result::Result<Self, Self::Error> {{").unwrap();
+        let error = format!("::cfg_toy::codegen::ConvertError {{ rule: {{}}, nonterminal: {name:?} }}");
+        if rules.is_empty() {
+            writeln!(out, "        Err({})", error.replace("{}", "ast.first().map(|node| node.rule)")).unwrap();
+        } else {
+            writeln!(out, "        let Some(node) = ast.first() else {{").unwrap();
+            writeln!(out, "            return Err({});", error.replace("{}", "None")).unwrap();
+            writeln!(out, "        }};").unwrap();
+            writeln!(out, "        #[allow(unused_mut, unused_variables)]").unwrap();
+            writeln!(out, "        let mut children = ::cfg_toy::visit::children(ast);").unwrap();
+            // The number of children is checked too, so the children are all there
+            writeln!(out, "        match (node.rule, node.children) {{").unwrap();
+            for (idx, variant) in rules.zip(&variants) {
+                let fields = child_types(names, &cfg.rules[idx].parts);
+                if fields.is_empty() {
+                    writeln!(out, "            ({idx}, 0) => Ok(Self::{variant}),").unwrap();
+                } else {
+                    let args = vec!["::std::boxed::Box::new(::core::convert::TryFrom::try_from(children.next().unwrap())?)"; fields.len()];
+                    let arity = fields.len();
+                    writeln!(out, "            ({idx}, {arity}) => Ok(Self::{variant}({})),", args.join(", ")).unwrap();
+                }
+            }
+            writeln!(out, "            (rule, _) => Err({}),", error.replace("{}", "Some(rule)")).unwrap();
+            writeln!(out, "        }}").unwrap();
+        }
+        writeln!(out, "    }}").unwrap();
+        writeln!(out, "}}").unwrap();
+    }
+    out
+}
+
+/// A tree that doesn't fit the types from [`generate_ast_types`], because one of its
+/// nodes wasn't made with a rule of the nonterminal it's converted to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConvertError {
+    /// The rule of the node, or `None` if there was no node at all
+    pub rule: Option<usize>,
+    pub nonterminal: &'static str,
+}
+impl std::fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.rule {
+            Some(rule) => write!(f, "the node for rule {rule} isn't a `{}` in this grammar", self.nonterminal),
+            None => write!(f, "there's no node to convert to a `{}`", self.nonterminal),
+        }
+    }
+}
+impl std::error::Error for ConvertError {}
+
+/// The name of `nt` in `names`, which starts at nonterminal 256 like the names `cfg!` returns.
+pub(crate) fn nt_name<'n>(names: &[&'n str], nt: u32) -> &'n str {
+    names[(nt - 256) as usize]
+}
+
+fn child_types<Symbol: CfgSymbol>(names: &[&str], parts: &[Symbol]) -> Vec<String> {
+    parts
+        .iter()
+        .filter_map(|part| part.as_part().err())
+        .map(|nt| format!("::std::boxed::Box<{}>", type_name(nt_name(names, nt))))
+        .collect()
+}
+
+/// The source as text, cut off after `max` terminals.
+pub(crate) fn excerpt<Symbol: CfgSymbol>(src: &[Symbol::Terminal], max: usize) -> String {
+    let mut text = String::new();
+    for terminal in src.iter().take(max) {
+        match Symbol::terminal_char(terminal) {
+            Some(c) => text.push(c),
+            None => write!(text, "{terminal:?}").unwrap(),
+        }
+    }
+    if src.len() > max {
+        text.push('…');
+    }
+    text
+}
+
+pub(crate) fn describe_rule<Symbol: CfgSymbol>(cfg: &Cfg<Symbol>, names: &[&str], idx: usize) -> String {
+    use std::borrow::Borrow;
+    let rule = &cfg.rules[idx];
+    let mut desc = format!("{} ::=", nt_name(names, rule.for_nt));
+    if rule.parts.is_empty() {
+        desc.push_str(" ε");
+    }
+    let mut literal = String::new();
+    for part in &rule.parts {
+        match part.as_part() {
+            Ok(terminal) => match Symbol::terminal_char(terminal.borrow()) {
+                Some(c) => literal.push(c),
+                None => write!(desc, " {:?}", terminal.borrow()).unwrap(),
+            },
+            Err(nt) => {
+                if !literal.is_empty() {
+                    write!(desc, " {:?}", std::mem::take(&mut literal)).unwrap();
+                }
+                write!(desc, " {}", nt_name(names, nt)).unwrap();
+            }
+        }
+    }
+    if !literal.is_empty() {
+        write!(desc, " {literal:?}").unwrap();
+    }
+    // Don't let the rule close the doc comment's code span
+    desc.replace('`', "'")
+}
+
+// Variants are named after the nonterminals and the words in the rule:
+// `expr ::= and_expr "or" expr` becomes `AndExprOrExpr`.
+fn variant_name<Symbol: CfgSymbol>(names: &[&str], parts: &[Symbol]) -> String {
+    use std::borrow::Borrow;
+    let mut name = String::new();
+    let mut word = String::new();
+    for part in parts {
+        match part.as_part() {
+            Ok(terminal) => match Symbol::terminal_char(terminal.borrow()) {
+                Some(c) if c.is_ascii_alphanumeric() => word.push(c),
+                _ => name.push_str(&type_name(&std::mem::take(&mut word))),
+            },
+            Err(nt) => {
+                name.push_str(&type_name(&std::mem::take(&mut word)));
+                name.push_str(&type_name(nt_name(names, nt)));
+            }
+        }
+    }
+    name.push_str(&type_name(&word));
+    if name.is_empty() {
+        name.push_str(if parts.is_empty() { "Empty" } else { "Alt" });
+    }
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, 'N');
+    }
+    name
+}
+
+// Rules that would get the same name are numbered instead
+fn deduplicate(variants: Vec<String>) -> Vec<String> {
+    let mut seen = std::collections::HashMap::<&str, usize>::new();
+    variants
+        .iter()
+        .map(|variant| {
+            if variants.iter().filter(|v| *v == variant).count() == 1 {
+                return variant.clone();
+            }
+            let n = seen.entry(variant).or_default();
+            *n += 1;
+            format!("{variant}{n}")
+        })
+        .collect()
+}
+
+/// `and_expr` -> `AndExpr`
+fn type_name(name: &str) -> String {
+    let mut out = String::new();
+    for word in name.split(|c: char| !c.is_ascii_alphanumeric()) {
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            out.push(first.to_ascii_uppercase());
+            out.extend(chars);
+        }
+    }
+    if out == "Self" {
+        out.push('_');
+    }
+    out
+}
@@ -0,0 +1,528 @@
+//! Tables for parsing with the same grammar over and over.
+//!
+//! [`parse_earley`](crate::parse_earley) works out what to predict from the rules every time
+//! it sees a nonterminal. A [`CompiledGrammar`] does that once: predicting a nonterminal
+//! takes everything it transitively predicts at once, with the nullable parts at the start
+//! of the predicted rules already skipped, and only the scans for the next input symbol
+//! turn into states.
+//!
+//! The scanner doesn't look at the terminals themselves either. Two terminals are
+//! equivalent when swapping one for the other anywhere in a rule gives another rule of the
+//! grammar, like the rules for the digits usually do. Each input symbol is looked up once
+//! as its class and its number within the class, and the scan tables only hold the rules
+//! for the first terminal of each class, which are switched over to the rule for the
+//! actual terminal as it's scanned. However many terminals there are, the tables grow with
+//! the number of classes, and the input is only compared to the grammar's terminals once.
+//!
+//! ```
+//! let (cfg, _) = cfg_toy::cfg! {
+//!     list item;
+//!     list ::= item "," list .
+//!     list ::= item .
+//!     item ::= "a" item .
+//!     item ::= .
+//! };
+//! let compiled = cfg_toy::compiled::CompiledGrammar::new(&cfg);
+//! for src in ["aa,a", "a,,aaa", "a,"] {
+//!     let completions = compiled.parse(src.as_bytes(), 256, ());
+//!     let ast = cfg_toy::trace_to_ast(&cfg, src.as_bytes(), &completions, &256);
+//!     assert_eq!(ast[0].end, src.len());
+//! }
+//! ```
+use std::borrow::Borrow;
+
+use crate::buffer_pair::{BufferPair, Transfer};
+use crate::completions::{Completions, CompletionsTransaction};
+use crate::grammar::Cfg;
+use crate::recognizer::{NtSymbol, ParseError, State, Trace, TraceAt};
+use crate::set_buffers::{StateSet, grow_set, sorted_set};
+use crate::{CfgSymbol, Either};
+
+/// The prediction and scanning tables for a [`Cfg`], see the [module docs](self).
+///
+/// Everything indexed by nonterminal is flattened into one buffer, with the range for
+/// `nt` at `offsets[nt]..offsets[nt + 1]`.
+#[derive(Debug)]
+pub struct CompiledGrammar<'c, Symbol> {
+    pub cfg: &'c Cfg<Symbol>,
+    // The nonterminals predicted along with each nonterminal, including itself
+    predicts: Vec<NtSymbol>,
+    predicts_offsets: Vec<usize>,
+    // The items a nonterminal's rules start out as that wait on a nonterminal, as
+    // `(awaited, state)` with `state` advanced past it and an origin of 0
+    waits: Vec<(NtSymbol, State)>,
+    waits_offsets: Vec<usize>,
+    // The items a nonterminal's rules start out as that scan the first terminal of a class,
+    // as `(class, state)` sorted by the class
+    scans: Vec<(u32, State)>,
+    scans_offsets: Vec<usize>,
+    // The grammar's terminals with their `(class, member)`, sorted by the terminal
+    alphabet: Vec<(&'c Symbol, Terminal)>,
+    classes: usize,
+    // The items for each rule start at `item_start[rule]`, one for each dot
+    item_start: Vec<usize>,
+    // The terminal after the dot of each item, if there's one there
+    item_terminal: Vec<Option<Terminal>>,
+    // For the items before the first terminal of a class, the rules that have each member
+    // of the class there instead, starting at `variants_start[item]`
+    variants: Vec<u32>,
+    variants_start: Vec<u32>,
+    // How many of the parts after the dot are nullable nonterminals. When that reaches the
+    // end of the rule, the rest of it is nullable and the item can complete right away.
+    nullable_run: Vec<u16>,
+}
+
+impl<'c, Symbol: CfgSymbol + Ord> CompiledGrammar<'c, Symbol>
+where
+    Symbol::Terminal: Ord,
+{
+    pub fn new(cfg: &'c Cfg<Symbol>) -> Self {
+        assert!(cfg.rules.len() <= u32::MAX as usize, "the grammar has too many rules");
+        assert!(cfg.rules.iter().all(|rule| rule.parts.len() < u16::MAX as usize), "a rule is too long");
+        let nullable = |part: &Symbol| match part.as_part() {
+            Either::Ok(_) => false,
+            Either::Err(nt) => cfg.nt_nullable.get(nt as usize).copied().unwrap_or(false),
+        };
+        let mut item_start = Vec::with_capacity(cfg.rules.len());
+        let mut nullable_run = vec![];
+        for rule in &cfg.rules {
+            item_start.push(nullable_run.len());
+            let start = nullable_run.len();
+            nullable_run.resize(start + rule.parts.len() + 1, 0);
+            for (dot, part) in rule.parts.iter().enumerate().rev() {
+                if nullable(part) {
+                    nullable_run[start + dot] = nullable_run[start + dot + 1] + 1;
+                }
+            }
+        }
+
+        let (alphabet, classes) = alphabet(cfg);
+        let item_terminal = cfg
+            .rules
+            .iter()
+            .flat_map(|rule| {
+                let terminals = rule.parts.iter().map(|part| match part.as_part() {
+                    Either::Ok(terminal) => lookup(&alphabet, terminal.borrow()),
+                    Either::Err(_) => None,
+                });
+                terminals.chain([None])
+            })
+            .collect::<Vec<_>>();
+        // The rules by their parts, to look up the ones with a terminal swapped out
+        let mut by_parts = (0..cfg.rules.len()).collect::<Vec<_>>();
+        by_parts.sort_by(|&a, &b| (cfg.rules[a].for_nt, &cfg.rules[a].parts).cmp(&(cfg.rules[b].for_nt, &cfg.rules[b].parts)));
+        let mut variants = vec![];
+        let mut variants_start = vec![u32::MAX; item_terminal.len()];
+        let mut members = vec![];
+        for (rule_idx, rule) in cfg.rules.iter().enumerate() {
+            for dot in 0..rule.parts.len() {
+                let Some(Terminal { class, member: 0 }) = item_terminal[item_start[rule_idx] + dot] else {
+                    continue;
+                };
+                variants_start[item_start[rule_idx] + dot] = variants.len() as u32;
+                members.clear();
+                members.extend(alphabet.iter().filter(|(_, terminal)| terminal.class == class));
+                members.sort_by_key(|(_, terminal)| terminal.member);
+                variants.push(rule_idx as u32);
+                for &(member, _) in &members[1..] {
+                    let swapped = |other: &usize| {
+                        let other = &cfg.rules[*other];
+                        let parts = rule.parts[..dot].iter().chain([member]).chain(&rule.parts[dot + 1..]);
+                        other.for_nt.cmp(&rule.for_nt).then_with(|| other.parts.iter().cmp(parts))
+                    };
+                    let found = by_parts.binary_search_by(swapped).expect("equivalent terminals swap to another rule");
+                    variants.push(by_parts[found] as u32);
+                }
+            }
+        }
+
+        let nt_count = cfg.nt_index.len();
+        let mut waits = vec![];
+        let mut waits_offsets = vec![0];
+        let mut scans = vec![];
+        let mut scans_offsets = vec![0];
+        for nt in 0..nt_count {
+            for rule in cfg.query_nt(nt as NtSymbol).unwrap() {
+                let parts = &cfg.rules[rule].parts;
+                let run = nullable_run[item_start[rule]] as usize;
+                // A rule that's entirely nullable is completed by the prediction instead
+                for (dot, part) in parts.iter().enumerate().take(run + 1) {
+                    match (part.as_part(), item_terminal[item_start[rule] + dot]) {
+                        // The other members of the class are switched to when they're scanned
+                        (Either::Ok(_), Some(Terminal { class, member: 0 })) => {
+                            scans.push((class, State::new(rule, dot + 1, 0)))
+                        }
+                        (Either::Ok(_), _) => (),
+                        (Either::Err(awaited), _) => waits.push((awaited, State::new(rule, dot + 1, 0))),
+                    }
+                }
+            }
+            scans[*scans_offsets.last().unwrap()..].sort_by_key(|&(class, _)| class);
+            waits_offsets.push(waits.len());
+            scans_offsets.push(scans.len());
+        }
+
+        let mut predicts = vec![];
+        let mut predicts_offsets = vec![0];
+        let mut seen = vec![usize::MAX; nt_count];
+        for nt in 0..nt_count {
+            seen[nt] = nt;
+            let start = predicts.len();
+            predicts.push(nt as NtSymbol);
+            let mut i = start;
+            while i < predicts.len() {
+                let predicted = predicts[i] as usize;
+                i += 1;
+                for &(awaited, _) in &waits[waits_offsets[predicted]..waits_offsets[predicted + 1]] {
+                    let awaited = awaited as usize;
+                    if awaited < nt_count && seen[awaited] != nt {
+                        seen[awaited] = nt;
+                        predicts.push(awaited as NtSymbol);
+                    }
+                }
+            }
+            predicts_offsets.push(predicts.len());
+        }
+
+        Self {
+            cfg,
+            predicts,
+            predicts_offsets,
+            waits,
+            waits_offsets,
+            scans,
+            scans_offsets,
+            alphabet,
+            classes,
+            item_start,
+            item_terminal,
+            variants,
+            variants_start,
+            nullable_run,
+        }
+    }
+    /// How many classes of equivalent terminals the grammar's terminals fall into,
+    /// see the [module docs](self).
+    pub fn classes(&self) -> usize {
+        self.classes
+    }
+    /// The class of `terminal` and its number within the class, or `None` if it's not
+    /// in the grammar.
+    pub fn classify(&self, terminal: &Symbol::Terminal) -> Option<(u32, u32)> {
+        lookup(&self.alphabet, terminal).map(|terminal| (terminal.class, terminal.member))
+    }
+    /// Like [`try_parse`](Self::try_parse), but panics if the input doesn't match the grammar.
+    pub fn parse(
+        &self,
+        src: &'c [Symbol::Terminal],
+        init_sym: NtSymbol,
+        trace: impl Trace<'c, Symbol>,
+    ) -> Completions<'c, Symbol> {
+        self.try_parse(src, init_sym, trace).unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Recognize `src` as `init_sym`, like [`try_parse_earley`](crate::try_parse_earley).
+    ///
+    /// The chart is the same one `try_parse_earley` builds.
+    pub fn try_parse(
+        &self,
+        src: &'c [Symbol::Terminal],
+        init_sym: NtSymbol,
+        trace: impl Trace<'c, Symbol>,
+    ) -> Result<Completions<'c, Symbol>, ParseError> {
+        let mut session = CompiledSession::new(self);
+        session.try_parse(src, init_sym, trace)?;
+        Ok(session.into_completions())
+    }
+}
+
+/// Everything [`CompiledGrammar::try_parse`] allocates, kept to parse any number of inputs,
+/// like a [`ParserSession`](crate::recognizer::ParserSession) for a compiled grammar.
+///
+/// ```
+/// let (cfg, _) = cfg_toy::cfg! {
+///     line word;
+///     line ::= word " " line .
+///     line ::= word .
+///     word ::= "a" word .
+///     word ::= "a" .
+/// };
+/// let compiled = cfg_toy::compiled::CompiledGrammar::new(&cfg);
+/// let mut session = cfg_toy::compiled::CompiledSession::new(&compiled);
+/// for src in ["a aa", "aaa", "a a a"] {
+///     let completions = session.parse(src.as_bytes(), 256, ());
+///     let ast = cfg_toy::trace_to_ast(&cfg, src.as_bytes(), completions, &256);
+///     assert_eq!(ast[0].end, src.len());
+/// }
+/// assert!(session.try_parse(b"a  a", 256, ()).is_err());
+/// ```
+#[derive(Debug)]
+pub struct CompiledSession<'g, 'c, Symbol> {
+    grammar: &'g CompiledGrammar<'c, Symbol>,
+    completions: Completions<'c, Symbol>,
+    // The position + 1 that each nonterminal was last predicted at
+    predicted: Vec<usize>,
+    // The states at the cursor, and the ones scanned into the next position
+    states: Vec<State>,
+    next_states: Vec<State>,
+    // The states generated at the cursor, for `grow_set`
+    new_states: Vec<State>,
+    seen: StateSet<State>,
+    // For following the waiting items up to the start symbol at the end
+    accept_todo: Vec<(NtSymbol, usize)>,
+    accept_seen: StateSet<(NtSymbol, usize)>,
+}
+impl<'g, 'c, Symbol: CfgSymbol + Ord> CompiledSession<'g, 'c, Symbol>
+where
+    Symbol::Terminal: Ord,
+{
+    pub fn new(grammar: &'g CompiledGrammar<'c, Symbol>) -> Self {
+        Self {
+            grammar,
+            completions: Completions::new(grammar.cfg, 0),
+            predicted: vec![],
+            states: vec![],
+            next_states: vec![],
+            new_states: vec![],
+            seen: StateSet::default(),
+            accept_todo: vec![],
+            accept_seen: StateSet::default(),
+        }
+    }
+    /// Like [`try_parse`](Self::try_parse), but panics if the input doesn't match the grammar.
+    pub fn parse(
+        &mut self,
+        src: &[Symbol::Terminal],
+        init_sym: NtSymbol,
+        trace: impl Trace<'c, Symbol>,
+    ) -> &Completions<'c, Symbol> {
+        self.try_parse(src, init_sym, trace).unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Recognize `src` as `init_sym`, like [`CompiledGrammar::try_parse`]. The chart is
+    /// kept in the session until the next parse.
+    pub fn try_parse(
+        &mut self,
+        src: &[Symbol::Terminal],
+        init_sym: NtSymbol,
+        trace: impl Trace<'c, Symbol>,
+    ) -> Result<&Completions<'c, Symbol>, ParseError> {
+        self.recognize(src, init_sym, trace)?;
+        Ok(&self.completions)
+    }
+    /// The chart from the last parse, or an empty one if there wasn't any.
+    pub fn into_completions(self) -> Completions<'c, Symbol> {
+        self.completions
+    }
+    fn recognize(
+        &mut self,
+        src: &[Symbol::Terminal],
+        init_sym: NtSymbol,
+        mut trace: impl Trace<'c, Symbol>,
+    ) -> Result<(), ParseError> {
+        let Self {
+            grammar,
+            completions,
+            predicted,
+            states,
+            next_states,
+            new_states,
+            seen,
+            accept_todo,
+            accept_seen,
+        } = self;
+        let grammar = *grammar;
+        assert!(src.len() < u32::MAX as usize, "the input is too long");
+        completions.reset(src.len());
+        predicted.clear();
+        predicted.resize(grammar.cfg.nt_index.len(), 0);
+        states.clear();
+        for cursor in 0..=src.len() {
+            let mut step = CompiledStep {
+                grammar,
+                // A terminal that's not in the grammar is in a class of its own that nothing scans
+                input: src.get(cursor).map(|terminal| match grammar.classify(terminal) {
+                    Some((class, member)) => Terminal { class, member },
+                    None => Terminal { class: u32::MAX, member: 0 },
+                }),
+                completions_tx: completions.add_group(),
+                next_states: std::mem::take(next_states),
+                predicted,
+                trace: trace.at(cursor),
+            };
+            if cursor == 0 {
+                step.predict(init_sym);
+            }
+            let mut transfer = Transfer {
+                states: &*states,
+                new_states: std::mem::take(new_states),
+            };
+            step.expand_states(&mut transfer);
+            *new_states = transfer.new_states;
+            grow_set(new_states, seen, |states| {
+                step.expand_states(states);
+            });
+            new_states.clear();
+            sorted_set(&mut step.next_states);
+
+            // The used up states are kept for double buffering
+            std::mem::swap(states, &mut step.next_states);
+            step.next_states.clear();
+            *next_states = step.next_states;
+            if cursor < src.len() && states.is_empty() {
+                return Err(ParseError { position: cursor });
+            }
+        }
+        if !completions.recognized(init_sym, 0, src.len(), accept_todo, accept_seen) {
+            return Err(ParseError { position: src.len() });
+        }
+        Ok(())
+    }
+}
+
+struct CompiledStep<'g, 'c, 'r, T, Symbol: Ord + CfgSymbol> {
+    grammar: &'g CompiledGrammar<'c, Symbol>,
+    // `None` past the end of the input
+    input: Option<Terminal>,
+    completions_tx: CompletionsTransaction<'c, 'r, Symbol>,
+    next_states: Vec<State>,
+    predicted: &'g mut Vec<usize>,
+    trace: T,
+}
+impl<'c, T: TraceAt<'c, Symbol>, Symbol: CfgSymbol + Ord> CompiledStep<'_, 'c, '_, T, Symbol>
+where
+    Symbol::Terminal: Ord,
+{
+    fn expand_states(&mut self, mut transfer: impl BufferPair<State>) {
+        for i in 0..transfer.read().len() {
+            let state = transfer.read()[i];
+            self.expand_state(state, transfer.write());
+        }
+    }
+    // Expand a state that was scanned or completed into this position. The states
+    // predicted here are handled by `predict` without going through here.
+    fn expand_state(&mut self, state: State, new: &mut Vec<State>) {
+        let cfg = self.grammar.cfg;
+        let item = self.grammar.item_start[state.rule as usize] + state.dot as usize;
+        let run = self.grammar.nullable_run[item];
+        let parts = state.parts(cfg);
+        // The nullable parts after the dot are skipped straight away
+        let first = state.dot;
+        for dot in first..=first + run {
+            let state = State { dot, ..state };
+            let Some(part) = parts.get(dot as usize) else {
+                let (back_ref, sym) = (state.origin as usize, state.sym(cfg));
+                self.trace.completed(back_ref, sym, parts);
+                self.completions_tx.complete(back_ref, sym, state.rule);
+                new.extend(self.completions_tx.query(back_ref, sym));
+                break;
+            };
+            match part.as_part() {
+                Either::Ok(_) => {
+                    if self.input.is_some() && self.input == self.grammar.item_terminal[item + (dot - first) as usize] {
+                        self.next_states.push(state.advance());
+                    }
+                }
+                Either::Err(nt) => {
+                    self.completions_tx.push(nt, state.advance());
+                    self.predict(nt);
+                }
+            }
+        }
+    }
+    fn predict(&mut self, nt: NtSymbol) {
+        let grammar = self.grammar;
+        let cfg = grammar.cfg;
+        let position = self.completions_tx.batch_id();
+        // A nonterminal without any rules predicts nothing
+        if self.predicted.get(nt as usize).is_none_or(|&at| at == position + 1) {
+            // Otherwise it was predicted here already, and so was everything it predicts
+            return;
+        }
+        for &nt in &grammar.predicts[grammar.predicts_offsets[nt as usize]..grammar.predicts_offsets[nt as usize + 1]] {
+            let nt = nt as usize;
+            if self.predicted[nt] == position + 1 {
+                continue;
+            }
+            self.predicted[nt] = position + 1;
+            for &rule in &cfg.nt_to_nullable_rules_index[cfg.query_nullable(nt as NtSymbol).unwrap()] {
+                self.trace.completed(position, nt as NtSymbol, &cfg.rules[rule].parts);
+                self.completions_tx.complete(position, nt as NtSymbol, rule as u32);
+            }
+            for &(awaited, state) in &grammar.waits[grammar.waits_offsets[nt]..grammar.waits_offsets[nt + 1]] {
+                self.completions_tx.push(awaited, State { origin: position as u32, ..state });
+            }
+            let Some(input) = self.input else {
+                continue;
+            };
+            let scans = &grammar.scans[grammar.scans_offsets[nt]..grammar.scans_offsets[nt + 1]];
+            let start = scans.partition_point(|&(class, _)| class < input.class);
+            let end = start + scans[start..].partition_point(|&(class, _)| class <= input.class);
+            self.next_states.extend(scans[start..end].iter().map(|&(_, state)| {
+                // Switch to the rule for the terminal that was actually scanned
+                let item = grammar.item_start[state.rule as usize] + state.dot as usize - 1;
+                let rule = grammar.variants[(grammar.variants_start[item] + input.member) as usize];
+                State { rule, origin: position as u32, ..state }
+            }));
+        }
+    }
+}
+
+/// A terminal as the scanner sees it, see the [module docs](self).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Terminal {
+    class: u32,
+    // Its index in the class, the first one is the one the scan tables are for
+    member: u32,
+}
+
+fn terminal_of<Symbol: CfgSymbol>(part: &Symbol) -> Symbol::TerminalRef<'_> {
+    match part.as_part() {
+        Either::Ok(terminal) => terminal,
+        Either::Err(_) => unreachable!("only terminals are in the alphabet"),
+    }
+}
+
+fn lookup<Symbol: CfgSymbol>(alphabet: &[(&Symbol, Terminal)], terminal: &Symbol::Terminal) -> Option<Terminal>
+where
+    Symbol::Terminal: Ord,
+{
+    let idx = alphabet.partition_point(|&(part, _)| terminal_of(part).borrow() < terminal);
+    alphabet.get(idx).filter(|&&(part, _)| terminal_of(part).borrow() == terminal).map(|&(_, terminal)| terminal)
+}
+
+// Sort the grammar's terminals into classes of equivalent ones: a terminal's occurrences
+// are the rules with a hole where it was, and the terminals with the same ones can be
+// swapped for each other anywhere.
+fn alphabet<Symbol: CfgSymbol + Ord>(cfg: &Cfg<Symbol>) -> (Vec<(&Symbol, Terminal)>, usize)
+where
+    Symbol::Terminal: Ord,
+{
+    let mut occurrences = vec![];
+    for rule in &cfg.rules {
+        for (dot, part) in rule.parts.iter().enumerate() {
+            if part.as_part().is_ok() {
+                occurrences.push((part, (rule.for_nt, dot, &rule.parts[..dot], &rule.parts[dot + 1..])));
+            }
+        }
+    }
+    occurrences.sort_by(|a, b| terminal_of(a.0).borrow().cmp(terminal_of(b.0).borrow()).then_with(|| a.1.cmp(&b.1)));
+    occurrences.dedup_by(|a, b| terminal_of(a.0).borrow() == terminal_of(b.0).borrow() && a.1 == b.1);
+    let mut terminals = occurrences
+        .chunk_by(|a, b| terminal_of(a.0).borrow() == terminal_of(b.0).borrow())
+        .map(|chunk| (chunk[0].0, chunk))
+        .collect::<Vec<_>>();
+    // Stable, so the members of a class stay sorted
+    terminals.sort_by(|a, b| {
+        let holes = |chunk: &[(&Symbol, _)]| chunk.iter().map(|occurrence| occurrence.1).collect::<Vec<_>>();
+        holes(a.1).cmp(&holes(b.1))
+    });
+    let mut alphabet = vec![];
+    let mut classes = 0;
+    for class in terminals.chunk_by(|a, b| a.1.iter().map(|o| o.1).eq(b.1.iter().map(|o| o.1))) {
+        for (member, &(part, _)) in class.iter().enumerate() {
+            alphabet.push((part, Terminal { class: classes, member: member as u32 }));
+        }
+        classes += 1;
+    }
+    alphabet.sort_by(|a, b| terminal_of(a.0).borrow().cmp(terminal_of(b.0).borrow()));
+    (alphabet, classes as usize)
+}
@@ -1,309 +1,428 @@
-use crate::CfgSymbol;
+use std::borrow::Borrow;
+use std::collections::{HashMap, HashSet};
+
+use crate::grammar::Cfg;
+use crate::set_buffers::StateSet;
+use crate::{CfgSymbol, Either};
 
 use super::recognizer::{NtSymbol, State};
 
-// pub(crate) type Completion<'a, Symbol> = (NtSymbol, State<'a, Symbol>);
-pub(crate) type Completion<'a, Symbol> = (NtSymbol, (usize, NtSymbol, &'a [Symbol], Remaining<'a, Symbol>));
-#[derive(Debug)]
-pub enum Remaining<'a, Symbol> {
-    EmptyAndForwardingTo(usize, usize),
-    More(&'a [Symbol]),
-}
-impl<Symbol> Clone for Remaining<'_, Symbol> {
-    fn clone(&self) -> Self {
-        match self {
-            Remaining::EmptyAndForwardingTo(a, b) => Remaining::EmptyAndForwardingTo(*a, *b),
-            Remaining::More(syms) => Remaining::More(syms),
-        }
-    }
+/// An item waiting on the nonterminal `awaited`, which continues as `state` once
+/// `awaited` has been recognized.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Completion {
+    pub awaited: NtSymbol,
+    // Already advanced past `awaited`
+    pub state: State,
+    // The index of the forwarding record for the deterministic reduction path
+    // this item is on, see `Completions::query`
+    pub forwarded: Option<u32>,
 }
-/// Semantically, this is a `BTreeMap<(usize, NtSymbol), State<'a>>`
+/// A recognized item: `(sym, back_ref, rule)` with the index of the rule in `Cfg::rules`,
+/// stored in the group for the position it ends at.
+pub type Completed = (NtSymbol, usize, u32);
+/// Semantically, this is a `BTreeMap<(usize, NtSymbol), State>`
 /// It's implemented via a flat buffer containing all the entries in correct order,
 /// and the completion index for locating each value of `usize`. This
 /// provides range queries for `(i, sym)` efficiently.
+///
+/// Alongside it we keep the items that were recognized, grouped by the position they
+/// end at in the same way. Together they're the whole Earley chart, so trees can
+/// be extracted from this without any other record of the parse.
 #[derive(Debug)]
 pub struct Completions<'a, Symbol> {
-    // Invariant: all of the forwarding records have nonempty remaining symbols.
-    // These are used to build cache entries
-    pub forwarding_records: Vec<State<'a, Symbol>>,
-    pub completions: Vec<Completion<'a, Symbol>>,
+    pub cfg: &'a Cfg<Symbol>,
+    // The tops of deterministic reduction paths (see `query`), which have no remaining
+    // symbols. The waiting items on a path point to theirs with `Completion::forwarded`.
+    pub forwarding_records: Vec<State>,
+    pub completions: Vec<Completion>,
     pub completion_index: Vec<usize>,
+    // Sorted by (sym, back_ref) within each group, and deduplicated.
+    // Items on a deterministic reduction path below its top aren't recorded here,
+    // see `completed_ending_at` and `CompletedIndex`.
+    pub completed: Vec<Completed>,
+    pub completed_index: Vec<usize>,
+    // Scratch space for `leo_item`, kept to save on allocating it for every query
+    leo_path: Vec<usize>,
 }
 impl<'a, Symbol: CfgSymbol> Completions<'a, Symbol> {
-    pub(crate) fn new(len: usize) -> Self {
+    pub(crate) fn new(cfg: &'a Cfg<Symbol>, len: usize) -> Self {
         let completions = vec![];
-        let mut completion_index = Vec::with_capacity(len + 1);
+        let mut completion_index = Vec::with_capacity(len + 2);
         completion_index.push(0);
+        let mut completed_index = Vec::with_capacity(len + 2);
+        completed_index.push(0);
         Self {
+            cfg,
             forwarding_records: vec![],
             completions,
             completion_index,
+            completed: vec![],
+            completed_index,
+            leo_path: vec![],
+        }
+    }
+    /// Empty the chart for parsing an input of length `len`, keeping the allocations.
+    pub(crate) fn reset(&mut self, len: usize) {
+        self.forwarding_records.clear();
+        self.completions.clear();
+        self.completion_index.clear();
+        self.completion_index.reserve(len + 2);
+        self.completion_index.push(0);
+        self.completed.clear();
+        self.completed_index.clear();
+        self.completed_index.reserve(len + 2);
+        self.completed_index.push(0);
+    }
+    /// The items recorded as completed at `end`, sorted by `(sym, back_ref)`.
+    pub fn completed_at(&self, end: usize) -> &[Completed] {
+        &self.completed[self.completed_index[end]..self.completed_index[end + 1]]
+    }
+    /// Every item that was recognized ending at `end`, sorted by `(sym, back_ref)`.
+    ///
+    /// This includes the items that the recognizer skipped over on deterministic reduction
+    /// paths, so it takes time proportional to the number of items, see `CompletedIndex`.
+    /// Whenever `sym` completed from `back_ref` to `end`, every item that was waiting
+    /// on `sym` at `back_ref` with nothing left to match has completed too, so they
+    /// can be recovered by following the waiting items up from the recorded ones.
+    pub fn completed_ending_at(&self, end: usize) -> Vec<Completed> {
+        let mut found = self.completed_at(end).to_vec();
+        let mut seen = found.iter().copied().collect::<HashSet<_>>();
+        let mut expanded = HashSet::new();
+        let mut i = 0;
+        while i < found.len() {
+            let (sym, back_ref, _) = found[i];
+            i += 1;
+            if !expanded.insert((sym, back_ref)) {
+                continue;
+            }
+            for c in &self.completions[self.query_range(back_ref, sym)] {
+                let parent = (c.state.sym(self.cfg), c.state.origin as usize, c.state.rule);
+                if self.finished(c) && seen.insert(parent) {
+                    found.push(parent);
+                }
+            }
         }
+        found.sort_by_key(|c| (c.0, c.1));
+        found
+    }
+    /// Whether `sym` was recognized from `start` to `end`, following the waiting items up
+    /// like [`completed_ending_at`](Self::completed_ending_at) does. `todo` and `seen` are
+    /// scratch space, passed in so their allocations can be reused.
+    pub(crate) fn recognized(
+        &self,
+        sym: NtSymbol,
+        start: usize,
+        end: usize,
+        todo: &mut Vec<(NtSymbol, usize)>,
+        seen: &mut StateSet<(NtSymbol, usize)>,
+    ) -> bool {
+        todo.clear();
+        seen.clear();
+        todo.extend(self.completed_at(end).iter().map(|&(sym, back_ref, _)| (sym, back_ref)));
+        while let Some(item) = todo.pop() {
+            if item == (sym, start) {
+                return true;
+            }
+            if !seen.insert(item) {
+                continue;
+            }
+            let (sym, back_ref) = item;
+            for c in &self.completions[self.query_range(back_ref, sym)] {
+                if self.finished(c) {
+                    todo.push((c.state.sym(self.cfg), c.state.origin as usize));
+                }
+            }
+        }
+        false
     }
     pub(crate) fn query_range(&self, back_ref: usize, sym: NtSymbol) -> std::ops::Range<usize> {
         let start = self.completion_index[back_ref];
         let end = self.completion_index[back_ref + 1];
-        let start_of_comps = start + self.completions[start..end].partition_point(|c| c.0 < sym);
+        let start_of_comps = start + self.completions[start..end].partition_point(|c| c.awaited < sym);
         let end_of_comps =
-            start_of_comps + self.completions[start_of_comps..end].partition_point(|c| c.0 <= sym);
+            start_of_comps + self.completions[start_of_comps..end].partition_point(|c| c.awaited <= sym);
         start_of_comps..end_of_comps
     }
+    // Whether the item completes along with the nonterminal it's waiting on
+    fn finished(&self, c: &Completion) -> bool {
+        c.forwarded.is_some() || c.state.remaining(self.cfg).is_empty()
+    }
+    /// The items waiting on `sym` at `back_ref`, to continue with now that `sym` has completed.
+    ///
+    /// When there's only a single item waiting, and `sym` is the last part of its rule,
+    /// completing `sym` completes that item too: it's a deterministic reduction path, as in
+    /// Leo's optimisation. The path is followed up to the topmost item that completes, which
+    /// is returned instead, and remembered in a forwarding record shared by every item on
+    /// the path. Right recursion then takes constant work per input symbol.
     pub(crate) fn query<'b>(
         &'b mut self,
         back_ref: usize,
         sym: NtSymbol,
-    ) -> impl Iterator<Item = State<'a, Symbol>> + 'b
-    where Symbol: PartialEq{
-        let mut range = self.query_range(back_ref, sym);
-        // let completions = &mut self.completions;
-        // let forwarding_records: &'b [_] = &self.forwarding_records;
-        let mut forwarding_drain = 0..0;
-        core::iter::from_fn(move ||  {
-            loop {
-                // println!("Querying {back_ref} {sym} range={:?} {:?} fwd={:?}", range, &self.completions[range.clone()], forwarding_drain);
-                if let Some(i) = forwarding_drain.next() {
-                    return Some(self.forwarding_records[i].clone());
-                }
-                let i = range.next()?;
-                let (back_ref, sym, rule, rem) = self.completions[i].1.clone();
-                break Some(match rem {
-                    // FIXME?: there is a case where we find a forwarding candidate, but it's wasteful.
-                    // in particular,
-                    // alpha ::= "a".
-                    // alpha ::= "b".
-                    // other_rule ::= alpha.
-                    // when we recognize the alpha, the other_rule will have a completion of the form
-                    // (alpha, (other_rule, idx, []))
-                    // we would normally allocate a bypass to switch this to 
-                    // (alpha, (other_rule, idx, bypass([..states_after_other_rule])))
-                    // however, no other rules on alpha could possibly match at the same position:
-                    // "a" isn't the prefix of any other rule for `alpha`.
-                    // so this bypass is statically unnecessary.
-                    // This also applies for the `ident` rule, because
-                    // we can statically know that all completions that hit an ident will have hit
-                    // an `alpha`, so we should just store the bypass for the alpha.
-                    // 
-                    // I think this requires performing this analysis on the cfg ahead of time,
-                    // this property doesnt seem to be available locally. if another rule was
-                    // `alpha ::= "a" "a".`, then it'd get use out of the bypass.
-                    //
-                    // There's also the question of how *much* use is worth paying for allocating
-                    // the bypass. again, a static analysis is probably helpful here, we can
-                    // count the number of potential reuses via different rules.
-                    // each rule can know "can occur as prefix of sibling rules > N times" as a flag.
-                    Remaining::More([])  => {
-                        fn setup_bypass<'a, Symbol: CfgSymbol + PartialEq>(
-                            completions: &mut Completions<'a, Symbol>,
-                            empty_rem_i: usize,
-                            back_ref: usize,
-                            sym: NtSymbol,
-                        ) -> std::ops::Range<usize> {
-                            let forward_to = completions.query_range(back_ref, sym);
-                            // println!("Found forwarding completions {:?} for {back_ref} {sym}", &completions.completions[forward_to.clone()]);
-                            for j in forward_to.clone() {
-                                let (b_ref, s, _, rem) = completions.completions[j].1.clone();
-                                let Remaining::More([]) = rem else { continue };
-                                setup_bypass(completions, j, b_ref, s);
-                            }
-                            if forward_to.len() == 1 &&
-                                let Remaining::EmptyAndForwardingTo(start, end ) = completions.completions[forward_to.start].1.3
-                            {
-                                // already set up
-                                completions.completions[empty_rem_i].1.3 =
-                                    Remaining::EmptyAndForwardingTo(start, end);
-                                start..end
-                            } else {
-
-                                // let start = completions.forwarding_records.len();
-                                enum Reuse {
-                                    Init,
-                                    Forwarded(usize, usize),
-                                    Reusing(usize, usize),
-                                }
-                                let mut reuse = Reuse::Init;
-                                for j in forward_to {
-                                    let (b_ref, s, rule, rem) = completions.completions[j].1.clone();
-                                    match rem {
-                                        Remaining::More(syms) => {
-                                            let (start, end) = match reuse {
-                                                Reuse::Init => {
-                                                    let start = completions.forwarding_records.len();
-                                                    (start, start)
-                                                }
-                                                Reuse::Reusing(from_start, from_end) => {
-                                                    let start = completions.forwarding_records.len();
-                                                    completions.forwarding_records
-                                                        .extend_from_within(from_start..from_end);
-                                                    let end = completions.forwarding_records.len();
-                                                    (start, end)
-                                                }
-                                                Reuse::Forwarded(start, end) => (start, end)
-                                            };
-                                            completions.forwarding_records.push(State {
-                                                back_ref: b_ref,
-                                                sym: s,
-                                                rule, 
-                                                remaining: syms,
-                                            });
-                                            reuse = Reuse::Forwarded(start, end + 1);
-                                        }
-                                        Remaining::EmptyAndForwardingTo(st, end) => {
-                                            match reuse {
-                                                Reuse::Init => {
-                                                    reuse = Reuse::Reusing(st, end);
-                                                }
-                                                Reuse::Reusing(from_start, from_end) => {
-                                                    if completions.forwarding_records[from_start..from_end]
-                                                        == completions.forwarding_records[st..end]
-                                                    {
-                                                        // already reusing
-                                                        continue;
-                                                    }
-                                                    let start = completions.forwarding_records.len();
-                                                    completions.forwarding_records
-                                                        .extend_from_within(from_start..from_end);
-                                                    completions.forwarding_records
-                                                        .extend_from_within(st..end);
-                                                    let end = completions.forwarding_records.len();
-                                                    reuse = Reuse::Forwarded(start, end);
-                                                }
-                                                Reuse::Forwarded(from_start, from_end) => {
-                                                    completions.forwarding_records
-                                                        .extend_from_within(st..end);
-                                                    let end = completions.forwarding_records.len();
-                                                    reuse = Reuse::Forwarded(from_start, end);
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                                let end = completions.forwarding_records.len();
-                                // println!("{back_ref} {sym} {:?}", &completions.forwarding_records[start..end]);
-                                match reuse {
-                                    Reuse::Init => end..end,
-                                    Reuse::Reusing(start, end) => {
-                                        completions.completions[empty_rem_i].1.3 =
-                                            Remaining::EmptyAndForwardingTo(start, end);
-                                        start..end
-                                    },
-                                    Reuse::Forwarded(start, end) => {
-                                        // FIXME: deduplication of the forwarding records might be useful here?
-                                        //   - if we complete A at a position with two parent rules that finish the same,
-                                        //   we'd avoid the bypass appearing to grow.
-                                        //
-                                        //   this is a likely candidate if the bypass buffer is growing way more
-                                        //   than expected.
-                                        // ```rs
-                                        // completions.forwarding_records[start..end].sort();
-                                        // let new_len = crate::set_buffers::slice_retain_with_context(&mut completions.forwarding_records[start..end], |cx, v| cx.last() != Some(v));
-                                        // let end = start + new_len;
-                                        // completions.forwarding_records.truncate(end);
-                                        // ```
-                                        completions.completions[empty_rem_i].1.3 =
-                                            Remaining::EmptyAndForwardingTo(start, end);
-                                        start..end
-
-                                    }
+    ) -> impl Iterator<Item = State> + 'b {
+        let range = self.query_range(back_ref, sym);
+        let (forwarded, waiting) = match self.leo_item(range.clone()) {
+            Some(record) => (record..record + 1, 0..0),
+            None => (0..0, range),
+        };
+        let this = &*self;
+        let waiting = this.completions[waiting].iter().map(|c| {
+            debug_assert!(c.forwarded.is_none(), "only deterministic items are forwarded");
+            c.state
+        });
+        this.forwarding_records[forwarded].iter().copied().chain(waiting)
+    }
+    // The forwarding record for the top of the deterministic reduction path starting with
+    // the waiting items in `range`, if there is one.
+    fn leo_item(&mut self, range: std::ops::Range<usize>) -> Option<usize> {
+        let is_deterministic = |completions: &Self, range: &std::ops::Range<usize>| {
+            range.len() == 1 && completions.finished(&completions.completions[range.start])
+        };
+        if !is_deterministic(self, &range) {
+            return None;
+        }
+        // Walk up the path until we find the top, or a part of it that's already been followed
+        let mut path = std::mem::take(&mut self.leo_path);
+        path.clear();
+        path.push(range.start);
+        let record = loop {
+            let c = self.completions[*path.last().unwrap()];
+            if let Some(record) = c.forwarded {
+                break record;
+            }
+            let above = self.query_range(c.state.origin as usize, c.state.sym(self.cfg));
+            if is_deterministic(self, &above) {
+                path.push(above.start);
+                continue;
+            }
+            self.forwarding_records.push(c.state);
+            break (self.forwarding_records.len() - 1) as u32;
+        };
+        for &idx in &path {
+            self.completions[idx].forwarded = Some(record);
+        }
+        self.leo_path = path;
+        Some(record as usize)
+    }
+    /// Drop everything from the chart that isn't part of a parse of `src` as `init_sym`,
+    /// so each item that's left is used by some tree. Trees are extracted from the pruned
+    /// chart just like from the whole one.
+    ///
+    /// This goes backwards from the accepting item: every rule of a recognized item is
+    /// matched against its span from the right, and the waiting items and children that
+    /// a match goes through are kept. A waiting item proves that the rest of the rule
+    /// before it matched, so every match found this way is part of a parse.
+    pub fn prune(&mut self, src: &[Symbol::Terminal], init_sym: NtSymbol) {
+        let cfg = self.cfg;
+        let mut keep_waiting = vec![false; self.completions.len()];
+        // The recognized items that are part of a parse, as `(sym, start, end)`
+        let mut useful = HashSet::new();
+        {
+            let mut index = CompletedIndex::new(self);
+            let mut todo = vec![];
+            if index.contains(init_sym, 0, src.len()) {
+                useful.insert((init_sym, 0, src.len()));
+                todo.push((init_sym, 0, src.len()));
+            }
+            // The rule's parts that are left to match, by how many, and where they end
+            let mut matches = vec![];
+            let mut visited = HashSet::new();
+            let mut starts = vec![];
+            while let Some((sym, start, end)) = todo.pop() {
+                for rule_idx in cfg.query_nt(sym).unwrap() {
+                    let parts = &cfg.rules[rule_idx].parts;
+                    visited.clear();
+                    matches.push((parts.len(), end));
+                    while let Some((len, end)) = matches.pop() {
+                        if !visited.insert((len, end)) {
+                            continue;
+                        }
+                        let Some(part) = parts[..len].last() else {
+                            continue;
+                        };
+                        let nt = match part.as_part() {
+                            Either::Ok(part) => {
+                                if start < end && src[end - 1] == *part.borrow() {
+                                    matches.push((len - 1, end - 1));
                                 }
+                                continue;
                             }
+                            Either::Err(nt) => nt,
+                        };
+                        starts.clear();
+                        starts.extend_from_slice(index.waiting(start, rule_idx, parts.len() - len));
+                        for &child_start in &starts {
+                            if child_start > end {
+                                break;
+                            }
+                            if child_start < start || !index.contains(nt, child_start, end) {
+                                continue;
+                            }
+                            // The items waiting on `nt` are sorted by their states
+                            let range = self.query_range(child_start, nt);
+                            let state = State::new(rule_idx, len, start);
+                            let waiting = &self.completions[range.clone()];
+                            let first = range.start + waiting.partition_point(|c| c.state < state);
+                            let last = range.start + waiting.partition_point(|c| c.state <= state);
+                            keep_waiting[first..last].fill(true);
+                            if useful.insert((nt, child_start, end)) {
+                                todo.push((nt, child_start, end));
+                            }
+                            matches.push((len - 1, child_start));
                         }
-                        // println!("Setting up bypass for {back_ref} {sym} {:?}", self.forwarding_records.len());
-                        forwarding_drain = setup_bypass(self, i, back_ref, sym);
-                        if forwarding_drain.is_empty() {
-                            return Some(State {
-                                back_ref,
-                                sym,
-                                rule,
-                                remaining: &[],
-                            })
-                        }
-                        // println!("Using forwarding records {:?}..{:?} via {back_ref}/{sym}", forwarding_drain.start, forwarding_drain.end);
-                        continue;
-                        // if forward_to.len() == 1 {
-
-                        //     (self.completion_index[i].1).2 = todo!();
-                        // }
                     }
-                    Remaining::More(syms) => State {
-                        back_ref,
-                        sym,
-                        rule,
-                        remaining: syms,
-                    },
-                    Remaining::EmptyAndForwardingTo(start, end) => {
-                        forwarding_drain = start..end;
-                        continue;
-                    }
-                })
+                }
             }
-        })
-        // self.completions[range]
-        //     .iter_mut()
-        //     .flat_map(|c| {
-        //         let (back_ref, sym, rem) = c.1.clone();
-        //     })
-    }
-    pub(crate) fn query_without_cache_update(
-        &self,
-        back_ref: usize,
-        sym: NtSymbol,
-    ) -> impl Iterator<Item = State<'a, Symbol>> + '_ {
-        let range = self.query_range(back_ref, sym);
-        self.completions[range]
-            .iter()
-            .flat_map(|c| {
-                let (back_ref, sym, rule, rem) = c.1.clone();
-                match rem {
-                    Remaining::More(syms) => Either::Left(core::iter::once(State {
-                        back_ref,
-                        sym,
-                        rule,
-                        remaining: syms,
-                    })),
-                    Remaining::EmptyAndForwardingTo(start, end) => {
-                        // println!("Using forwarding records {:?}..{:?} via {back_ref}/{sym}", start, end);
-                        Either::Right(self.forwarding_records[start..end].iter().cloned())
-                    }
+        }
+
+        let mut records = vec![None; self.forwarding_records.len()];
+        let mut forwarding_records = vec![];
+        let mut kept = 0;
+        for pos in 0..self.completion_index.len() - 1 {
+            let group = self.completion_index[pos]..self.completion_index[pos + 1];
+            self.completion_index[pos] = kept;
+            for idx in group {
+                if !keep_waiting[idx] {
+                    continue;
                 }
-            })
-    }
-    pub(crate) fn query_original_without_cache_update(
-        &self,
-        back_ref: usize,
-        sym: NtSymbol,
-    ) -> impl Iterator<Item = State<'a, Symbol>> + '_ {
-        let range = self.query_range(back_ref, sym);
-        self.completions[range]
-            .iter()
-            .map(|c| {
-                let (back_ref, sym, rule, rem) = c.1.clone();
-                State {
-                    back_ref,
-                    sym,
-                    rule,
-                    remaining: match rem {
-                        Remaining::More(syms) => syms,
-                        Remaining::EmptyAndForwardingTo(start, end) => &[]
-                    }
+                let mut c = self.completions[idx];
+                c.forwarded = c.forwarded.map(|record| {
+                    *records[record as usize].get_or_insert_with(|| {
+                        forwarding_records.push(self.forwarding_records[record as usize]);
+                        forwarding_records.len() as u32 - 1
+                    })
+                });
+                self.completions[kept] = c;
+                kept += 1;
+            }
+        }
+        *self.completion_index.last_mut().unwrap() = kept;
+        self.completions.truncate(kept);
+        self.forwarding_records = forwarding_records;
+
+        let mut kept = 0;
+        for end in 0..self.completed_index.len() - 1 {
+            let group = self.completed_index[end]..self.completed_index[end + 1];
+            self.completed_index[end] = kept;
+            for idx in group {
+                let (sym, back_ref, _) = self.completed[idx];
+                if useful.contains(&(sym, back_ref, end)) {
+                    self.completed[kept] = self.completed[idx];
+                    kept += 1;
                 }
-            })
+            }
+        }
+        *self.completed_index.last_mut().unwrap() = kept;
+        self.completed.truncate(kept);
     }
 }
 
-enum Either<A, B> {
-    Left(A),
-    Right(B),
+/// Answers whether an item was recognized, including the items that were skipped by
+/// deterministic reduction paths, without listing everything that ends at a position
+/// like [`Completions::completed_ending_at`] does. A right recursive list has an item
+/// for every suffix of the list ending at each position, so listing them all would take
+/// quadratic time.
+pub(crate) struct CompletedIndex<'b, 'a, Symbol> {
+    completions: &'b Completions<'a, Symbol>,
+    // The waiting items with nothing left to match after what they await, by
+    // `(back_ref, sym)`, as `(position, awaited)`
+    finished: HashMap<(usize, NtSymbol), Vec<(usize, NtSymbol)>>,
+    // The positions of the waiting items, by `(back_ref, rule, remaining.len())`, ascending
+    waiting: HashMap<(usize, u32, usize), Vec<usize>>,
+    // Items that were found by following the finished items, by `(sym, start, end)`
+    found: HashMap<(NtSymbol, usize, usize), bool>,
 }
-impl<A, B> Iterator for Either<A, B>
-where
-    A: Iterator,
-    B: Iterator<Item = A::Item>,
-{
-    type Item = A::Item;
-    fn next(&mut self) -> Option<Self::Item> {
-        match self {
-            Either::Left(a) => a.next(),
-            Either::Right(b) => b.next(),
+impl<'b, 'a, Symbol: CfgSymbol> CompletedIndex<'b, 'a, Symbol> {
+    pub(crate) fn new(completions: &'b Completions<'a, Symbol>) -> Self {
+        let mut finished: HashMap<_, Vec<_>> = HashMap::new();
+        let mut waiting: HashMap<_, Vec<_>> = HashMap::new();
+        for (pos, group) in completions.completion_index.windows(2).enumerate() {
+            for c in &completions.completions[group[0]..group[1]] {
+                let back_ref = c.state.origin as usize;
+                let remaining = c.state.remaining(completions.cfg).len();
+                waiting.entry((back_ref, c.state.rule, remaining)).or_default().push(pos);
+                if remaining == 0 {
+                    finished.entry((back_ref, c.state.sym(completions.cfg))).or_default().push((pos, c.awaited));
+                }
+            }
+        }
+        Self {
+            completions,
+            finished,
+            waiting,
+            found: HashMap::new(),
+        }
+    }
+    /// The positions where an item for the rule with index `rule` from `back_ref` was
+    /// waiting with `remaining` symbols left after the one it awaited, in ascending order.
+    pub(crate) fn waiting(&self, back_ref: usize, rule: usize, remaining: usize) -> &[usize] {
+        self.waiting.get(&(back_ref, rule as u32, remaining)).map_or(&[], Vec::as_slice)
+    }
+    fn known(&self, sym: NtSymbol, start: usize, end: usize) -> Option<bool> {
+        let group = self.completions.completed_at(end);
+        let idx = group.partition_point(|c| (c.0, c.1) < (sym, start));
+        if group.get(idx).is_some_and(|c| (c.0, c.1) == (sym, start)) {
+            return Some(true);
+        }
+        self.found.get(&(sym, start, end)).copied()
+    }
+    /// Whether `sym` was recognized from `start` to `end`.
+    ///
+    /// It was if it was recorded, or if something it was waiting on with nothing left to
+    /// match afterwards was recognized up to `end`, which is how the items skipped by
+    /// deterministic reduction paths are found again.
+    pub(crate) fn contains(&mut self, sym: NtSymbol, start: usize, end: usize) -> bool {
+        if let Some(found) = self.known(sym, start, end) {
+            return found;
+        }
+        // Depth first through the finished waiting items, so long paths don't recurse.
+        // Each item on the stack is waiting on the one above it, with the index of the
+        // next of its finished waiting items to try.
+        let mut stack = vec![(sym, start, 0)];
+        let mut visiting = HashSet::from([(sym, start)]);
+        // Items that were given up on because they're being visited might still be found,
+        // so nothing can be remembered as missing after that
+        let mut cyclic = false;
+        loop {
+            let (sym, start, next) = *stack.last().unwrap();
+            let child = self.finished.get(&(start, sym)).and_then(|children| children.get(next)).copied();
+            let found = match child {
+                Some((pos, awaited)) => {
+                    stack.last_mut().unwrap().2 += 1;
+                    if pos > end {
+                        continue;
+                    }
+                    if visiting.contains(&(awaited, pos)) {
+                        cyclic = true;
+                        continue;
+                    }
+                    match self.known(awaited, pos, end) {
+                        Some(true) => true,
+                        Some(false) => continue,
+                        None => {
+                            visiting.insert((awaited, pos));
+                            stack.push((awaited, pos, 0));
+                            continue;
+                        }
+                    }
+                }
+                None => false,
+            };
+            if found {
+                // Then everything waiting on it was recognized too
+                for (sym, start, _) in stack {
+                    self.found.insert((sym, start, end), true);
+                }
+                return true;
+            }
+            stack.pop();
+            visiting.remove(&(sym, start));
+            if !cyclic {
+                self.found.insert((sym, start, end), false);
+            }
+            if stack.is_empty() {
+                return false;
+            }
         }
     }
 }
@@ -331,14 +450,20 @@ impl<'a, 'b, Symbol: Ord + CfgSymbol> CompletionsTransaction<'a, 'b, Symbol> {
         &mut self,
         back_ref: usize,
         sym: NtSymbol,
-    ) -> impl Iterator<Item = State<'a, Symbol>> + '_ {
+    ) -> impl Iterator<Item = State> + '_ {
         self.completions.query(back_ref, sym)
     }
-    pub(crate) fn push(&mut self, nt: NtSymbol, state: State<'a, Symbol>) {
-        self.completions.completions.push((
-            nt,
-            (state.back_ref, state.sym, state.rule, Remaining::More(state.remaining)),
-        ));
+    /// Record that `state` is waiting on `nt`, and continues with `state` once it's recognized.
+    pub(crate) fn push(&mut self, nt: NtSymbol, state: State) {
+        self.completions.completions.push(Completion {
+            awaited: nt,
+            state,
+            forwarded: None,
+        });
+    }
+    /// Record that `sym` was recognized from `back_ref` up to this group's position.
+    pub(crate) fn complete(&mut self, back_ref: usize, sym: NtSymbol, rule: u32) {
+        self.completions.completed.push((sym, back_ref, rule));
     }
     pub(crate) fn batch_id(&self) -> usize {
         self.completions.completion_index.len() - 1
@@ -346,9 +471,21 @@ impl<'a, 'b, Symbol: Ord + CfgSymbol> CompletionsTransaction<'a, 'b, Symbol> {
 }
 impl<'a, 'b, Symbol: Ord> Drop for CompletionsTransaction<'a, 'b, Symbol> {
     fn drop(&mut self) {
-        self.completions.completions[self.start_len..].sort_by_key(|c| c.0);
+        // The stable sorts would allocate, the states break the ties instead
+        self.completions.completions[self.start_len..].sort_unstable_by_key(|c| (c.awaited, c.state));
         self.completions
             .completion_index
             .push(self.completions.completions.len());
+
+        // Nullable items are completed once per prediction, so they turn up repeatedly
+        let completed_start = *self.completions.completed_index.last().unwrap();
+        let group = &mut self.completions.completed[completed_start..];
+        group.sort_unstable();
+        let len = completed_start
+            + crate::set_buffers::slice_retain_with_context(group, |cx, c| cx.last() != Some(c));
+        self.completions.completed.truncate(len);
+        self.completions
+            .completed_index
+            .push(self.completions.completed.len());
     }
 }
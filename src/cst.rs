@@ -0,0 +1,260 @@
+//! Lossless concrete syntax trees.
+//!
+//! The AST from [`trace_to_ast`](crate::trace_to_ast) has a node for every nonterminal,
+//! including the whitespace and comments in between everything else. For formatters
+//! and refactoring tools it's more convenient to have those as *trivia*: the CST keeps
+//! the tree of the other nonterminals, and hangs the trivia off the tokens next to them.
+//!
+//! ```ignore
+//! let cst = CstBuilder::new(&cfg).trivia(gap).build(&ast);
+//! assert_eq!(cst.text(src), src);
+//! ```
+//!
+//! A token is a run of terminals written next to each other in a rule, like the `"and"`
+//! in `and ::= gap "and" gap`. Every terminal of the input is in exactly one token or
+//! piece of trivia, so the tokens and their trivia give back the exact input.
+//!
+//! Trivia is attached to the token before it, except for trivia at the start of the
+//! input, which leads the first token.
+//!
+//! Nodes reshaped by the grammar's [`Shape`](crate::grammar::Shape)s don't have the parts
+//! of their rule to go by, so the text around their children is made into tokens instead.
+//! An atomic node is a single token.
+use std::ops::Range;
+
+use crate::grammar::Cfg;
+use crate::recognizer::NtSymbol;
+use crate::{CfgSymbol, Node};
+
+#[derive(Debug)]
+pub struct Cst<'c, Symbol> {
+    /// The nodes and tokens of the tree in pre-order, like an AST.
+    pub elements: Vec<Element<'c, Symbol>>,
+    pub tokens: Vec<Token>,
+    pub trivia: Vec<Trivia>,
+}
+#[derive(Debug)]
+pub enum Element<'c, Symbol> {
+    Node(CstNode<'c, Symbol>),
+    /// An index into `Cst::tokens`
+    Token(usize),
+}
+#[derive(Debug)]
+pub struct CstNode<'c, Symbol> {
+    pub nt: NtSymbol,
+    pub transition: &'c [Symbol],
+    // The index of the rule in `Cfg::rules`
+    pub rule: usize,
+    pub start: usize,
+    pub end: usize,
+    pub transitive_children: usize,
+}
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub start: usize,
+    pub end: usize,
+    /// Indices into `Cst::trivia`
+    pub leading: Range<usize>,
+    pub trailing: Range<usize>,
+}
+/// The span of a trivia nonterminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Trivia {
+    pub nt: NtSymbol,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Builds CSTs from the ASTs of a grammar, given which of its nonterminals are trivia.
+pub struct CstBuilder<'a, Symbol> {
+    cfg: &'a Cfg<Symbol>,
+    trivia: Vec<NtSymbol>,
+}
+impl<'a, Symbol: CfgSymbol> CstBuilder<'a, Symbol> {
+    pub fn new(cfg: &'a Cfg<Symbol>) -> Self {
+        Self { cfg, trivia: vec![] }
+    }
+    /// Treat `nt` as trivia. Everything inside it is part of the trivia too.
+    pub fn trivia(mut self, nt: NtSymbol) -> Self {
+        self.trivia.push(nt);
+        self
+    }
+    fn is_trivia(&self, node: &Node<'_, Symbol>) -> bool {
+        self.trivia.contains(&self.cfg.rules[node.rule].for_nt)
+    }
+
+    /// Build the CST for the tree rooted at `ast[0]`.
+    pub fn build<'c>(&self, ast: &[Node<'c, Symbol>]) -> Cst<'c, Symbol> {
+        let mut cst = Cst {
+            elements: vec![],
+            tokens: vec![],
+            trivia: vec![],
+        };
+        // Like `visit::walk`, this uses an explicit stack so long lists don't overflow
+        struct Frame {
+            node: usize,
+            element: usize,
+            part: usize,
+            next_child: usize,
+            reshaped: bool,
+        }
+        let mut stack = vec![Frame {
+            node: 0,
+            element: 0,
+            part: 0,
+            next_child: 1,
+            reshaped: self.cfg.rule_is_reshaped(ast[0].rule),
+        }];
+        cst.push_node(self.cfg, &ast[0]);
+        let mut pos = ast[0].start;
+        // The start of the run of terminals we're in, if any
+        let mut token_start = None;
+        // Trivia before the first token
+        let mut leading = 0..0;
+        while let Some(frame) = stack.last_mut() {
+            let node = &ast[frame.node];
+            if frame.reshaped {
+                // `part` counts the children instead
+                let next = ast.get(frame.next_child).filter(|_| frame.part < node.children);
+                let end = next.map_or(node.end, |child| child.start);
+                if pos < end {
+                    token_start.get_or_insert(pos);
+                    pos = end;
+                }
+                frame.part += 1;
+                if next.is_none() {
+                    cst.push_token(&mut token_start, pos, &mut leading);
+                    cst.close(frame.element);
+                    stack.pop();
+                    continue;
+                }
+            } else {
+                let Some(part) = node.transition.get(frame.part) else {
+                    cst.push_token(&mut token_start, pos, &mut leading);
+                    cst.close(frame.element);
+                    stack.pop();
+                    continue;
+                };
+                frame.part += 1;
+                if part.as_part().is_ok() {
+                    token_start.get_or_insert(pos);
+                    pos += 1;
+                    continue;
+                }
+            }
+            cst.push_token(&mut token_start, pos, &mut leading);
+            let child_idx = frame.next_child;
+            let child = &ast[child_idx];
+            frame.next_child += 1 + child.transitive_children;
+            if self.is_trivia(child) {
+                cst.trivia.push(Trivia {
+                    nt: self.cfg.rules[child.rule].for_nt,
+                    start: child.start,
+                    end: child.end,
+                });
+                match cst.tokens.last_mut() {
+                    Some(token) => token.trailing.end += 1,
+                    None => leading.end += 1,
+                }
+                pos = child.end;
+            } else {
+                stack.push(Frame {
+                    node: child_idx,
+                    element: cst.elements.len(),
+                    part: 0,
+                    next_child: child_idx + 1,
+                    reshaped: self.cfg.rule_is_reshaped(child.rule),
+                });
+                cst.push_node(self.cfg, child);
+            }
+        }
+        // Trivia without any tokens to lead still needs a (empty) token to keep it
+        if !leading.is_empty() {
+            token_start = Some(pos);
+            cst.push_token(&mut token_start, pos, &mut leading);
+            cst.close(0);
+        }
+        cst
+    }
+}
+
+impl<'c, Symbol: CfgSymbol> Cst<'c, Symbol> {
+    fn push_node(&mut self, cfg: &Cfg<Symbol>, node: &Node<'c, Symbol>) {
+        self.elements.push(Element::Node(CstNode {
+            nt: cfg.rules[node.rule].for_nt,
+            transition: node.transition,
+            rule: node.rule,
+            start: node.start,
+            end: node.end,
+            transitive_children: 0,
+        }));
+    }
+    fn push_token(&mut self, start: &mut Option<usize>, end: usize, leading: &mut Range<usize>) {
+        let Some(start) = start.take() else {
+            return;
+        };
+        let leading = if self.tokens.is_empty() {
+            std::mem::replace(leading, 0..0)
+        } else {
+            0..0
+        };
+        let trivia = self.trivia.len();
+        self.elements.push(Element::Token(self.tokens.len()));
+        self.tokens.push(Token {
+            start,
+            end,
+            leading,
+            trailing: trivia..trivia,
+        });
+    }
+    fn close(&mut self, element: usize) {
+        let len = self.elements.len();
+        if let Element::Node(node) = &mut self.elements[element] {
+            node.transitive_children = len - element - 1;
+        }
+    }
+}
+impl<Symbol> Cst<'_, Symbol> {
+    /// The source the tree was built from, reassembled from its tokens and trivia.
+    pub fn text<T: Clone>(&self, src: &[T]) -> Vec<T> {
+        let mut out = vec![];
+        let push_trivia = |out: &mut Vec<T>, range: Range<usize>| {
+            for trivia in &self.trivia[range] {
+                out.extend_from_slice(&src[trivia.start..trivia.end]);
+            }
+        };
+        for token in &self.tokens {
+            push_trivia(&mut out, token.leading.clone());
+            out.extend_from_slice(&src[token.start..token.end]);
+            push_trivia(&mut out, token.trailing.clone());
+        }
+        out
+    }
+    /// The elements for the children of the node at `element`, in order.
+    pub fn children(&self, element: usize) -> impl Iterator<Item = usize> + '_ {
+        let end = match &self.elements[element] {
+            Element::Node(node) => element + 1 + node.transitive_children,
+            Element::Token(_) => element + 1,
+        };
+        let mut next = element + 1;
+        std::iter::from_fn(move || {
+            let child = next;
+            if child >= end {
+                return None;
+            }
+            next += match &self.elements[child] {
+                Element::Node(node) => 1 + node.transitive_children,
+                Element::Token(_) => 1,
+            };
+            Some(child)
+        })
+    }
+}
+impl Token {
+    /// The span of the token including its trivia.
+    pub fn full_span(&self, trivia: &[Trivia]) -> Range<usize> {
+        let start = trivia[self.leading.clone()].first().map_or(self.start, |t| t.start);
+        let end = trivia[self.trailing.clone()].last().map_or(self.end, |t| t.end);
+        start..end
+    }
+}
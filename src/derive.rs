@@ -0,0 +1,205 @@
+//! Grammars declared by Rust types, with `#[derive(Grammar)]`.
+//!
+//! Each type becomes a nonterminal. A struct has one rule and an enum has a rule per
+//! variant, made of its fields in order. Literal tokens go between the fields with a
+//! `grammar` attribute, where `_` stands for the next field (or named fields by name):
+//!
+//! ```ignore
+//! #[derive(Grammar)]
+//! enum Primary {
+//!     #[grammar("not" _)]
+//!     Not(Box<Primary>),
+//!     #[grammar("(" _ ")")]
+//!     Paren(Box<Expr>),
+//!     #[grammar("true")]
+//!     True,
+//! }
+//! let expr: Expr = Parser::<Expr>::new().parse(b"not (true)")?;
+//! ```
+//!
+//! Named fields are labelled with their names in the grammar's rules.
+//!
+//! `Box<T>` is parsed as `T`, `Option<T>` as an optional `T`, and `Vec<T>` as any number of them.
+//! These are still parsed by the Earley recognizer, so left recursive and ambiguous
+//! declarations are fine.
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+pub use cfg_toy_derive::Grammar;
+
+use crate::Node;
+use crate::grammar::{Cfg, Rule};
+use crate::recognizer::{NtSymbol, ParseError};
+
+/// A type that can be parsed with the Earley parser. Usually derived.
+pub trait Grammar: Sized {
+    /// The name of the nonterminal for this type.
+    fn name() -> String {
+        std::any::type_name::<Self>().to_string()
+    }
+    /// Add the rules for this type's nonterminal `nt`, in the order of [`Tree::alternative`].
+    fn add_rules(builder: &mut GrammarBuilder, nt: NtSymbol);
+    /// The nonterminal this type is parsed as.
+    fn nonterminal(builder: &mut GrammarBuilder) -> NtSymbol
+    where
+        Self: 'static,
+    {
+        builder.nonterminal::<Self>()
+    }
+    /// Build the value from its node in the tree.
+    fn from_tree(tree: Tree<'_, '_>) -> Self;
+}
+
+/// Collects the rules for a set of types, giving each type a nonterminal.
+#[derive(Default)]
+pub struct GrammarBuilder {
+    rules: Vec<Rule<u32>>,
+    names: Vec<String>,
+    types: HashMap<TypeId, NtSymbol>,
+}
+impl GrammarBuilder {
+    /// The nonterminal for `T`, adding its rules the first time it's seen.
+    pub fn nonterminal<T: Grammar + 'static>(&mut self) -> NtSymbol {
+        if let Some(&nt) = self.types.get(&TypeId::of::<T>()) {
+            return nt;
+        }
+        // Allocated before adding the rules, so recursive types find it
+        let nt = 256 + self.names.len() as NtSymbol;
+        self.types.insert(TypeId::of::<T>(), nt);
+        self.names.push(T::name());
+        T::add_rules(self, nt);
+        nt
+    }
+    pub fn add_rule(&mut self, rule: Rule<u32>) {
+        self.rules.push(rule);
+    }
+}
+
+/// The parts for a literal token.
+pub fn literal(text: &str) -> impl Iterator<Item = u32> + '_ {
+    text.bytes().map(u32::from)
+}
+
+/// A node in a parse tree, for building the value of a type from.
+#[derive(Clone, Copy)]
+pub struct Tree<'a, 'c> {
+    cfg: &'a Cfg<u32>,
+    ast: &'a [Node<'c, u32>],
+    src: &'a [u8],
+}
+impl<'a, 'c> Tree<'a, 'c> {
+    /// Which of the type's rules matched, counting from 0 in the order they were added.
+    pub fn alternative(&self) -> usize {
+        let node = &self.ast[0];
+        node.rule - self.cfg.query_nt(self.cfg.rules[node.rule].for_nt).unwrap().start
+    }
+    /// The trees for the nonterminals in the rule.
+    pub fn children(&self) -> impl Iterator<Item = Tree<'a, 'c>> + 'a {
+        let (cfg, src) = (self.cfg, self.src);
+        crate::visit::children(self.ast).map(move |ast| Tree { cfg, ast, src })
+    }
+    /// The tree for the part of the rule labelled `name`.
+    pub fn child_by_field(&self, name: &str) -> Option<Tree<'a, 'c>> {
+        let ast = crate::visit::child_by_field(self.ast, name)?;
+        Some(Tree { ast, ..*self })
+    }
+    /// The input this node was parsed from.
+    pub fn text(&self) -> &'a [u8] {
+        &self.src[self.ast[0].start..self.ast[0].end]
+    }
+}
+
+/// A parser for `T` and everything it contains.
+pub struct Parser<T> {
+    cfg: Cfg<u32>,
+    names: Vec<String>,
+    root: NtSymbol,
+    _parses: PhantomData<fn() -> T>,
+}
+impl<T: Grammar + 'static> Default for Parser<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<T: Grammar + 'static> Parser<T> {
+    pub fn new() -> Self {
+        let mut builder = GrammarBuilder::default();
+        let root = T::nonterminal(&mut builder);
+        Self {
+            cfg: Cfg::new(builder.rules),
+            names: builder.names,
+            root,
+            _parses: PhantomData,
+        }
+    }
+    /// The grammar, its nonterminals numbered from 256.
+    pub fn cfg(&self) -> &Cfg<u32> {
+        &self.cfg
+    }
+    /// The names of the nonterminals, from 256 upwards.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+    pub fn parse(&self, src: &[u8]) -> Result<T, ParseError> {
+        let completions = crate::try_parse_earley(&self.cfg, src, self.root, ())?;
+        let ast = crate::trace_to_ast(&self.cfg, src, &completions, &self.root);
+        Ok(T::from_tree(Tree {
+            cfg: &self.cfg,
+            ast: &ast,
+            src,
+        }))
+    }
+}
+
+impl<T: Grammar + 'static> Grammar for Box<T> {
+    fn name() -> String {
+        T::name()
+    }
+    fn add_rules(builder: &mut GrammarBuilder, nt: NtSymbol) {
+        T::add_rules(builder, nt)
+    }
+    // Boxes are only there to break up recursive types, they don't need their own nonterminal
+    fn nonterminal(builder: &mut GrammarBuilder) -> NtSymbol {
+        T::nonterminal(builder)
+    }
+    fn from_tree(tree: Tree<'_, '_>) -> Self {
+        Box::new(T::from_tree(tree))
+    }
+}
+impl<T: Grammar + 'static> Grammar for Option<T> {
+    fn name() -> String {
+        format!("Option<{}>", T::name())
+    }
+    fn add_rules(builder: &mut GrammarBuilder, nt: NtSymbol) {
+        builder.add_rule(Rule::new(nt, vec![]));
+        let item = T::nonterminal(builder);
+        builder.add_rule(Rule::new(nt, vec![item]));
+    }
+    fn from_tree(tree: Tree<'_, '_>) -> Self {
+        tree.children().next().map(T::from_tree)
+    }
+}
+impl<T: Grammar + 'static> Grammar for Vec<T> {
+    fn name() -> String {
+        format!("Vec<{}>", T::name())
+    }
+    // list ::= ε | list item
+    fn add_rules(builder: &mut GrammarBuilder, nt: NtSymbol) {
+        builder.add_rule(Rule::new(nt, vec![]));
+        let item = T::nonterminal(builder);
+        builder.add_rule(Rule::new(nt, vec![nt, item]));
+    }
+    fn from_tree(mut tree: Tree<'_, '_>) -> Self {
+        // Walk down the left spine, rather than recursing for each item
+        let mut items = vec![];
+        while tree.alternative() == 1 {
+            let mut children = tree.children();
+            let rest = children.next().unwrap();
+            items.push(T::from_tree(children.next().unwrap()));
+            tree = rest;
+        }
+        items.reverse();
+        items
+    }
+}
@@ -0,0 +1,368 @@
+//! Structural diffs between two trees parsed with the same grammar, in the style of GumTree.
+//!
+//! The nodes of the old tree are first matched to nodes of the new one, and then every
+//! node is reported as inserted, deleted, moved or updated (parsed with another rule of
+//! the same nonterminal) depending on how it was matched:
+//!
+//! 1. Identical subtrees are matched, the largest first. Since every terminal is written
+//!    in a rule, two subtrees with the same rules in the same shape have the same text.
+//! 2. Nodes that weren't matched are matched to a node for the same nonterminal in the
+//!    new tree that contains enough of the matches of their descendants.
+//! 3. Inside matched nodes, the remaining identical subtrees are matched, and then the
+//!    unmatched children are matched in order by their nonterminals.
+//!
+//! Everything works on the flat pre-order ASTs from [`trace_to_ast`](crate::trace_to_ast),
+//! with a few arrays on the side for the parents, heights and hashes of the nodes.
+use std::collections::HashMap;
+use std::hash::{BuildHasherDefault, Hash, Hasher};
+
+use crate::grammar::Cfg;
+use crate::recognizer::NtSymbol;
+use crate::set_buffers::StateHasher;
+use crate::{CfgSymbol, Node};
+
+// Subtrees smaller than this are left to the later phases, since they match too easily
+const MIN_HEIGHT: usize = 2;
+// How much of a node's descendants have to be in a candidate for them to be matched
+const MIN_DICE: f64 = 0.5;
+// Nodes with more descendants than this aren't searched for identical subtrees in phase 3
+const MAX_RECOVERY_SIZE: usize = 100;
+
+/// A node in one of the trees.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiffNode {
+    /// The index of the node in its AST
+    pub index: usize,
+    /// The index of the rule in `Cfg::rules`
+    pub rule: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edit {
+    /// A node of the new tree that wasn't in the old one. `parent` is `None` for the root.
+    Insert { new: DiffNode, parent: Option<DiffNode> },
+    /// A node of the old tree that isn't in the new one.
+    Delete { old: DiffNode },
+    /// A node that's somewhere else in the new tree: under another parent, or in another
+    /// order among its siblings.
+    Move { old: DiffNode, new: DiffNode },
+    /// A node that was parsed with another rule for the same nonterminal.
+    Update { old: DiffNode, new: DiffNode },
+}
+
+#[derive(Debug, Clone)]
+pub struct Diff {
+    /// The nodes of the old tree that were matched, paired with their match in the new tree
+    pub matches: Vec<(usize, usize)>,
+    /// The updates, insertions and moves in pre-order of the new tree, then the deletions
+    /// in pre-order of the old tree.
+    pub edits: Vec<Edit>,
+}
+
+/// Diff the trees rooted at `old[0]` and `new[0]`.
+pub fn diff<Symbol: CfgSymbol>(cfg: &Cfg<Symbol>, old: &[Node<'_, Symbol>], new: &[Node<'_, Symbol>]) -> Diff {
+    let old = Tree::new(cfg, old);
+    let new = Tree::new(cfg, new);
+    let mut matcher = Matcher {
+        old_to_new: vec![None; old.ast.len()],
+        new_to_old: vec![None; new.ast.len()],
+        old: &old,
+        new: &new,
+    };
+    matcher.top_down();
+    matcher.bottom_up();
+    let Matcher {
+        old_to_new, new_to_old, ..
+    } = matcher;
+
+    // Moves are found for the children of each node at once, to tell which ones are out of order
+    let mut moved = vec![false; new.ast.len()];
+    for b in 0..new.ast.len() {
+        let children = new.children(b);
+        let Some(a) = new_to_old[b] else {
+            // Every matched child of an inserted node has moved
+            for child in children {
+                moved[child] = new_to_old[child].is_some();
+            }
+            continue;
+        };
+        let mut stayed = vec![];
+        for &child in &children {
+            match new_to_old[child] {
+                Some(old_child) if old.parent[old_child] == Some(a) => stayed.push(old_child),
+                Some(_) => moved[child] = true,
+                None => {}
+            }
+        }
+        // The children that are still under the same parent, but out of order
+        let in_order = longest_increasing(&stayed);
+        for &child in &children {
+            if let Some(old_child) = new_to_old[child]
+                && old.parent[old_child] == Some(a)
+                && !in_order.contains(&old_child)
+            {
+                moved[child] = true;
+            }
+        }
+    }
+    let mut edits = vec![];
+    for b in 0..new.ast.len() {
+        let Some(a) = new_to_old[b] else {
+            let parent = new.parent[b].map(|parent| new.node(parent));
+            edits.push(Edit::Insert {
+                new: new.node(b),
+                parent,
+            });
+            continue;
+        };
+        if old.ast[a].rule != new.ast[b].rule {
+            edits.push(Edit::Update {
+                old: old.node(a),
+                new: new.node(b),
+            });
+        }
+        if moved[b] {
+            edits.push(Edit::Move {
+                old: old.node(a),
+                new: new.node(b),
+            });
+        }
+    }
+    for (a, matched) in old_to_new.iter().enumerate() {
+        if matched.is_none() {
+            edits.push(Edit::Delete { old: old.node(a) });
+        }
+    }
+    Diff {
+        matches: old_to_new.iter().enumerate().filter_map(|(a, b)| Some((a, (*b)?))).collect(),
+        edits,
+    }
+}
+
+// The elements of the longest increasing subsequence of `values`
+fn longest_increasing(values: &[usize]) -> Vec<usize> {
+    // The index of the smallest last element of an increasing subsequence of each length,
+    // and the index of the element before each one in its subsequence
+    let mut tails: Vec<usize> = vec![];
+    let mut previous = vec![None; values.len()];
+    for (i, &value) in values.iter().enumerate() {
+        let len = tails.partition_point(|&tail| values[tail] < value);
+        previous[i] = len.checked_sub(1).map(|len| tails[len]);
+        if len == tails.len() {
+            tails.push(i);
+        } else {
+            tails[len] = i;
+        }
+    }
+    let mut result = vec![];
+    let mut i = tails.last().copied();
+    while let Some(idx) = i {
+        result.push(values[idx]);
+        i = previous[idx];
+    }
+    result.reverse();
+    result
+}
+
+struct Tree<'a, 'c, Symbol> {
+    ast: &'a [Node<'c, Symbol>],
+    nt: Vec<NtSymbol>,
+    parent: Vec<Option<usize>>,
+    height: Vec<usize>,
+    hash: Vec<u64>,
+}
+impl<'a, 'c, Symbol> Tree<'a, 'c, Symbol> {
+    fn new(cfg: &Cfg<Symbol>, ast: &'a [Node<'c, Symbol>]) -> Self {
+        let ast = &ast[..ast.first().map_or(0, |root| root.transitive_children + 1)];
+        let mut parent = vec![None; ast.len()];
+        // The nodes we're inside of, paired with the index their subtree ends at
+        let mut open: Vec<(usize, usize)> = vec![];
+        for (i, node) in ast.iter().enumerate() {
+            while open.last().is_some_and(|&(_, end)| end <= i) {
+                open.pop();
+            }
+            parent[i] = open.last().map(|&(parent, _)| parent);
+            open.push((i, i + 1 + node.transitive_children));
+        }
+        // Walking the pre-order backwards reaches every child before its parent,
+        // with the values for a node's children on top of the stack (first child last)
+        let mut height = vec![0; ast.len()];
+        let mut hash = vec![0; ast.len()];
+        let mut stack: Vec<usize> = vec![];
+        for (i, node) in ast.iter().enumerate().rev() {
+            let mut hasher = StateHasher::default();
+            node.rule.hash(&mut hasher);
+            let mut max_height = 0;
+            for child in stack.drain(stack.len() - node.children..).rev() {
+                hash[child].hash(&mut hasher);
+                max_height = max_height.max(height[child]);
+            }
+            hash[i] = hasher.finish();
+            height[i] = 1 + max_height;
+            stack.push(i);
+        }
+        Self {
+            nt: ast.iter().map(|node| cfg.rules[node.rule].for_nt).collect(),
+            ast,
+            parent,
+            height,
+            hash,
+        }
+    }
+    fn node(&self, idx: usize) -> DiffNode {
+        let node = &self.ast[idx];
+        DiffNode {
+            index: idx,
+            rule: node.rule,
+            start: node.start,
+            end: node.end,
+        }
+    }
+    fn end(&self, idx: usize) -> usize {
+        idx + 1 + self.ast[idx].transitive_children
+    }
+    fn children(&self, idx: usize) -> Vec<usize> {
+        let mut children = vec![];
+        let mut child = idx + 1;
+        for _ in 0..self.ast[idx].children {
+            children.push(child);
+            child = self.end(child);
+        }
+        children
+    }
+}
+
+struct Matcher<'t, 'a, 'c, Symbol> {
+    old: &'t Tree<'a, 'c, Symbol>,
+    new: &'t Tree<'a, 'c, Symbol>,
+    old_to_new: Vec<Option<usize>>,
+    new_to_old: Vec<Option<usize>>,
+}
+impl<Symbol> Matcher<'_, '_, '_, Symbol> {
+    fn add(&mut self, a: usize, b: usize) {
+        self.old_to_new[a] = Some(b);
+        self.new_to_old[b] = Some(a);
+    }
+    fn isomorphic(&self, a: usize, b: usize) -> bool {
+        let (old, new) = (self.old, self.new);
+        old.hash[a] == new.hash[b]
+            && old.end(a) - a == new.end(b) - b
+            && (old.ast[a..old.end(a)].iter().zip(&new.ast[b..new.end(b)]))
+                .all(|(x, y)| (x.rule, x.children) == (y.rule, y.children))
+    }
+
+    // Phase 1: the largest identical subtrees
+    fn top_down(&mut self) {
+        let mut by_hash: HashMap<u64, Vec<usize>, BuildHasherDefault<StateHasher>> = HashMap::default();
+        for b in 0..self.new.ast.len() {
+            if self.new.height[b] >= MIN_HEIGHT {
+                by_hash.entry(self.new.hash[b]).or_default().push(b);
+            }
+        }
+        let mut old_nodes = (0..self.old.ast.len()).filter(|&a| self.old.height[a] >= MIN_HEIGHT).collect::<Vec<_>>();
+        old_nodes.sort_by_key(|&a| std::cmp::Reverse(self.old.height[a]));
+        for a in old_nodes {
+            if self.old_to_new[a].is_some() {
+                continue;
+            }
+            // Out of the identical subtrees, the one that's closest in the input
+            let candidates = by_hash.get(&self.old.hash[a]).into_iter().flatten();
+            let candidates = candidates.filter(|&&b| self.new_to_old[b].is_none() && self.isomorphic(a, b));
+            let Some(&b) = candidates.min_by_key(|&&b| self.old.ast[a].start.abs_diff(self.new.ast[b].start)) else {
+                continue;
+            };
+            for offset in 0..self.old.end(a) - a {
+                self.add(a + offset, b + offset);
+            }
+        }
+    }
+
+    // Phases 2 and 3: the nodes containing the matches, and then their children
+    fn bottom_up(&mut self) {
+        let (old, new) = (self.old, self.new);
+        // The matches of the descendants of the node being matched, sorted. A subtree of the
+        // new tree is a range of the pre-order, so the ones inside it are found by bisecting.
+        let mut matches = vec![];
+        // The node each new node was last visited for as a candidate, plus one
+        let mut visited = vec![0; new.ast.len()];
+        let mut candidates = vec![];
+        // Backwards through the pre-order, so descendants are matched before their ancestors
+        for a in (0..old.ast.len()).rev() {
+            if self.old_to_new[a].is_some() {
+                continue;
+            }
+            let descendants = old.end(a) - a - 1;
+            matches.clear();
+            matches.extend((a + 1..old.end(a)).filter_map(|d| self.old_to_new[d]));
+            matches.sort_unstable();
+            // The unmatched ancestors of the matches of the descendants, for the same nonterminal.
+            // Above an ancestor that was already visited, the rest were visited too.
+            candidates.clear();
+            for &m in &matches {
+                let mut b = new.parent[m];
+                while let Some(ancestor) = b {
+                    if std::mem::replace(&mut visited[ancestor], a + 1) == a + 1 {
+                        break;
+                    }
+                    if self.new_to_old[ancestor].is_none() && new.nt[ancestor] == old.nt[a] {
+                        candidates.push(ancestor);
+                    }
+                    b = new.parent[ancestor];
+                }
+            }
+            let dice = |b: usize| {
+                let common = matches.partition_point(|&m| m < new.end(b)) - matches.partition_point(|&m| m <= b);
+                2.0 * common as f64 / (descendants + new.end(b) - b - 1) as f64
+            };
+            let best = candidates.iter().map(|&b| (b, dice(b))).max_by(|x, y| x.1.total_cmp(&y.1));
+            let matched = match best {
+                Some((b, dice)) if dice >= MIN_DICE => Some(b),
+                // The roots are always matched, so the diff is between their contents
+                _ if a == 0 && self.new_to_old[0].is_none() && old.nt[0] == new.nt[0] => Some(0),
+                _ => None,
+            };
+            if let Some(b) = matched {
+                self.add(a, b);
+                self.recover(a, b);
+            }
+        }
+    }
+    fn recover(&mut self, a: usize, b: usize) {
+        let (old, new) = (self.old, self.new);
+        // Small nodes are searched for identical subtrees of any size first,
+        // so that wrapping a node in another one doesn't match the wrong ones
+        if old.end(a) - a <= MAX_RECOVERY_SIZE && new.end(b) - b <= MAX_RECOVERY_SIZE {
+            for d in a + 1..old.end(a) {
+                if self.old_to_new[d].is_some() {
+                    continue;
+                }
+                let found = (b + 1..new.end(b)).find(|&e| self.new_to_old[e].is_none() && self.isomorphic(d, e));
+                if let Some(e) = found {
+                    for offset in 0..old.end(d) - d {
+                        self.add(d + offset, e + offset);
+                    }
+                }
+            }
+        }
+        let mut pairs = vec![(a, b)];
+        while let Some((a, b)) = pairs.pop() {
+            let old_children = old.children(a).into_iter().filter(|&c| self.old_to_new[c].is_none());
+            let old_children = old_children.collect::<Vec<_>>();
+            let new_children = new.children(b).into_iter().filter(|&c| self.new_to_old[c].is_none());
+            let new_children = new_children.collect::<Vec<_>>();
+            // Greedily in order, so the children stay in the same order
+            let mut next = 0;
+            for old_child in old_children {
+                let found = new_children[next..].iter().position(|&c| new.nt[c] == old.nt[old_child]);
+                if let Some(offset) = found {
+                    let new_child = new_children[next + offset];
+                    next += offset + 1;
+                    self.add(old_child, new_child);
+                    pairs.push((old_child, new_child));
+                }
+            }
+        }
+    }
+}
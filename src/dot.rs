@@ -0,0 +1,198 @@
+//! Graphviz DOT export, for looking at the results of a parse while debugging grammars.
+//!
+//! ```ignore
+//! std::fs::write("ast.dot", cfg_toy::dot::ast(&cfg, &names, src, &ast)).unwrap();
+//! ```
+//!
+//! and then `dot -Tsvg ast.dot > ast.svg`. Like [`codegen`](crate::codegen), these take
+//! the names of the nonterminals from 256 upwards, as returned by `cfg!`.
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+
+use crate::codegen::{describe_rule, excerpt, nt_name};
+use crate::completions::Completions;
+use crate::grammar::Cfg;
+use crate::recognizer::NtSymbol;
+use crate::{CfgSymbol, Node};
+
+// Longer source excerpts are cut off in the labels
+const MAX_TEXT: usize = 32;
+
+/// The tree rooted at `ast[0]`, with each node labelled by its nonterminal, span and text.
+pub fn ast<Symbol: CfgSymbol>(
+    cfg: &Cfg<Symbol>,
+    names: &[&str],
+    src: &[Symbol::Terminal],
+    ast: &[Node<'_, Symbol>],
+) -> String {
+    let mut out = String::new();
+    writeln!(out, "digraph ast {{").unwrap();
+    writeln!(out, "  node [shape=box, fontname=monospace];").unwrap();
+    let end = ast.first().map_or(0, |root| root.transitive_children + 1);
+    for (i, node) in ast[..end].iter().enumerate() {
+        let name = nt_name(names, cfg.rules[node.rule].for_nt);
+        let text = excerpt::<Symbol>(&src[node.start..node.end], MAX_TEXT);
+        let label = format!("{name}\n{}..{}\n{text:?}", node.start, node.end);
+        writeln!(out, "  n{i} [label=\"{}\"];", escape(&label)).unwrap();
+        let mut child = i + 1;
+        for _ in 0..node.children {
+            writeln!(out, "  n{i} -> n{child};").unwrap();
+            child += 1 + ast[child].transitive_children;
+        }
+    }
+    writeln!(out, "}}").unwrap();
+    out
+}
+
+/// The items of the chart, in a cluster for each position.
+///
+/// Each position has the items that were waiting there for a nonterminal, and the items
+/// that completed there. Waiting items that the recognizer bypassed point to the
+/// forwarding records they were replaced by.
+pub fn chart<Symbol: CfgSymbol>(cfg: &Cfg<Symbol>, names: &[&str], completions: &Completions<'_, Symbol>) -> String {
+    let item = |sym: NtSymbol, rule: usize, dot: usize, back_ref: usize| {
+        let parts = &cfg.rules[rule].parts;
+        let mut desc = format!("{} ::=", nt_name(names, sym));
+        for (i, part) in parts.iter().enumerate() {
+            if i == dot {
+                desc.push_str(" •");
+            }
+            write!(desc, " {}", symbol_name::<Symbol>(names, part)).unwrap();
+        }
+        if dot == parts.len() {
+            desc.push_str(" •");
+        }
+        write!(desc, "  ({back_ref})").unwrap();
+        desc
+    };
+
+    let mut out = String::new();
+    writeln!(out, "digraph chart {{").unwrap();
+    writeln!(out, "  rankdir=LR;").unwrap();
+    writeln!(out, "  node [shape=box, fontname=monospace];").unwrap();
+    for (pos, window) in completions.completion_index.windows(2).enumerate() {
+        writeln!(out, "  subgraph cluster_{pos} {{").unwrap();
+        writeln!(out, "    label=\"{pos}\";").unwrap();
+        for idx in window[0]..window[1] {
+            let completion = completions.completions[idx];
+            let state = completion.state;
+            let label = format!(
+                "{}\nwaits for {}",
+                item(state.sym(cfg), state.rule as usize, state.dot as usize - 1, state.origin as usize),
+                nt_name(names, completion.awaited)
+            );
+            writeln!(out, "    w{idx} [label=\"{}\"];", escape(&label)).unwrap();
+        }
+        for (i, &(sym, back_ref, rule)) in completions.completed_at(pos).iter().enumerate() {
+            let label = item(sym, rule as usize, cfg.rules[rule as usize].parts.len(), back_ref);
+            writeln!(out, "    c{pos}_{i} [label=\"{}\", style=rounded];", escape(&label)).unwrap();
+        }
+        writeln!(out, "  }}").unwrap();
+    }
+    for (idx, record) in completions.forwarding_records.iter().enumerate() {
+        let label = item(record.sym(cfg), record.rule as usize, record.dot as usize, record.origin as usize);
+        writeln!(out, "  f{idx} [label=\"{}\", style=dashed];", escape(&label)).unwrap();
+    }
+    for (idx, completion) in completions.completions.iter().enumerate() {
+        if let Some(record) = completion.forwarded {
+            writeln!(out, "  w{idx} -> f{record} [style=dashed, label=\"bypass\"];").unwrap();
+        }
+    }
+    writeln!(out, "}}").unwrap();
+    out
+}
+
+/// The shared packed parse forest for `init_sym` over the whole input.
+///
+/// Symbol nodes (ellipses) are a nonterminal recognized over a span, and they point to a
+/// packed node (a box) for each rule it was recognized with. Each packed node points to
+/// every symbol node that's a child in some derivation with that rule, so ambiguities
+/// show up as symbol nodes with more than one packed node or child for the same part.
+pub fn forest<Symbol: CfgSymbol>(
+    cfg: &Cfg<Symbol>,
+    names: &[&str],
+    src: &[Symbol::Terminal],
+    completions: &Completions<'_, Symbol>,
+    init_sym: NtSymbol,
+) -> String {
+    use std::borrow::Borrow;
+    // (sym, start) -> ends, and (sym, start, end) -> rules
+    let mut ends: HashMap<(NtSymbol, usize), BTreeSet<usize>> = HashMap::new();
+    let mut rules: HashMap<(NtSymbol, usize, usize), BTreeSet<usize>> = HashMap::new();
+    for end in 0..=src.len() {
+        for (sym, start, rule) in completions.completed_ending_at(end) {
+            ends.entry((sym, start)).or_default().insert(end);
+            rules.entry((sym, start, end)).or_default().insert(rule as usize);
+        }
+    }
+    // Where each part of a rule can take us from `pos`
+    let step = |part: &Symbol, pos: usize| -> Vec<usize> {
+        match part.as_part() {
+            Ok(terminal) => match src.get(pos) {
+                Some(t) if t == terminal.borrow() => vec![pos + 1],
+                _ => vec![],
+            },
+            Err(nt) => ends.get(&(nt, pos)).into_iter().flatten().copied().collect(),
+        }
+    };
+
+    let mut out = String::new();
+    writeln!(out, "digraph forest {{").unwrap();
+    writeln!(out, "  node [fontname=monospace];").unwrap();
+    let mut seen = BTreeSet::new();
+    let mut todo = vec![(init_sym, 0, src.len())];
+    while let Some(symbol @ (sym, start, end)) = todo.pop() {
+        if !seen.insert(symbol) {
+            continue;
+        }
+        let label = format!("{} {start}..{end}", nt_name(names, sym));
+        writeln!(out, "  s{sym}_{start}_{end} [label=\"{}\"];", escape(&label)).unwrap();
+        for &rule in rules.get(&symbol).into_iter().flatten() {
+            let packed = format!("p{rule}_{start}_{end}");
+            let label = describe_rule(cfg, names, rule);
+            writeln!(out, "  {packed} [label=\"{}\", shape=box];", escape(&label)).unwrap();
+            writeln!(out, "  s{sym}_{start}_{end} -> {packed};").unwrap();
+
+            // The positions reachable after each prefix of the rule...
+            let parts = &cfg.rules[rule].parts;
+            let mut forward = vec![BTreeSet::from([start])];
+            for part in parts {
+                let next = forward.last().unwrap().iter().flat_map(|&pos| step(part, pos));
+                let next = next.filter(|&pos| pos <= end).collect();
+                forward.push(next);
+            }
+            // ...and then only those that can still get to the end
+            let mut backward = BTreeSet::from([end]);
+            for (k, part) in parts.iter().enumerate().rev() {
+                let mut previous = BTreeSet::new();
+                for &pos in &forward[k] {
+                    for next in step(part, pos).into_iter().filter(|next| backward.contains(next)) {
+                        previous.insert(pos);
+                        if let Err(nt) = part.as_part() {
+                            writeln!(out, "  {packed} -> s{nt}_{pos}_{next} [label=\"{k}\"];").unwrap();
+                            todo.push((nt, pos, next));
+                        }
+                    }
+                }
+                backward = previous;
+            }
+        }
+    }
+    writeln!(out, "}}").unwrap();
+    out
+}
+
+fn symbol_name<Symbol: CfgSymbol>(names: &[&str], symbol: &Symbol) -> String {
+    use std::borrow::Borrow;
+    match symbol.as_part() {
+        Ok(terminal) => match Symbol::terminal_char(terminal.borrow()) {
+            Some(c) => format!("{c:?}"),
+            None => format!("{:?}", terminal.borrow()),
+        },
+        Err(nt) => nt_name(names, nt).to_string(),
+    }
+}
+
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
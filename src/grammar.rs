@@ -2,6 +2,22 @@
 pub struct Rule<Symbol> {
     pub for_nt: u32,
     pub parts: Vec<Symbol>,
+    /// The labelled parts of the rule, as (label, index into `parts`).
+    /// They're written `label:part` in `cfg!`.
+    pub fields: Vec<(&'static str, usize)>,
+}
+impl<Symbol> Rule<Symbol> {
+    pub fn new(for_nt: u32, parts: Vec<Symbol>) -> Self {
+        Self {
+            for_nt,
+            parts,
+            fields: vec![],
+        }
+    }
+    /// The index into `parts` of the part labelled `name`.
+    pub fn field(&self, name: &str) -> Option<usize> {
+        self.fields.iter().find(|&&(label, _)| label == name).map(|&(_, idx)| idx)
+    }
 }
 #[derive(Debug)]
 pub struct Cfg<Symbol> {
@@ -11,25 +27,90 @@ pub struct Cfg<Symbol> {
     pub nt_to_nullable_rules_index_offsets: Vec<usize>,
     pub nt_nullable: Vec<bool>,
     pub nt_index: Vec<usize>,
+    /// The terminals each nonterminal can start with, as the `(rule, part)` they appear at
+    /// in `rules`. The ones for `nt` are at `nt_first_offsets[nt]..nt_first_offsets[nt + 1]`,
+    /// sorted by the terminal and without repeats, so they can be searched.
+    pub nt_first: Vec<(usize, usize)>,
+    pub nt_first_offsets: Vec<usize>,
+    /// The same for each rule, by index in `rules`.
+    pub rule_first: Vec<(usize, usize)>,
+    pub rule_first_offsets: Vec<usize>,
+    /// How each nonterminal appears in the AST, indexed by nonterminal.
+    /// Nonterminals past the end are `Shape::Normal`.
+    pub nt_shape: Vec<Shape>,
+}
+/// How the nodes for a nonterminal are shaped by `trace_to_ast`.
+///
+/// Once a grammar uses any of these, a node's children don't necessarily line up with
+/// the nonterminals in its `transition` anymore, see [`Cfg::rule_is_reshaped`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Shape {
+    /// A node with its children, like every other nonterminal.
+    #[default]
+    Normal,
+    /// No node at all, nor for anything inside it, like `gap`.
+    Silent,
+    /// No node, but its children are spliced into its parent's.
+    Inlined,
+    /// A leaf node: the parse inside it is dropped, leaving its span (like `ident`).
+    Atomic,
 }
+/// A node that's been reshaped by the grammar's [`Shape`]s, where its children needed to
+/// line up with the nonterminals of its rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReshapedError {
+    /// The index of the node in the tree
+    pub node: usize,
+}
+impl std::fmt::Display for ReshapedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "node {} has been reshaped, so its children don't line up with its rule", self.node)
+    }
+}
+impl std::error::Error for ReshapedError {}
 impl<Symbol> Cfg<Symbol> {
     /// Needs to preserve nullability
-    pub fn map<U>(&self, mut f: impl FnMut(&Symbol) -> U) -> Cfg<U> {
+    pub fn map<U: crate::CfgSymbol>(&self, mut f: impl FnMut(&Symbol) -> U) -> Cfg<U> {
+        let rules = self
+            .rules
+            .iter()
+            .map(|rule| Rule {
+                for_nt: rule.for_nt,
+                parts: rule.parts.iter().map(&mut f).collect(),
+                fields: rule.fields.clone(),
+            })
+            .collect::<Vec<_>>();
+        // The new terminals might be ordered differently
+        let resort = |flat: &[(usize, usize)], offsets: &[usize]| {
+            let mut resorted = flat.to_vec();
+            for set in offsets.windows(2) {
+                resorted[set[0]..set[1]].sort_by(|&a, &b| terminal_order(&rules, a, b));
+            }
+            resorted
+        };
         Cfg {
-            rules: self
-                .rules
-                .iter()
-                .map(|rule| Rule {
-                    for_nt: rule.for_nt,
-                    parts: rule.parts.iter().map(&mut f).collect(),
-                })
-                .collect(),
             nt_index: self.nt_index.clone(),
             rule_nullable: self.rule_nullable.clone(),
             nt_nullable: self.nt_nullable.clone(),
             nt_to_nullable_rules_index: self.nt_to_nullable_rules_index.clone(),
             nt_to_nullable_rules_index_offsets: self.nt_to_nullable_rules_index_offsets.clone(),
+            nt_first: resort(&self.nt_first, &self.nt_first_offsets),
+            nt_first_offsets: self.nt_first_offsets.clone(),
+            rule_first: resort(&self.rule_first, &self.rule_first_offsets),
+            rule_first_offsets: self.rule_first_offsets.clone(),
+            nt_shape: self.nt_shape.clone(),
+            rules,
+        }
+    }
+    pub fn shape(&self, nt: u32) -> Shape {
+        self.nt_shape.get(nt as usize).copied().unwrap_or_default()
+    }
+    pub fn set_shape(&mut self, nt: u32, shape: Shape) {
+        let nt = nt as usize;
+        if self.nt_shape.len() <= nt {
+            self.nt_shape.resize(nt + 1, Shape::Normal);
         }
+        self.nt_shape[nt] = shape;
     }
 }
 fn nullable_closure<Symbol: crate::CfgSymbol>(rules: &[Rule<Symbol>]) -> (Vec<bool>, Vec<bool>) {
@@ -68,6 +149,88 @@ fn nullable_closure<Symbol: crate::CfgSymbol>(rules: &[Rule<Symbol>]) -> (Vec<bo
     }
     (rule_nullable, nt_nullable)
 }
+/// The FIRST sets of the nonterminals and then the rules, each flattened with its offsets
+type FirstSets = ((Vec<(usize, usize)>, Vec<usize>), (Vec<(usize, usize)>, Vec<usize>));
+// How the terminals at two `(rule, part)` positions compare
+fn terminal_order<Symbol: crate::CfgSymbol>(rules: &[Rule<Symbol>], a: (usize, usize), b: (usize, usize)) -> std::cmp::Ordering {
+    use std::borrow::Borrow;
+    match (rules[a.0].parts[a.1].as_part(), rules[b.0].parts[b.1].as_part()) {
+        (Ok(a), Ok(b)) => a.borrow().cmp(b.borrow()),
+        _ => unreachable!("FIRST sets only have terminals"),
+    }
+}
+// Sort a FIRST set by its terminals and drop the repeated ones
+fn sort_terminals<Symbol: crate::CfgSymbol>(rules: &[Rule<Symbol>], set: &mut Vec<(usize, usize)>) {
+    set.sort_by(|&a, &b| terminal_order(rules, a, b));
+    set.dedup_by(|a, b| terminal_order(rules, *a, *b).is_eq());
+}
+fn first_sets<Symbol: crate::CfgSymbol>(rules: &[Rule<Symbol>], nt_nullable: &[bool]) -> FirstSets {
+    // The terminals the rule can start with, given what the nonterminals can start with so far
+    let rule_first = |nt_first: &[Vec<(usize, usize)>], i: usize| {
+        let mut first: Vec<(usize, usize)> = vec![];
+        for (part_idx, part) in rules[i].parts.iter().enumerate() {
+            match part.as_part() {
+                Ok(_) => {
+                    first.push((i, part_idx));
+                    break;
+                }
+                Err(nt) => {
+                    first.extend_from_slice(nt_first.get(nt as usize).map_or(&[][..], Vec::as_slice));
+                    if !nt_nullable.get(nt as usize).copied().unwrap_or(false) {
+                        break;
+                    }
+                }
+            }
+        }
+        sort_terminals(rules, &mut first);
+        first
+    };
+    // The rules that start with each nonterminal, maybe after some nullable ones, which
+    // need looking at again when its FIRST set grows
+    let mut users = vec![vec![]; nt_nullable.len()];
+    for (i, rule) in rules.iter().enumerate() {
+        for part in &rule.parts {
+            let Err(nt) = part.as_part() else {
+                break;
+            };
+            if let Some(users) = users.get_mut(nt as usize) {
+                users.push(i);
+            }
+            if !nt_nullable.get(nt as usize).copied().unwrap_or(false) {
+                break;
+            }
+        }
+    }
+    let mut nt_first = vec![vec![]; nt_nullable.len()];
+    let mut todo = (0..rules.len()).collect::<Vec<_>>();
+    let mut queued = vec![true; rules.len()];
+    while let Some(i) = todo.pop() {
+        queued[i] = false;
+        let nt = rules[i].for_nt as usize;
+        let before = nt_first[nt].len();
+        let first = rule_first(&nt_first, i);
+        nt_first[nt].extend(first);
+        sort_terminals(rules, &mut nt_first[nt]);
+        if nt_first[nt].len() > before {
+            for &user in &users[nt] {
+                if !std::mem::replace(&mut queued[user], true) {
+                    todo.push(user);
+                }
+            }
+        }
+    }
+    let flatten = |sets: Vec<Vec<(usize, usize)>>| {
+        let mut offsets = vec![0];
+        let mut flat = vec![];
+        for set in sets {
+            flat.extend(set);
+            offsets.push(flat.len());
+        }
+        (flat, offsets)
+    };
+    let rule_first = (0..rules.len()).map(|i| rule_first(&nt_first, i)).collect();
+    (flatten(nt_first), flatten(rule_first))
+}
 impl<Symbol: super::CfgSymbol> Cfg<Symbol> {
     pub fn new(mut rules: Vec<Rule<Symbol>>) -> Self {
         rules.sort_by_key(|rule| rule.for_nt);
@@ -100,13 +263,20 @@ impl<Symbol: super::CfgSymbol> Cfg<Symbol> {
             nt_to_nullable_rules_index_offsets.push(nt_to_nullable_rules_index.len());
         }
 
+        let ((nt_first, nt_first_offsets), (rule_first, rule_first_offsets)) = first_sets(&rules, &nt_nullable);
+
         Self {
             rules,
             rule_nullable,
             nt_nullable,
             nt_index,
+            nt_first,
+            nt_first_offsets,
+            rule_first,
+            rule_first_offsets,
             nt_to_nullable_rules_index,
             nt_to_nullable_rules_index_offsets,
+            nt_shape: vec![],
         }
     }
     pub(crate) fn query_nullable(&self, nt: u32) -> Option<std::ops::Range<usize>> {
@@ -126,12 +296,52 @@ impl<Symbol: super::CfgSymbol> Cfg<Symbol> {
             .unwrap_or(0);
         Some(start..end)
     }
-    pub(crate) fn rules_for(&self, nt: u32) -> impl Iterator<Item = &'_ Rule<Symbol>> + '_ {
-        self.rules[self.query_nt(nt).unwrap()].iter()
+    /// Whether `nt` can match nothing. A nonterminal without any rules can't.
+    pub fn nt_is_nullable(&self, nt: u32) -> bool {
+        self.nt_nullable.get(nt as usize).copied().unwrap_or(false)
+    }
+    /// Whether `nt` can start with `terminal`, ignoring that it might match nothing.
+    pub fn nt_can_start_with(&self, nt: u32, terminal: &Symbol::Terminal) -> bool {
+        let nt = nt as usize;
+        let (Some(&start), Some(&end)) = (self.nt_first_offsets.get(nt), self.nt_first_offsets.get(nt + 1)) else {
+            return false;
+        };
+        self.any_terminal(&self.nt_first[start..end], terminal)
+    }
+    /// Whether the rule with index `rule` can start with `terminal`, ignoring that it might
+    /// match nothing.
+    pub fn rule_can_start_with(&self, rule: usize, terminal: &Symbol::Terminal) -> bool {
+        let first = &self.rule_first[self.rule_first_offsets[rule]..self.rule_first_offsets[rule + 1]];
+        self.any_terminal(first, terminal)
+    }
+    // The sets are sorted by terminal, see `first_sets`
+    fn any_terminal(&self, positions: &[(usize, usize)], terminal: &Symbol::Terminal) -> bool {
+        use std::borrow::Borrow;
+        positions
+            .binary_search_by(|&(rule, part)| match self.rules[rule].parts[part].as_part() {
+                Ok(t) => t.borrow().cmp(terminal),
+                Err(_) => unreachable!("FIRST sets only have terminals"),
+            })
+            .is_ok()
+    }
+    /// Whether the children of the nodes for the rule with index `rule` don't line up with
+    /// the nonterminals in its parts, because an atomic node has no children, or the rule
+    /// has silent or inlined nonterminals in it.
+    pub fn rule_is_reshaped(&self, rule: usize) -> bool {
+        let rule = &self.rules[rule];
+        let atomic = self.shape(rule.for_nt) == Shape::Atomic;
+        rule.parts
+            .iter()
+            .filter_map(|part| part.as_part().err())
+            .any(|nt| atomic || matches!(self.shape(nt), Shape::Silent | Shape::Inlined))
     }
 }
 #[macro_export]
 macro_rules! cfg_rules {
+    {$cx:ident $label:ident : $rule_name:ident $($t:tt)*} => {
+        $cx.4.push((stringify!($label), $cx.1.len()));
+        $crate::cfg_rules!($cx $rule_name $($t)*);
+    };
     {$cx:ident $rule_name:ident $($t:tt)*} => {
         $cx.1.push($rule_name);
         $crate::cfg_rules!($cx $($t)*)
@@ -140,11 +350,44 @@ macro_rules! cfg_rules {
         $cx.1.extend($literal.as_bytes().iter().map(|&b| b as u32));
         $crate::cfg_rules!($cx $($t)*);
     };
-    {$cx:ident . $rulename:ident :: = $($t:tt)*} => {
-        $cx.0.push($crate::grammar::Rule {
-            parts: std::mem::take(&mut $cx.1),
-            for_nt: $cx.2,
+    // An action runs until the `.` that ends the rule, so its tokens are collected
+    // up before we can tell where the closure's body ends.
+    {$cx:ident => || $($t:tt)*} => {
+        $crate::cfg_rules!(@action $cx [] [] $($t)*);
+    };
+    {$cx:ident => |$($arg:pat_param),*| $($t:tt)*} => {
+        $crate::cfg_rules!(@action $cx [$($arg),*] [] $($t)*);
+    };
+    {@action $cx:ident [$($arg:pat_param),*] [$($body:tt)*] . $rulename:ident :: = $($t:tt)*} => {
+        $crate::cfg_rules!(@push_action $cx [$($arg),*] [$($body)*]);
+        $crate::cfg_rules!($cx . $rulename ::= $($t)*);
+    };
+    {@action $cx:ident [$($arg:pat_param),*] [$($body:tt)*] .} => {
+        $crate::cfg_rules!(@push_action $cx [$($arg),*] [$($body)*]);
+        $crate::cfg_rules!($cx .);
+    };
+    {@action $cx:ident [$($arg:pat_param),*] [$($body:tt)*] $next:tt $($t:tt)*} => {
+        $crate::cfg_rules!(@action $cx [$($arg),*] [$($body)* $next] $($t)*);
+    };
+    {@push_action $cx:ident [$($arg:pat_param),*] [$($body:tt)*]} => {
+        assert_eq!(
+            <[()]>::len(&[$($crate::cfg_rules!(@unit $arg)),*]),
+            $cx.1.iter().filter(|&&part| 256 <= part).count(),
+            "the action needs one parameter for each nonterminal in the rule",
+        );
+        let action = $crate::actions::action(&$cx.3, move |args| {
+            let mut args = args.into_iter();
+            $(let $arg = args.next().unwrap();)*
+            $($body)*
         });
+        $cx.3.push(action);
+    };
+    {@unit $arg:pat_param} => { () };
+    {@shape silent} => { $crate::grammar::Shape::Silent };
+    {@shape inline} => { $crate::grammar::Shape::Inlined };
+    {@shape atomic} => { $crate::grammar::Shape::Atomic };
+    {$cx:ident . $rulename:ident :: = $($t:tt)*} => {
+        $crate::cfg_rules!($cx .);
         $cx.2 = $rulename;
         $crate::cfg_rules!($cx $($t)*);
     };
@@ -152,20 +395,51 @@ macro_rules! cfg_rules {
         $cx.0.push($crate::grammar::Rule {
             parts: std::mem::take(&mut $cx.1),
             for_nt: $cx.2,
+            fields: std::mem::take(&mut $cx.4),
         });
+        // Every rule gets an entry, the rules with actions have already pushed theirs
+        if $cx.3.len() < $cx.0.len() {
+            $cx.3.push(None);
+        }
     };
 }
+/// Build a `Cfg<u32>` from a list of nonterminals and BNF style rules, returning the
+/// grammar and the names of the nonterminals.
+///
+/// If the nonterminals are followed by `=> Type`, rules can have semantic actions
+/// producing values of that type, and they're returned as a third element.
+/// See the [`actions`](crate::actions) module.
+///
+/// Nonterminals can be marked `#[silent]`, `#[inline]` or `#[atomic]` to shape their
+/// nodes in the AST, see [`Shape`].
 #[macro_export]
 macro_rules! cfg {
     {
-        $($states:ident)*;
+        $($(#[$shape:ident])? $states:ident)* => $value:ty;
+        $first_rule:ident ::= $($rule_definition:tt)*
+    } => {{
+        let mut state_names: Vec<&'static str> = vec![];
+        let mut states = 256u32;
+        $(#[allow(non_snake_case)] let $states = states; #[allow(unused_assignments)] { states += 1; }; state_names.push(stringify!($states));)*
+        let mut cx: (Vec<$crate::grammar::Rule<u32>>, Vec<u32>, u32, Vec<Option<$crate::actions::Action<$value>>>, Vec<(&'static str, usize)>) = (vec![], vec![], $first_rule, vec![], vec![]);
+        $crate::cfg_rules!(cx $($rule_definition)*);
+        #[allow(unused_mut)]
+        let (mut cfg, actions) = $crate::actions::Actions::with_cfg(cx.0, cx.3);
+        $($(cfg.set_shape($states, $crate::cfg_rules!(@shape $shape));)?)*
+        (cfg, state_names, actions)
+    }};
+    {
+        $($(#[$shape:ident])? $states:ident)*;
         $first_rule:ident ::= $($rule_definition:tt)*
     } => {{
         let mut state_names: Vec<&'static str> = vec![];
         let mut states = 256u32;
-        $(let $states = states; #[allow(unused_assignments)] { states += 1; }; state_names.push(stringify!($states));)*
-        let mut cx: (Vec<$crate::grammar::Rule<u32>>, Vec<u32>, u32) = (vec![], vec![], $first_rule);
+        $(#[allow(non_snake_case)] let $states = states; #[allow(unused_assignments)] { states += 1; }; state_names.push(stringify!($states));)*
+        let mut cx: (Vec<$crate::grammar::Rule<u32>>, Vec<u32>, u32, Vec<Option<$crate::actions::Action<()>>>, Vec<(&'static str, usize)>) = (vec![], vec![], $first_rule, vec![], vec![]);
         $crate::cfg_rules!(cx $($rule_definition)*);
-        ($crate::grammar::Cfg::new(cx.0), state_names)
+        #[allow(unused_mut)]
+        let mut cfg = $crate::grammar::Cfg::new(cx.0);
+        $($(cfg.set_shape($states, $crate::cfg_rules!(@shape $shape));)?)*
+        (cfg, state_names)
     }};
 }
@@ -0,0 +1,56 @@
+//! The tokens shared by the little languages for queries, rewrites and S-expressions.
+//!
+//! They're all made of parentheses, names and strings, with `;` starting a comment that
+//! runs to the end of the line.
+
+/// An error in one of the languages, at a byte offset in its source.
+pub(crate) trait SyntaxError {
+    fn new(offset: usize, message: String) -> Self;
+}
+
+pub(crate) struct Lexer<'a> {
+    pub(crate) source: &'a str,
+    pub(crate) pos: usize,
+}
+impl<'a> Lexer<'a> {
+    pub(crate) fn new(source: &'a str) -> Self {
+        Self { source, pos: 0 }
+    }
+    pub(crate) fn error<T, E: SyntaxError>(&self, offset: usize, message: impl Into<String>) -> Result<T, E> {
+        Err(E::new(offset, message.into()))
+    }
+    pub(crate) fn rest(&self) -> &'a str {
+        &self.source[self.pos..]
+    }
+    // Skips whitespace and comments, returning the new position
+    pub(crate) fn skip_trivia(&mut self) -> usize {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+            if !trimmed.starts_with(';') {
+                return self.pos;
+            }
+            self.pos += trimmed.find('\n').unwrap_or(trimmed.len());
+        }
+    }
+    pub(crate) fn eat(&mut self, s: &str) -> bool {
+        self.skip_trivia();
+        let eaten = self.rest().starts_with(s);
+        if eaten {
+            self.pos += s.len();
+        }
+        eaten
+    }
+    // The name of a nonterminal, field or capture
+    pub(crate) fn identifier(&mut self) -> Option<&'a str> {
+        self.skip_trivia();
+        let len = self
+            .rest()
+            .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-' || c == '.'))
+            .unwrap_or(self.rest().len());
+        let start = self.pos;
+        self.pos += len;
+        Some(&self.source[start..self.pos]).filter(|ident| !ident.is_empty())
+    }
+}
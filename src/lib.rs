@@ -1,66 +1,91 @@
+pub mod actions;
+pub mod bounded;
 mod buffer_pair;
+pub mod codegen;
+pub mod compiled;
 pub mod completions;
+pub mod cst;
+pub mod derive;
+pub mod diff;
+pub mod dot;
 pub mod grammar;
+mod lexer;
+pub mod lr0;
+pub mod query;
 pub mod recognizer;
+pub mod render;
+pub mod rewrite;
+pub mod unparse;
+pub mod serialize;
 mod set_buffers;
+pub mod visit;
 use std::borrow::Borrow;
 
-pub use recognizer::{Trace, parse_earley};
+pub use actions::evaluate;
+pub use recognizer::{ParseError, Trace, parse_earley, try_parse_earley};
 
 enum CallFrame<'a, 'c, Symbol: CfgSymbol> {
-    ProcessNode(
-        &'a [Symbol::Terminal],
-        &'a [(usize, usize, NtSymbol, &'c [Symbol])],
-        &'c Symbol,
-        Option<(Vec<(usize, usize, u32, &'c [Symbol])>, usize)>,
-    ),
-    ReturnToParent(usize),
+    ProcessNode(&'a [Symbol::Terminal], &'c Symbol),
+    // The node that's done, or `None` for an inlined one that has no node
+    ReturnToParent(Option<usize>),
 }
-// Build an AST for a trace of an unambiguous parse.
+// Build an AST for an unambiguous parse.
 // That is, a parse where all ambiguities have been resolved, in examples like
 // S ::= "then"
 // S ::= "that"
 //
 // It's fine that the transition is ambiguous with arbitrary lookahead,
 // because it'll be resolved by the time we need to build the AST: one of the two will have been invalid
-// The trace should be sorted by (start, sym). This is not inherentely true of the trace we build during parsing.
-// It's sorted by `end` and arbitrary sym order.
+// Everything needed is in the chart: the completed items give us where each child could start,
+// and the waiting items tell us whether the rest of the rule could have matched before it.
 pub fn trace_to_ast<'c, Symbol: CfgSymbol + PartialEq>(
     cfg: &'c crate::grammar::Cfg<Symbol>,
     src: &[Symbol::Terminal],
-    init_trace: &[(usize, usize, NtSymbol, &'c [Symbol])],
     completions: &crate::completions::Completions<'c, Symbol>,
     init_sym: &'c Symbol,
 ) -> Ast<'c, Symbol> {
     let mut ast: Ast<'c, Symbol> = vec![];
+    let mut completed = completions::CompletedIndex::new(completions);
 
     // This virtual stack is used to speculatively visit children,
     // and allow it to be aborted with `stack.truncate()` if a rule fails to match.
     // It makes this function look way more complicated! It's not really necessary
     // for the logic, just premature "optimization" :D
-    let mut stack = vec![CallFrame::ProcessNode(src, init_trace, init_sym, None)];
-    // println!("{trace:?}");
+    let mut stack = vec![CallFrame::ProcessNode(src, init_sym)];
+    // The nodes we're inside of. The grammar's `Shape`s are applied as the nodes are pushed,
+    // so an inlined node's children are counted for the node it's inside of instead.
+    let mut open: Vec<usize> = vec![];
     'next_node: while let Some(res) = stack.pop() {
-        let (span, trace_slice, state, reconstructed) = match res {
-            CallFrame::ProcessNode(span, trace_slice, state, reconstructed) => (span, trace_slice, state, reconstructed),
-            CallFrame::ReturnToParent(idx) => {
+        let (span, state) = match res {
+            CallFrame::ProcessNode(span, state) => (span, state),
+            CallFrame::ReturnToParent(Some(idx)) => {
                 let current = ast.len();
                 let x: &mut Node<'c, Symbol> = &mut ast[idx];
                 x.transitive_children = current - idx - 1;
+                open.pop();
                 continue;
             }
+            CallFrame::ReturnToParent(None) => continue,
         };
         // println!("{state:?} under {:?}", stack.iter().map(|(_, s)| s).collect::<Vec<_>>());
         let state_nt = match state.as_part() {
             Either::Err(sym) => sym,
             Either::Ok(_) => panic!("terminal in trace"),
         };
-        let rules = cfg.rules_for(state_nt);
+        let shape = match cfg.shape(state_nt) {
+            // The root is always kept
+            grammar::Shape::Silent | grammar::Shape::Inlined if ast.is_empty() => grammar::Shape::Normal,
+            shape => shape,
+        };
+        if shape == grammar::Shape::Silent {
+            continue;
+        }
+        let rules = cfg.query_nt(state_nt).unwrap();
         let start = span.as_ptr() as usize - src.as_ptr() as usize;
-        stack.push(CallFrame::ReturnToParent(ast.len()));
+        stack.push(CallFrame::ReturnToParent(Some(ast.len()).filter(|_| shape != grammar::Shape::Inlined)));
         let stack_len = stack.len();
-        // TODO: The Trace is recording the rules directly now, searching isnt necessary
-        for rule in rules {
+        for rule_idx in rules {
+            let rule = &cfg.rules[rule_idx];
             stack.truncate(stack_len);
             // println!(
             //     "  trying rule {state:?}{:?} for span {:?}",
@@ -93,7 +118,7 @@ pub fn trace_to_ast<'c, Symbol: CfgSymbol + PartialEq>(
             //
             // great! actually pinned it down. So a goal here would be to also efficiently implement
             // ltr disambiguation. And the critical challenge is that our data is naturally
-            // asymmetrical: The information about `end`s comes from the Trace, and the paired
+            // asymmetrical: The information about `end`s comes from the completed items, and the paired
             // starts comes from the completions.
             //
             // whether that'll be an issue is to be seen! I'll have to try the implementation
@@ -101,16 +126,13 @@ pub fn trace_to_ast<'c, Symbol: CfgSymbol + PartialEq>(
             if matched_rule(
                 span,
                 start,
-                trace_slice,
-                completions,
+                &mut completed,
+                rule_idx,
                 &rule.parts,
                 &mut stack,
                 state_nt,
-                span.len(),
-                // FIXME: cloning this is definitely silly
-                reconstructed.clone(),
             ) {
-                if let Some(CallFrame::ProcessNode(new_span, _, first_child, _)) =
+                if let Some(CallFrame::ProcessNode(new_span, first_child)) =
                     stack.last().filter(|_| stack_len + 1 == stack.len())
                     && let Err(new_nt) = first_child.as_part()
                     && new_nt == state_nt
@@ -121,13 +143,26 @@ pub fn trace_to_ast<'c, Symbol: CfgSymbol + PartialEq>(
                     // I think we might be able to use the same trick from eta rules though: we can always
                     // jump through identity definitions
                 } else {
+                    if shape == grammar::Shape::Inlined {
+                        continue 'next_node;
+                    }
+                    if shape == grammar::Shape::Atomic {
+                        // The parse inside it is dropped, leaving a leaf
+                        stack.truncate(stack_len);
+                    }
                     // push nodes to ast
+                    if let Some(&parent) = open.last() {
+                        ast[parent].children += 1;
+                    }
+                    open.push(ast.len());
                     let end = start + span.len();
                     ast.push(Node {
                         transition: &rule.parts,
+                        rule: rule_idx,
+                        fields: &rule.fields,
                         start,
                         end,
-                        children: stack.len() - stack_len,
+                        children: 0,
                         transitive_children: 0,
                     });
                     continue 'next_node;
@@ -144,11 +179,18 @@ type Either<L, R> = std::result::Result<L, R>;
 /// This is a very blunt approach just to line all the types
 /// up right for making the original (u8, u32) version generic
 pub trait CfgSymbol: std::fmt::Debug {
-    type Terminal: PartialEq + std::fmt::Debug;
+    /// Ordered so the FIRST sets can be searched, see [`Cfg::nt_can_start_with`](grammar::Cfg::nt_can_start_with).
+    type Terminal: Ord + std::fmt::Debug;
     type TerminalRef<'a>: std::borrow::Borrow<Self::Terminal>
     where
         Self: 'a;
     fn as_part(&self) -> Either<Self::TerminalRef<'_>, NtSymbol>;
+    /// The character a terminal stands for, when showing rules and source text to people.
+    /// Terminals without one are shown with their `Debug` impl.
+    fn terminal_char(terminal: &Self::Terminal) -> Option<char> {
+        let _ = terminal;
+        None
+    }
 }
 impl CfgSymbol for u32 {
     type Terminal = u8;
@@ -160,30 +202,29 @@ impl CfgSymbol for u32 {
             Either::Err(*self)
         }
     }
+    fn terminal_char(terminal: &u8) -> Option<char> {
+        Some(char::from(*terminal))
+    }
 }
-type ReconstructedTraceRhs<'c, Symbol> = Option<(Vec<(usize, usize, u32, &'c [Symbol])>, usize)>;
-#[allow(clippy::too_many_arguments)]
 fn matched_rule<'a, 'c, Symbol: CfgSymbol + PartialEq>(
     mut src: &'a [Symbol::Terminal],
     offset: usize,
-    mut trace: &'a [(usize, usize, NtSymbol, &'c [Symbol])],
-    completions: &crate::completions::Completions<'c, Symbol>,
+    completed: &mut completions::CompletedIndex<'_, 'c, Symbol>,
+    rule_idx: usize,
     rule: &'c [Symbol],
     children: &mut Vec<CallFrame<'a, 'c, Symbol>>,
     parent_sym: u32,
-    parent_len: usize,
-    reconstructed: ReconstructedTraceRhs<'c, Symbol>,
 ) -> bool {
     // In the current algorithm it's possible to see a match for the RHS of a
     // rule, which is *actually* the child of another higherlevel rule which
     // led to a failed parse because it returned "too late"
     // this form of ambiguity could cause a match on a rule to fail, because
-    // the wrong path was taken. This means the reconstruction must
-    // attempt nondeterminism whenever selecting the match that we'll use for
-    // a nonterminal.
+    // the wrong path was taken. So before accepting a child, we check that the
+    // recognizer was waiting for it on behalf of this very rule, which proves
+    // that the rest of the rule matched up to where the child starts.
     //
     // This can happen even in unambiguous parses.
-    // println!("checking rule {:?} against src {offset:?} {:?} {:?} {trace:?}", rule, offset + src.len(), reconstructed);
+    let parent_len = src.len();
     let mut iter = rule.iter();
     while let Some(part) = iter.next_back() {
         match part.as_part() {
@@ -194,144 +235,32 @@ fn matched_rule<'a, 'c, Symbol: CfgSymbol + PartialEq>(
                 src = &src[..src.len() - 1];
             }
             Either::Err(sym) => {
-                let end_loc = offset + src.len();
-                // let mut tests = 0;
-                // find the last occurrence of this symbol in the trace that ends at src_index + 1
-                let Some((start, end, reconstructed)) = 
-                    (
-                        reconstructed.as_ref().and_then(|recon_trace|
-                        recon_trace.0[..recon_trace.1]
-                            .iter()
-                            .rev()
-                            .take_while(|&&(_, match_end, _, _)| end_loc <= match_end)
-                            // .inspect(|c| println!("reconstructed check: {:?}", c))
-                            .find_map(|&(start, end, s, _)| 
-                                (s == sym && end == end_loc
-                                // FIXME: This needs to work recursively again,
-                                // if a rule is left/right recursive but hidden through another rule
-                                && (s != parent_sym || (end - start) < parent_len)
-                                && {
-                                    
-                                    let range = completions.query_range(start, sym);
-                                    completions.completions[range]
-                                        .iter()
-                                        .any(|c| {
-                                            let (back_ref, sym, src_rule, rem) = c.1.clone();
-                                            back_ref ==  offset && sym == parent_sym
-                                            // The completion must cover the nodes we've parsed so far
-                                            && src_rule == rule
-                                        })
-                                }
-                            ).then(|| 
-                                {
-                                    // println!("used reconstruction :/");
-                                    (start, end, reconstructed.clone())
-                            })))
-                    ).or_else(|| {
-                        // println!("checking the actual trace?");
-                        trace
-                        .iter()
-                        .rev()
-                        .take_while(|&&(_, match_end, _, _)| end_loc <= match_end)
-                        .find_map(|&(start, end, s, _)| {
-                            ((({
-                            true
-                        }) &&
-                        (s == sym && end == end_loc
-                            // FIXME: This needs to work recursively again,
-                            // if a rule is left/right recursive but hidden through another rule
-                            && (s != parent_sym || (end - start) < parent_len)
-                            && completions
-                                .query_original_without_cache_update(start, sym)
-                                .any(|st| st.back_ref ==  offset && st.sym == parent_sym
-                                    // The completion must cover the nodes we've parsed so far
-                                    && st.remaining == &rule[iter.as_slice().len() + 1..]
-                                )
-                            )).then(|| {
-                                // println!("using it!");
-                                (start, end, None)
-                            })).or_else(|| {
-                                // FIXME: scanning completions for every trace entry is really inefficient, the trace
-                                // should probably remember the actual completions that were triggered? granted, this
-                                // is also only applicable for the right recursive rules that could be children of the
-                                // rule we're searching for.
-                                let in_bypass = end == end_loc
-                                    && completions.query_without_cache_update(start, s).any(|st| {
-                                    st.back_ref ==  offset && st.sym == parent_sym
-                                        // The completion must cover the nodes we've parsed so far
-                                        && st.remaining == &rule[iter.as_slice().len() + 1..]
-                                        && st.rule == rule
-                                });
-                                // println!("need to check bypass: {in_bypass:?} {offset:?} {parent_sym:?}");
-                                in_bypass.then(|| {
-                                    let got_it = (start, s);
-                                    // We've detected that the recognizer used the right recursion path here.
-                                    let mut reconstructed_trace = vec![];
-                                    let mut todo = vec![got_it];
-                                    while let Some((start, sym)) = todo.pop() {
-                                        // let nodes_completed_by =
-                                        // completions.query_without_cache_update(start, sym).filter(|st| {
-                                        //     st.back_ref ==  offset && st.sym == parent_sym
-                                        //         // The completion must cover the nodes we've parsed so far
-                                        //         && st.remaining == &rule[iter.as_slice().len() + 1..]
-                                        // });
-                                        let range = completions.query_range(start, sym);
-                                        let nodes_completed_by = completions.completions[range]
-                                            .iter()
-                                            .filter_map(|c| {
-                                                let (back_ref, sym, src_rule, rem) = c.1.clone();
-                                                match rem {
-                                                    crate::completions::Remaining::EmptyAndForwardingTo(start, end) => {
-                                                        completions.forwarding_records[start..end].iter()
-                                                            .any(|st| {
-                                                                st.back_ref ==  offset && st.sym == parent_sym
-                                                                    // The completion must cover the nodes we've parsed so far
-                                                                    && st.remaining == &rule[iter.as_slice().len() + 1..]
-
-                                                            }).then_some((back_ref, sym, src_rule))
-                                                    }
-                                                    _ => None,
-                                                }
-                                            });
-                                        for (back_ref, sym, src_rule) in nodes_completed_by {
-                                            reconstructed_trace.push((back_ref, end_loc, sym, src_rule));
-                                            // println!("{back_ref:?} {end_loc:?} {sym:?} {src_rule:?}");
-                                            todo.push((back_ref, sym));
-                                            // if reconstructed_trace.len() > 60 {
-                                            //     break;
-                                            // }
-                                        }
-                                        todo.sort_by_key(|a| a.0);
-                                        todo.dedup_by_key(|a| a.0);
-                                    }
-                                    reconstructed_trace.sort_by_key(|a| (-(a.0 as isize), a.2));
-                                    println!("{got_it:?} {reconstructed_trace:?} {end_loc:?} {sym:?}");
-                                    let i = reconstructed_trace.partition_point(|(_, end, _, _)| *end <= end_loc);
-                                    let &(start, _, _, _) = reconstructed_trace[..i].iter().rev().find(|(_, end, nt, _)| {
-                                        *end == end_loc && *nt == sym
-                                    }).unwrap();
-
-                                    (start, end_loc, Some((reconstructed_trace, i)))
-                                })
-                            })
-                        })
-                    })
-                else {
-                    return false;
+                let end = offset + src.len();
+                let remaining = &rule[iter.as_slice().len() + 1..];
+                // The recognizer was waiting for `sym` on behalf of this rule at these positions
+                let starts = completed.waiting(offset, rule_idx, remaining.len());
+                let mut candidates = starts.partition_point(|&start| start <= end);
+                // The shortest match is tried first
+                let start = loop {
+                    let Some(i) = candidates.checked_sub(1) else {
+                        return false;
+                    };
+                    candidates = i;
+                    let start = completed.waiting(offset, rule_idx, remaining.len())[i];
+                    // FIXME: This needs to work recursively again,
+                    // if a rule is left/right recursive but hidden through another rule
+                    if offset <= start
+                        && (sym != parent_sym || end - start < parent_len)
+                        && completed.contains(sym, start, end)
+                    {
+                        break start;
+                    }
                 };
-                // println!("accepted {:?}", start..end);
-                let i = trace.partition_point(|(_, match_end, _, _)| *match_end <= end);
-                // println!("pushing {start}..{end} for sym {sym}");
                 children.push(CallFrame::ProcessNode(
                     &src[start - offset..end - offset],
-                    &trace[..i],
                     part,
-                    reconstructed
                 ));
-                trace = &trace[..i];
                 src = &src[..start - offset];
-                let i = trace.partition_point(|(_, match_end, _, _)| *match_end <= start);
-                trace = &trace[..i];
             }
         }
     }
@@ -341,6 +270,10 @@ fn matched_rule<'a, 'c, Symbol: CfgSymbol + PartialEq>(
 pub struct Node<'c, Symbol> {
     // FIXME: Adding this lifetime is silly. switch later
     pub transition: &'c [Symbol],
+    // The index of the rule in `Cfg::rules`
+    pub rule: usize,
+    // The rule's labelled parts, see `Rule::fields`
+    pub fields: &'c [(&'static str, usize)],
     pub start: usize,
     pub end: usize,
     pub children: usize,
@@ -348,117 +281,39 @@ pub struct Node<'c, Symbol> {
     // parent: usize,
     // // next_sibling: usize,
 }
-pub fn print_ast<'a, 'c, S: CfgSymbol + PartialEq>(ast: &'a [Node<'c, S>], indent: usize) {
-    print!("{:?}", DebugIt(ast, indent));
-    struct DebugIt<'a, S: CfgSymbol>(&'a [Node<'a, S>], usize);
-    impl<S> std::fmt::Debug for DebugIt<'_, S>
-    where
-        S: CfgSymbol + PartialEq,
-    {
+// Not derived, since the symbols are only borrowed
+impl<Symbol> Clone for Node<'_, Symbol> {
+    fn clone(&self) -> Self {
+        Self { ..*self }
+    }
+}
+/// Print the tree rooted at `ast[0]`, with a line for each node giving its span and the
+/// parts of its rule, and its children indented below it.
+///
+/// The tree is printed as it is. To leave out or flatten nodes, like whitespace or the
+/// chains of a list, declare their [`Shape`](grammar::Shape) in the grammar.
+pub fn print_ast<S: CfgSymbol>(ast: &[Node<'_, S>], indent: usize) {
+    struct Parts<'a, S>(&'a [S]);
+    impl<S: CfgSymbol> std::fmt::Debug for Parts<'_, S> {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            let ast = self.0;
-            let indent = self.1;
-            let node = &ast[0];
-            for _ in 0..indent {
-                write!(f, " ")?;
-            }
-            write!(f, "- {}..{} ", node.start, node.end)?;
             let mut rule_desc = f.debug_list();
-            // Add inlining rules.
-            // let mut inlined_children = vec![];
-            let mut result_edges = vec![];
-            let mut list_terminators = vec![];
-            let mut transition = node.transition;
-            let mut rem = &ast[1..];
-            while !transition.is_empty() {
-                let part = &transition[0];
+            for part in self.0 {
                 match part.as_part() {
-                    Either::Ok(part) => {
-                        rule_desc.entry(part.borrow());
-                    }
-                    Either::Err(_sym) => {
-                        let (child, rest) = rem.split_at(1 + rem[0].transitive_children);
-                        if (transition.len() == 1)
-                            && result_edges.is_empty()
-                            && !child[0].transition.is_empty()
-                        {
-                            assert_eq!(rest.len(), 0);
-                            transition = child[0].transition;
-                            rem = &child[1..];
-                            continue;
-                        } else if child[0].transition.last() == Some(part) && rest.is_empty() {
-                            if !transition.is_empty() {
-                                list_terminators.push(&transition[1..]);
-                            }
-                            transition = child[0].transition;
-                            rem = &child[1..];
-                            continue;
-                        } else if child[..child.len() - 1]
-                            .iter()
-                            .all(|node| node.children == 1)
-                        // child.len() == 1 || child.len() == 0
-                        {
-                            let mut js = vec![0; child.len() - 1];
-                            for (i, jo) in js.iter_mut().enumerate() {
-                                let leaf = &child[i];
-                                let first = match leaf.transition[0].as_part() {
-                                    Either::Ok(t) => t,
-                                    Either::Err(_) => {
-                                        *jo = 1;
-                                        continue;
-                                    }
-                                };
-                                rule_desc.entry(&format_args!("^{:?}", first.borrow()));
-                                for i in 1..leaf.transition.len() {
-                                    let term = match leaf.transition[i].as_part() {
-                                        Either::Ok(t) => t,
-                                        Either::Err(_) => {
-                                            *jo = i + 1;
-                                            continue;
-                                        }
-                                    };
-                                    rule_desc.entry(&term.borrow());
-                                }
-                            }
-                            let leaf = &child[child.len() - 1];
-                            if leaf.transition.is_empty() {
-                                rule_desc.entry(&format_args!("^ε"));
-                            } else {
-                                let first = &leaf.transition[0];
-                                rule_desc.entry(&format_args!("^{:?}", first));
-                                for sym in &leaf.transition[1..] {
-                                    rule_desc.entry(sym);
-                                }
-                            }
-                            for i in (0..js.len()).rev() {
-                                let skip = js[i];
-                                let leaf = &child[i];
-                                for sym in &leaf.transition[skip..] {
-                                    rule_desc.entry(sym);
-                                }
-                            }
-                        } else {
-                            rule_desc.entry(part);
-                            result_edges.push(child);
-                        }
-                        rem = rest;
-                    }
-                }
-                transition = &transition[1..];
-            }
-            while let Some(terminator) = list_terminators.pop() {
-                for sym in terminator {
-                    rule_desc.entry(sym);
-                }
-            }
-            rule_desc.finish()?;
-            writeln!(f)?;
-            for emit in result_edges {
-                print_ast(emit, indent + 1);
+                    Either::Ok(terminal) => rule_desc.entry(terminal.borrow()),
+                    Either::Err(_) => rule_desc.entry(part),
+                };
             }
-            Ok(())
+            rule_desc.finish()
         }
     }
+    // (subtree, depth), so long lists don't recurse
+    let mut stack = vec![(ast, indent)];
+    while let Some((subtree, depth)) = stack.pop() {
+        let node = &subtree[0];
+        println!("{:depth$}- {}..{} {:?}", "", node.start, node.end, Parts(node.transition));
+        let children = visit::children(subtree).collect::<Vec<_>>();
+        stack.extend(children.into_iter().rev().map(|child| (child, depth + 1)));
+    }
 }
 type Ast<'c, Symbol> = Vec<Node<'c, Symbol>>;
 use recognizer::NtSymbol;
@@ -484,7 +339,7 @@ impl PartialEq for LabelledSymbol {
     }
 }
 impl Eq for LabelledSymbol {}
-#[derive(PartialEq)]
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
 #[repr(transparent)]
 pub struct Utf8SingleByte(u8);
 pub fn cast_buf(buf: &[u8]) -> &[Utf8SingleByte] {
@@ -508,6 +363,9 @@ impl CfgSymbol for LabelledSymbol {
             Either::Err(self.symbol)
         }
     }
+    fn terminal_char(terminal: &Utf8SingleByte) -> Option<char> {
+        Some(char::from(terminal.0))
+    }
 }
 impl std::fmt::Debug for LabelledSymbol {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -0,0 +1,396 @@
+//! An Earley recognizer over the states of an LR(0) automaton, after Aycock and Horspool's
+//! "Practical Earley Parsing".
+//!
+//! Instead of an item for each rule and dot, the Earley sets hold `(state, origin)` pairs,
+//! where `state` is a set of LR(0) items that always turn up together. Like in their
+//! paper each state is split in two: the kernel items that were advanced into it, and the
+//! items that they predict, which start wherever the kernel is entered. Nullable
+//! nonterminals are skipped inside the states, so they're never completed during the parse.
+//!
+//! The chart is written out as the usual [`Completions`], so trees are extracted from it
+//! with [`trace_to_ast`](crate::trace_to_ast) like any other parse.
+//!
+//! ```
+//! let (cfg, _) = cfg_toy::cfg! {
+//!     sum num digit;
+//!     sum ::= sum "+" num .
+//!     sum ::= num .
+//!     num ::= digit num .
+//!     num ::= digit .
+//!     digit ::= "1" .
+//!     digit ::= "2" .
+//! };
+//! let automaton = cfg_toy::lr0::Lr0Automaton::new(&cfg, 256);
+//! let src = b"12+2+121";
+//! let completions = automaton.parse(src, ());
+//! let ast = cfg_toy::trace_to_ast(&cfg, src, &completions, &256);
+//! assert_eq!((ast[0].start, ast[0].end), (0, src.len()));
+//! ```
+use std::borrow::Borrow;
+use std::collections::HashMap;
+
+use crate::completions::Completions;
+use crate::grammar::Cfg;
+use crate::recognizer::{NtSymbol, ParseError, State, Trace, TraceAt};
+use crate::set_buffers::{StateSet, grow_set, sorted_set};
+use crate::{CfgSymbol, Either};
+
+/// An LR(0) automaton for recognizing `init_sym`, see the [module docs](self).
+#[derive(Debug)]
+pub struct Lr0Automaton<'c, Symbol> {
+    pub cfg: &'c Cfg<Symbol>,
+    pub init_sym: NtSymbol,
+    states: Vec<Lr0State<'c, Symbol>>,
+}
+#[derive(Debug)]
+struct Lr0State<'c, Symbol> {
+    // The LR(0) items, as (rule, dot), sorted
+    items: Vec<(u32, u16)>,
+    // The state that each nonterminal leads to, sorted
+    gotos: Vec<(NtSymbol, u32)>,
+    // The state that each terminal leads to, sorted by the terminal
+    scans: Vec<(&'c Symbol, u32)>,
+    // The state of the items this one predicts, which start where this one was entered
+    epsilon: Option<u32>,
+    // The nonterminal, when every item is a completed rule for it. Entering the state
+    // just completes that nonterminal, so it's on a deterministic reduction path.
+    completes: Option<NtSymbol>,
+}
+
+impl<'c, Symbol: CfgSymbol + Ord> Lr0Automaton<'c, Symbol>
+where
+    Symbol::Terminal: Ord,
+{
+    pub fn new(cfg: &'c Cfg<Symbol>, init_sym: NtSymbol) -> Self {
+        assert!(cfg.rules.len() <= u32::MAX as usize, "the grammar has too many rules");
+        assert!(cfg.rules.iter().all(|rule| rule.parts.len() < u16::MAX as usize), "a rule is too long");
+        let mut builder = Builder {
+            cfg,
+            states: vec![],
+            interned: HashMap::new(),
+            kernel: vec![],
+        };
+        // The first state only has the items predicted at the start of the input
+        let start = builder.predict(vec![init_sym]);
+        builder.intern(start);
+        // The states are built breadth first, each one is added to the end as it's found
+        let mut next = 0;
+        while next < builder.states.len() {
+            builder.build_transitions(next);
+            next += 1;
+        }
+        Self {
+            cfg,
+            init_sym,
+            states: builder.states,
+        }
+    }
+    /// The number of states in the automaton.
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+    /// Like [`try_parse`](Self::try_parse), but panics if the input doesn't match the grammar.
+    pub fn parse(&self, src: &'c [Symbol::Terminal], trace: impl Trace<'c, Symbol>) -> Completions<'c, Symbol> {
+        self.try_parse(src, trace).unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Recognize `src` as `init_sym`, building the same chart as
+    /// [`CompiledGrammar::try_parse`](crate::compiled::CompiledGrammar::try_parse).
+    pub fn try_parse(
+        &self,
+        src: &'c [Symbol::Terminal],
+        mut trace: impl Trace<'c, Symbol>,
+    ) -> Result<Completions<'c, Symbol>, ParseError> {
+        assert!(src.len() < u32::MAX as usize, "the input is too long");
+        let cfg = self.cfg;
+        let mut completions = Completions::new(cfg, src.len());
+        let mut gotos = Gotos {
+            entries: vec![],
+            index: vec![0],
+            leo: HashMap::new(),
+        };
+        // The Earley items, as `(state, origin)`
+        let mut items = vec![(0, 0)];
+        let mut next_items = vec![];
+        let mut waiting = vec![];
+        let mut seen = StateSet::default();
+        for cursor in 0..=src.len() {
+            let input_symbol = src.get(cursor);
+            let mut completions_tx = completions.add_group();
+            let mut trace = trace.at(cursor);
+            grow_set(&mut items, &mut seen, |mut batch| {
+                use crate::buffer_pair::BufferPair;
+                for i in 0..batch.read().len() {
+                    let (state_id, origin) = batch.read()[i];
+                    let state = &self.states[state_id as usize];
+                    for &(rule, dot) in &state.items {
+                        let parts = &cfg.rules[rule as usize].parts;
+                        let Some(part) = parts.get(dot as usize) else {
+                            let sym = cfg.rules[rule as usize].for_nt;
+                            trace.completed(origin as usize, sym, parts);
+                            completions_tx.complete(origin as usize, sym, rule);
+                            // Nullable nonterminals were already skipped by the items waiting on them
+                            if (origin as usize) < cursor {
+                                if let Some((target, parent_origin)) = self.leo(&mut gotos, origin, sym) {
+                                    self.enter(target, parent_origin, cursor, batch.write());
+                                    continue;
+                                }
+                                for &(_, target, parent_origin) in gotos.get(origin, sym) {
+                                    self.enter(target, parent_origin, cursor, batch.write());
+                                }
+                            }
+                            continue;
+                        };
+                        if let Either::Err(nt) = part.as_part() {
+                            waiting.push((nt, State::new(rule as usize, dot as usize + 1, origin as usize)));
+                        }
+                    }
+                    gotos.entries.extend(state.gotos.iter().map(|&(nt, target)| (nt, target, origin)));
+                    let Some(input_symbol) = input_symbol else {
+                        continue;
+                    };
+                    let terminal = |scan: &(&Symbol, u32)| match scan.0.as_part() {
+                        Either::Ok(terminal) => terminal.borrow().cmp(input_symbol),
+                        Either::Err(_) => unreachable!("scans are for terminals"),
+                    };
+                    let scan = state.scans.partition_point(|scan| terminal(scan).is_lt());
+                    if let Some(&(_, target)) = state.scans.get(scan).filter(|scan| terminal(scan).is_eq()) {
+                        self.enter(target, origin, cursor + 1, &mut next_items);
+                    }
+                }
+            });
+            // The same item can be in more than one state
+            sorted_set(&mut waiting);
+            for (nt, state) in waiting.drain(..) {
+                completions_tx.push(nt, state);
+            }
+            drop(completions_tx);
+            gotos.close_group();
+
+            sorted_set(&mut next_items);
+            std::mem::swap(&mut items, &mut next_items);
+            next_items.clear();
+            if cursor < src.len() && items.is_empty() {
+                return Err(ParseError { position: cursor });
+            }
+        }
+        let accepted = completions
+            .completed_ending_at(src.len())
+            .iter()
+            .any(|&(sym, back_ref, _)| sym == self.init_sym && back_ref == 0);
+        if !accepted {
+            return Err(ParseError { position: src.len() });
+        }
+        Ok(completions)
+    }
+    // The item at the top of the deterministic reduction path from completing `sym` from
+    // `origin`, as in Leo's optimisation. Right recursion would otherwise complete every
+    // item on the path again each time the innermost one completes. The path is followed
+    // once, and every position on it remembers the top.
+    //
+    // The items skipped over aren't recorded as completed, like with
+    // `Completions::query`, so `CompletedIndex` finds them from the waiting items.
+    fn leo(&self, gotos: &mut Gotos, origin: u32, sym: NtSymbol) -> Option<(u32, u32)> {
+        let mut path = vec![];
+        let mut top = None;
+        let (mut origin, mut sym) = (origin, sym);
+        loop {
+            if let Some(&known) = gotos.leo.get(&(origin, sym)) {
+                top = known.or(top);
+                break;
+            }
+            let &[(_, target, parent_origin)] = gotos.get(origin, sym) else {
+                gotos.leo.insert((origin, sym), None);
+                break;
+            };
+            let Some(completes) = self.states[target as usize].completes else {
+                gotos.leo.insert((origin, sym), None);
+                break;
+            };
+            path.push((origin, sym));
+            top = Some((target, parent_origin));
+            (origin, sym) = (parent_origin, completes);
+        }
+        for key in path {
+            gotos.leo.insert(key, top);
+        }
+        top
+    }
+    // Enter the kernel `state` at `position`, along with the items it predicts
+    fn enter(&self, state: u32, origin: u32, position: usize, items: &mut Vec<(u32, u32)>) {
+        items.push((state, origin));
+        if let Some(epsilon) = self.states[state as usize].epsilon {
+            items.push((epsilon, position as u32));
+        }
+    }
+}
+
+// Where each item in the earlier sets goes once a nonterminal is completed, as
+// `(nt, state, origin)`, grouped by position and sorted like `Completions`
+struct Gotos {
+    entries: Vec<(NtSymbol, u32, u32)>,
+    index: Vec<usize>,
+    // The tops of deterministic reduction paths by `(origin, sym)`, see `Lr0Automaton::leo`
+    leo: HashMap<(u32, NtSymbol), Option<(u32, u32)>>,
+}
+impl Gotos {
+    fn get(&self, origin: u32, sym: NtSymbol) -> &[(NtSymbol, u32, u32)] {
+        let group = &self.entries[self.index[origin as usize]..self.index[origin as usize + 1]];
+        let start = group.partition_point(|g| g.0 < sym);
+        let end = start + group[start..].partition_point(|g| g.0 == sym);
+        &group[start..end]
+    }
+    fn close_group(&mut self) {
+        let start = *self.index.last().unwrap();
+        // Different items can go to the same place
+        self.entries[start..].sort();
+        let len = start
+            + crate::set_buffers::slice_retain_with_context(&mut self.entries[start..], |cx, entry| {
+                cx.last() != Some(entry)
+            });
+        self.entries.truncate(len);
+        self.index.push(self.entries.len());
+    }
+}
+
+struct Builder<'c, Symbol> {
+    cfg: &'c Cfg<Symbol>,
+    states: Vec<Lr0State<'c, Symbol>>,
+    interned: HashMap<Vec<(u32, u16)>, u32>,
+    // Whether each state's `epsilon` has been worked out, which is only done once
+    // it's entered by a transition
+    kernel: Vec<bool>,
+}
+impl<'c, Symbol: CfgSymbol + Ord> Builder<'c, Symbol>
+where
+    Symbol::Terminal: Ord,
+{
+    fn nullable(&self, part: &Symbol) -> bool {
+        match part.as_part() {
+            Either::Ok(_) => false,
+            Either::Err(nt) => self.cfg.nt_nullable.get(nt as usize).copied().unwrap_or(false),
+        }
+    }
+    // Add the items past the nullable parts after each dot, and sort them
+    fn skip_nullable(&self, mut items: Vec<(u32, u16)>) -> Vec<(u32, u16)> {
+        let mut i = 0;
+        while i < items.len() {
+            let (rule, dot) = items[i];
+            if self.cfg.rules[rule as usize].parts.get(dot as usize).is_some_and(|part| self.nullable(part)) {
+                items.push((rule, dot + 1));
+            }
+            i += 1;
+        }
+        items.sort();
+        items.dedup();
+        items
+    }
+    // The items for the rules of `nts`, and everything they predict in turn
+    fn predict(&self, mut nts: Vec<NtSymbol>) -> Vec<(u32, u16)> {
+        let cfg = self.cfg;
+        let mut items = vec![];
+        let mut i = 0;
+        while i < nts.len() {
+            let nt = nts[i];
+            i += 1;
+            let Some(rules) = cfg.query_nt(nt) else {
+                continue;
+            };
+            let start = items.len();
+            items.extend(rules.map(|rule| (rule as u32, 0)));
+            let predicted = self.skip_nullable(items.split_off(start));
+            for &(rule, dot) in &predicted {
+                if let Some(Either::Err(next)) = cfg.rules[rule as usize].parts.get(dot as usize).map(|p| p.as_part())
+                    && !nts.contains(&next)
+                {
+                    nts.push(next);
+                }
+            }
+            items.extend(predicted);
+        }
+        items.sort();
+        items.dedup();
+        items
+    }
+    fn intern(&mut self, items: Vec<(u32, u16)>) -> u32 {
+        if let Some(&id) = self.interned.get(&items) {
+            return id;
+        }
+        let id = self.states.len() as u32;
+        self.interned.insert(items.clone(), id);
+        self.kernel.push(false);
+        let cfg = self.cfg;
+        let completes = items.first().map(|&(first, _)| cfg.rules[first as usize].for_nt).filter(|&nt| {
+            items.iter().all(|&(rule, dot)| {
+                let rule = &cfg.rules[rule as usize];
+                dot as usize == rule.parts.len() && rule.for_nt == nt
+            })
+        });
+        self.states.push(Lr0State {
+            items,
+            gotos: vec![],
+            scans: vec![],
+            epsilon: None,
+            completes,
+        });
+        id
+    }
+    fn build_transitions(&mut self, id: usize) {
+        let cfg = self.cfg;
+        // The items after each transition, by the part they move over
+        let mut moves: Vec<(&'c Symbol, (u32, u16))> = self.states[id]
+            .items
+            .iter()
+            .filter_map(|&(rule, dot)| Some((cfg.rules[rule as usize].parts.get(dot as usize)?, (rule, dot + 1))))
+            .collect();
+        moves.sort_by(|a, b| part_order(a.0, b.0));
+        let mut gotos = vec![];
+        let mut scans = vec![];
+        for group in moves.chunk_by(|a, b| part_order(a.0, b.0).is_eq()) {
+            let kernel = self.skip_nullable(group.iter().map(|&(_, item)| item).collect());
+            let target = self.kernel(kernel);
+            match group[0].0.as_part() {
+                Either::Ok(_) => scans.push((group[0].0, target)),
+                Either::Err(nt) => gotos.push((nt, target)),
+            }
+        }
+        self.states[id].gotos = gotos;
+        self.states[id].scans = scans;
+    }
+    // Intern a state that's entered by a transition, and the state for what it predicts
+    fn kernel(&mut self, items: Vec<(u32, u16)>) -> u32 {
+        let cfg = self.cfg;
+        let id = self.intern(items);
+        if !std::mem::replace(&mut self.kernel[id as usize], true) {
+            let mut nts = vec![];
+            for &(rule, dot) in &self.states[id as usize].items {
+                if let Some(Either::Err(nt)) = cfg.rules[rule as usize].parts.get(dot as usize).map(|p| p.as_part())
+                    && !nts.contains(&nt)
+                {
+                    nts.push(nt);
+                }
+            }
+            let predicted = self.predict(nts);
+            if !predicted.is_empty() {
+                let epsilon = self.intern(predicted);
+                self.states[id as usize].epsilon = Some(epsilon);
+            }
+        }
+        id
+    }
+}
+
+// Terminals first, then nonterminals
+fn part_order<Symbol: CfgSymbol>(a: &Symbol, b: &Symbol) -> std::cmp::Ordering
+where
+    Symbol::Terminal: Ord,
+{
+    match (a.as_part(), b.as_part()) {
+        (Either::Ok(a), Either::Ok(b)) => a.borrow().cmp(b.borrow()),
+        (Either::Err(a), Either::Err(b)) => a.cmp(&b),
+        (Either::Ok(_), Either::Err(_)) => std::cmp::Ordering::Less,
+        (Either::Err(_), Either::Ok(_)) => std::cmp::Ordering::Greater,
+    }
+}
@@ -0,0 +1,254 @@
+//! Tree queries, like tree-sitter's.
+//!
+//! A query is a list of S-expression patterns, compiled against the names of a grammar's
+//! nonterminals and then run over an AST:
+//!
+//! ```text
+//! ; every member with an object value
+//! (member key: (string) @key value: (value (object)) @value)
+//! ```
+//!
+//! `(name ...)` matches a node for the nonterminal `name`, and `(_ ...)` matches any node.
+//! The patterns inside it have to match children of the node, in order but not
+//! necessarily adjacent, unless they're labelled with a field (`key: ...`), in which case
+//! they match the child for that part of the rule (see [`Rule::fields`](crate::grammar::Rule::fields)).
+//! `@name` after a pattern captures the node it matched. `;` starts a comment.
+use crate::grammar::Cfg;
+use crate::lexer::{Lexer, SyntaxError};
+use crate::recognizer::NtSymbol;
+use crate::{CfgSymbol, Node};
+
+/// A query failed to compile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryError {
+    /// The byte offset in the query's source
+    pub offset: usize,
+    pub message: String,
+}
+impl std::fmt::Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at offset {}", self.message, self.offset)
+    }
+}
+impl std::error::Error for QueryError {}
+impl SyntaxError for QueryError {
+    fn new(offset: usize, message: String) -> Self {
+        Self { offset, message }
+    }
+}
+
+#[derive(Debug)]
+pub struct Query {
+    patterns: Vec<Pattern>,
+    capture_names: Vec<String>,
+    // The nonterminal of each rule, so matching only needs the tree
+    rule_nt: Vec<NtSymbol>,
+}
+#[derive(Debug)]
+struct Pattern {
+    /// `None` for the wildcard
+    nt: Option<NtSymbol>,
+    fields: Vec<(String, Pattern)>,
+    children: Vec<Pattern>,
+    capture: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryMatch {
+    /// The index of the pattern in the query
+    pub pattern: usize,
+    pub captures: Vec<Capture>,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capture {
+    /// The index of the capture's name, see [`Query::capture_names`]
+    pub index: usize,
+    /// The index of the captured node in the AST
+    pub node: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Query {
+    /// Compile `source` for the grammar `cfg`, whose nonterminals from 256 upwards are
+    /// called `names` (as returned by `cfg!`).
+    pub fn new<Symbol: CfgSymbol>(cfg: &Cfg<Symbol>, names: &[&str], source: &str) -> Result<Self, QueryError> {
+        let mut compiler = Compiler {
+            cfg,
+            names,
+            lexer: Lexer::new(source),
+            capture_names: vec![],
+        };
+        let mut patterns = vec![];
+        while compiler.lexer.skip_trivia() < source.len() {
+            patterns.push(compiler.pattern()?);
+        }
+        Ok(Self {
+            patterns,
+            capture_names: compiler.capture_names,
+            rule_nt: cfg.rules.iter().map(|rule| rule.for_nt).collect(),
+        })
+    }
+    pub fn capture_names(&self) -> &[String] {
+        &self.capture_names
+    }
+    pub fn capture_index(&self, name: &str) -> Option<usize> {
+        self.capture_names.iter().position(|n| n == name)
+    }
+
+    /// Match the patterns against every node of the tree rooted at `ast[0]`.
+    ///
+    /// The matches are in pre-order of the node they matched at, and then in the order
+    /// of the patterns. Each pattern matches a node at most once.
+    pub fn matches<Symbol: CfgSymbol>(&self, ast: &[Node<'_, Symbol>]) -> Vec<QueryMatch> {
+        let mut matches = vec![];
+        let end = ast.first().map_or(0, |root| root.transitive_children + 1);
+        for i in 0..end {
+            for (pattern_idx, pattern) in self.patterns.iter().enumerate() {
+                let mut captures = vec![];
+                if self.match_pattern(pattern, ast, i, &mut captures) {
+                    matches.push(QueryMatch {
+                        pattern: pattern_idx,
+                        captures,
+                    });
+                }
+            }
+        }
+        matches
+    }
+
+    // Pushes the captures on success, and leaves `captures` as it was otherwise
+    fn match_pattern<Symbol: CfgSymbol>(
+        &self,
+        pattern: &Pattern,
+        ast: &[Node<'_, Symbol>],
+        idx: usize,
+        captures: &mut Vec<Capture>,
+    ) -> bool {
+        let node = &ast[idx];
+        if pattern.nt.is_some_and(|nt| self.rule_nt[node.rule] != nt) {
+            return false;
+        }
+        let captured = captures.len();
+        if let Some(index) = pattern.capture {
+            captures.push(Capture {
+                index,
+                node: idx,
+                start: node.start,
+                end: node.end,
+            });
+        }
+        let mut children = vec![];
+        let mut child = idx + 1;
+        for _ in 0..node.children {
+            children.push(child);
+            child += 1 + ast[child].transitive_children;
+        }
+        for (field, child_pattern) in &pattern.fields {
+            let matched = crate::visit::field_child(node, field)
+                .is_some_and(|child| self.match_pattern(child_pattern, ast, children[child], captures));
+            if !matched {
+                captures.truncate(captured);
+                return false;
+            }
+        }
+        if !self.match_children(&pattern.children, ast, &children, captures) {
+            captures.truncate(captured);
+            return false;
+        }
+        true
+    }
+    // Match `patterns` against a subsequence of `children`, backtracking on failure
+    fn match_children<Symbol: CfgSymbol>(
+        &self,
+        patterns: &[Pattern],
+        ast: &[Node<'_, Symbol>],
+        children: &[usize],
+        captures: &mut Vec<Capture>,
+    ) -> bool {
+        let Some((first, rest)) = patterns.split_first() else {
+            return true;
+        };
+        for (i, &child) in children.iter().enumerate() {
+            let captured = captures.len();
+            if self.match_pattern(first, ast, child, captures) {
+                if self.match_children(rest, ast, &children[i + 1..], captures) {
+                    return true;
+                }
+                captures.truncate(captured);
+            }
+        }
+        false
+    }
+}
+
+struct Compiler<'a, Symbol> {
+    cfg: &'a Cfg<Symbol>,
+    names: &'a [&'a str],
+    lexer: Lexer<'a>,
+    capture_names: Vec<String>,
+}
+impl<'a, Symbol: CfgSymbol> Compiler<'a, Symbol> {
+    fn pattern(&mut self) -> Result<Pattern, QueryError> {
+        let start = self.lexer.skip_trivia();
+        if !self.lexer.eat("(") {
+            return self.lexer.error(start, "expected `(`");
+        }
+        let name_pos = self.lexer.skip_trivia();
+        let Some(name) = self.lexer.identifier() else {
+            return self.lexer.error(name_pos, "expected the name of a nonterminal");
+        };
+        let nt = if name == "_" {
+            None
+        } else {
+            match self.names.iter().position(|&n| n == name) {
+                Some(idx) => Some(256 + idx as NtSymbol),
+                None => return self.lexer.error(name_pos, format!("there's no nonterminal named `{name}`")),
+            }
+        };
+        let mut pattern = Pattern {
+            nt,
+            fields: vec![],
+            children: vec![],
+            capture: None,
+        };
+        while !self.lexer.eat(")") {
+            let child_pos = self.lexer.skip_trivia();
+            if self.lexer.rest().is_empty() {
+                return self.lexer.error(start, "unclosed `(`");
+            }
+            if self.lexer.rest().starts_with('(') {
+                pattern.children.push(self.pattern()?);
+                continue;
+            }
+            let Some(field) = self.lexer.identifier().map(str::to_string) else {
+                return self.lexer.error(child_pos, "expected a pattern or a field");
+            };
+            if !self.lexer.eat(":") {
+                return self.lexer.error(self.lexer.pos, "expected `:` after the field name");
+            }
+            if let Some(nt) = nt
+                && !(self.cfg.query_nt(nt).into_iter().flatten()).any(|rule| self.cfg.rules[rule].field(&field).is_some())
+            {
+                return self.lexer.error(child_pos, format!("`{name}` has no field `{field}`"));
+            }
+            let child = self.pattern()?;
+            pattern.fields.push((field, child));
+        }
+        if self.lexer.eat("@") {
+            let capture_pos = self.lexer.pos;
+            let Some(capture) = self.lexer.identifier().map(str::to_string) else {
+                return self.lexer.error(capture_pos, "expected the name of the capture");
+            };
+            let index = match self.capture_names.iter().position(|c| *c == capture) {
+                Some(index) => index,
+                None => {
+                    self.capture_names.push(capture);
+                    self.capture_names.len() - 1
+                }
+            };
+            pattern.capture = Some(index);
+        }
+        Ok(pattern)
+    }
+}
@@ -2,7 +2,7 @@ use std::borrow::Borrow;
 
 use crate::buffer_pair::{BufferPair, Transfer};
 use crate::completions::{Completions, CompletionsTransaction};
-use crate::set_buffers::{grow_ordered_set, isolate_new_elements, sorted_set};
+use crate::set_buffers::{StateSet, grow_set, sorted_set};
 
 pub trait TraceAt<'a, Symbol> {
     fn completed(&mut self, back_ref: usize, sym: NtSymbol, rule: &'a [Symbol]);
@@ -24,218 +24,336 @@ impl<'a, Symbol> TraceAt<'a, Symbol> for () {
 
 // type Symbol = u32;
 pub(crate) type NtSymbol = u32;
-// TODO: can switch this to encoding the sym id into the slice.
-// the standard presentation is that these store (Rule, rule_offset)
 
-#[derive(Copy, Debug, PartialOrd, Ord, PartialEq, Eq)]
-pub struct State<'a, Symbol> {
-    pub back_ref: usize,
-    pub sym: NtSymbol,
-    pub rule: &'a [Symbol],
-    pub remaining: &'a [Symbol],
+/// An Earley item: the rule with index `rule` in `Cfg::rules`, matched up to `dot` from `origin`.
+///
+/// It's packed into 12 bytes, since the states are sorted and deduplicated over and over.
+/// The nonterminal and the remaining parts are looked up in the grammar.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct State {
+    pub rule: u32,
+    pub dot: u16,
+    pub origin: u32,
 }
-impl<'a, Symbol> Clone for State<'a, Symbol> {
-    fn clone(&self) -> Self {
+impl State {
+    pub fn new(rule: usize, dot: usize, origin: usize) -> Self {
         Self {
-            back_ref: self.back_ref,
-            sym: self.sym,
-            rule: self.rule,
-            remaining: self.remaining,
+            rule: rule as u32,
+            dot: dot as u16,
+            origin: origin as u32,
         }
     }
-}
-fn mk_state<'a, Symbol>(back_ref: usize, sym: NtSymbol, rule: &'a [Symbol], remaining: &'a [Symbol]) -> State<'a, Symbol> {
-    State {
-        back_ref,
-        sym,
-        rule,
-        remaining,
+    pub fn sym<Symbol>(self, cfg: &crate::grammar::Cfg<Symbol>) -> NtSymbol {
+        cfg.rules[self.rule as usize].for_nt
+    }
+    /// All of the parts of the rule.
+    pub fn parts<Symbol>(self, cfg: &crate::grammar::Cfg<Symbol>) -> &[Symbol] {
+        &cfg.rules[self.rule as usize].parts
+    }
+    /// The parts of the rule after the dot.
+    pub fn remaining<Symbol>(self, cfg: &crate::grammar::Cfg<Symbol>) -> &[Symbol] {
+        &self.parts(cfg)[self.dot as usize..]
+    }
+    /// The state after matching the next part.
+    pub fn advance(self) -> Self {
+        Self { dot: self.dot + 1, ..self }
+    }
+    /// Whether the state could still be going once `terminal` is next, going by the
+    /// grammar's FIRST sets. This is only an estimate past a nullable nonterminal.
+    pub fn can_continue_with<Symbol: super::CfgSymbol>(
+        self,
+        cfg: &crate::grammar::Cfg<Symbol>,
+        terminal: &Symbol::Terminal,
+    ) -> bool {
+        match self.remaining(cfg).first().map(|part| part.as_part()) {
+            None => true,
+            Some(super::Either::Ok(part)) => part.borrow() == terminal,
+            Some(super::Either::Err(nt)) => {
+                cfg.nt_is_nullable(nt) || cfg.nt_can_start_with(nt, terminal)
+            }
+        }
     }
 }
 
-struct EarleyStep<'c, 'r, T, Symbol: Ord + super::CfgSymbol> {
+struct EarleyStep<'c, 's, 'r, T, Symbol: Ord + super::CfgSymbol> {
     cfg: &'c crate::grammar::Cfg<Symbol>,
     // TODO(opts): Try making this `u8` instead of `&u8` while parsing a normal buffer
-    input_symbol: &'c Symbol::Terminal,
+    input_symbol: &'s Symbol::Terminal,
     completions_tx: CompletionsTransaction<'c, 'r, Symbol>,
-    next_states: Vec<State<'c, Symbol>>,
+    next_states: Vec<State>,
     trace: T,
 }
+/// The input can't be parsed: no parse could continue past `position`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseError {
+    pub position: usize,
+}
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "no parse could continue at position {}", self.position)
+    }
+}
+impl std::error::Error for ParseError {}
+
+/// Like [`try_parse_earley`], but panics if the input doesn't match the grammar.
 pub fn parse_earley<'c, Symbol: super::CfgSymbol + Ord>(
     cfg: &'c crate::grammar::Cfg<Symbol>,
     src: &'c [Symbol::Terminal],
     init_sym: u32,
-    mut trace: impl Trace<'c, Symbol>,
+    trace: impl Trace<'c, Symbol>,
 ) -> Completions<'c, Symbol> {
-    // println!("initial states: {:?}", states);
-    // This is kept between iterations for double buffering to
-    // save on allocating it.
-    let mut next_states = vec![];
-
-    // TODO: The completions should sorta have a GC pass. Especially for longer files,
-    // most of its content is completely unreferenced.
-    // Currently using binary search maps due to the need for range queries, it'd be totally sensible
-    // to revisit that
-    let mut completions = Completions::new(src.len());
+    try_parse_earley(cfg, src, init_sym, trace).unwrap_or_else(|e| panic!("{e}"))
+}
+pub fn try_parse_earley<'c, Symbol: super::CfgSymbol + Ord>(
+    cfg: &'c crate::grammar::Cfg<Symbol>,
+    src: &'c [Symbol::Terminal],
+    init_sym: u32,
+    trace: impl Trace<'c, Symbol>,
+) -> Result<Completions<'c, Symbol>, ParseError> {
+    let mut session = ParserSession::new(cfg);
+    session.try_parse(src, init_sym, trace)?;
+    Ok(session.into_completions())
+}
 
-    let mut states = cfg
-        .query_nt(init_sym)
-        .unwrap()
-        // .filter(|i| !cfg.rule_nullable[*i])
-        .map(|i| &cfg.rules[i])
-        .filter(|r| !r.parts.is_empty())
-        .map(|r| mk_state(0, init_sym, &r.parts[..], &r.parts[..]))
-        .collect::<Vec<_>>();
-    // println!("wut {:?}", cfg.query_nullable(init_sym).unwrap());
-    // println!("wut {:?}", cfg
-    //     .query_nt(init_sym)
-    //     .unwrap()
-    //     // .filter(|i| !cfg.rule_nullable[*i])
-    //     .map(|i| &cfg.rules[i]).collect::<Vec<_>>());
-    // println!("{cfg:?}");
-    for &rule in &cfg.nt_to_nullable_rules_index[cfg.query_nullable(init_sym).unwrap()] {
-        trace.at(0).completed(0, init_sym, &cfg.rules[rule].parts);
+/// Everything [`try_parse_earley`] allocates, kept to parse any number of inputs with
+/// the same grammar. Once the buffers have grown to fit the inputs, parsing another one
+/// doesn't allocate at all.
+///
+/// ```
+/// let (cfg, _) = cfg_toy::cfg! {
+///     line word;
+///     line ::= word " " line .
+///     line ::= word .
+///     word ::= "a" word .
+///     word ::= "a" .
+/// };
+/// let mut session = cfg_toy::recognizer::ParserSession::new(&cfg);
+/// for src in ["a aa", "aaa", "a a a"] {
+///     let completions = session.parse(src.as_bytes(), 256, ());
+///     let ast = cfg_toy::trace_to_ast(&cfg, src.as_bytes(), completions, &256);
+///     assert_eq!(ast[0].end, src.len());
+/// }
+/// assert!(session.try_parse(b"a  a", 256, ()).is_err());
+/// ```
+#[derive(Debug)]
+pub struct ParserSession<'c, Symbol> {
+    cfg: &'c crate::grammar::Cfg<Symbol>,
+    completions: Completions<'c, Symbol>,
+    // The states at the cursor, and the ones scanned into the next position
+    states: Vec<State>,
+    next_states: Vec<State>,
+    // The states generated at the cursor, for `grow_set`
+    new_states: Vec<State>,
+    seen: StateSet<State>,
+    // For following the waiting items up to the start symbol at the end
+    accept_todo: Vec<(NtSymbol, usize)>,
+    accept_seen: StateSet<(NtSymbol, usize)>,
+}
+impl<'c, Symbol: super::CfgSymbol + Ord> ParserSession<'c, Symbol> {
+    pub fn new(cfg: &'c crate::grammar::Cfg<Symbol>) -> Self {
+        Self {
+            cfg,
+            completions: Completions::new(cfg, 0),
+            states: vec![],
+            next_states: vec![],
+            new_states: vec![],
+            seen: StateSet::default(),
+            accept_todo: vec![],
+            accept_seen: StateSet::default(),
+        }
     }
-
-    for (cursor, input_symbol) in src.iter().enumerate() {
-        // println!("{cursor}@{states:?}");
-        let mut step = EarleyStep {
+    /// Like [`try_parse`](Self::try_parse), but panics if the input doesn't match the grammar.
+    pub fn parse(
+        &mut self,
+        src: &[Symbol::Terminal],
+        init_sym: u32,
+        trace: impl Trace<'c, Symbol>,
+    ) -> &Completions<'c, Symbol> {
+        self.try_parse(src, init_sym, trace).unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Recognize `src` as `init_sym`, like [`try_parse_earley`]. The chart is kept in the
+    /// session until the next parse.
+    pub fn try_parse(
+        &mut self,
+        src: &[Symbol::Terminal],
+        init_sym: u32,
+        trace: impl Trace<'c, Symbol>,
+    ) -> Result<&Completions<'c, Symbol>, ParseError> {
+        self.recognize(src, init_sym, trace)?;
+        Ok(&self.completions)
+    }
+    /// The chart from the last parse, or an empty one if there wasn't any.
+    pub fn into_completions(self) -> Completions<'c, Symbol> {
+        self.completions
+    }
+    fn recognize(
+        &mut self,
+        src: &[Symbol::Terminal],
+        init_sym: u32,
+        mut trace: impl Trace<'c, Symbol>,
+    ) -> Result<(), ParseError> {
+        let Self {
             cfg,
-            input_symbol,
-            // The states for the next character get accumulated here, they'll need to be deduplicated
-            // before we actually process the next character
+            completions,
+            states,
             next_states,
-            // If any state transition is a prediction, we remember the completion for it to use later
-            completions_tx: completions.add_group(),
-            trace: trace.at(cursor),
-        };
-        // As we expand the states, we'll generate more states that need to be processed.
-        // we keep track of all generated states here to deduplicate them
-        let mut transfer = Transfer {
-            states: &states,
-            new_states: vec![],
-        };
-        // To optimize deduplicating the new states, we deduplicate in batches, so that nothing
-        // before the current pass needs to be checked again.
-        let states_before_pass = transfer.new_states.len();
-        // First we transfer out of the states from the last character.
-        step.expand_states(&mut transfer);
-        let mut new_states = transfer.new_states;
+            new_states,
+            seen,
+            accept_todo,
+            accept_seen,
+        } = self;
+        let cfg = *cfg;
+        // The chart keeps everything, `Completions::prune` drops what no parse uses
+        // afterwards, and `bounded` recognizes without keeping it around.
+        completions.reset(src.len());
+        // The states are packed, see `State`
+        assert!(src.len() < u32::MAX as usize, "the input is too long");
+        assert!(cfg.rules.len() <= u32::MAX as usize, "the grammar has too many rules");
+        assert!(cfg.rules.iter().all(|rule| rule.parts.len() < u16::MAX as usize), "a rule is too long");
 
-        isolate_new_elements(&mut new_states, states_before_pass);
-        grow_ordered_set(&mut new_states, |states| {
-            step.expand_states(states);
-        });
-        // TODO: we can improve the representation of states to encode the rule + nonterminal much more efficiently:
-        // The remaining data should be stored as an offset into the "rule data", where it's held as a null terminated
-        // string. These offsets can then use the high bits to encode the nonterminal and a rule id.
-        // These rule ids can be prepared ahead of time to deduplicate identical suffixes, so that A ::= B C. and A :: C D.
-        // would be represented by offset = rule_1_idx and offset = rule_2_idx, which would differ only in the bitmask
-        // for the rule they're from, so the deduplication step can merge them to the version of the rule that completes
-        // as rule_1 *and* rule_2.
-        sorted_set(&mut step.next_states);
+        states.clear();
+        states.extend(
+            cfg.query_nt(init_sym)
+                .unwrap()
+                // .filter(|i| !cfg.rule_nullable[*i])
+                .filter(|&i| !cfg.rules[i].parts.is_empty())
+                .map(|i| State::new(i, 0, 0)),
+        );
+        for &rule in &cfg.nt_to_nullable_rules_index[cfg.query_nullable(init_sym).unwrap()] {
+            trace.at(0).completed(0, init_sym, &cfg.rules[rule].parts);
+            // This lands in the group for position 0 once it's opened
+            completions.completed.push((init_sym, 0, rule as u32));
+        }
 
-        let mut used_up_states = std::mem::replace(&mut states, step.next_states);
-        used_up_states.clear();
-        next_states = used_up_states;
-        if states.is_empty() {
-            panic!("no states left at cursor {}", cursor);
+        for (cursor, input_symbol) in src.iter().enumerate() {
+            // println!("{cursor}@{states:?}");
+            let mut step = EarleyStep {
+                cfg,
+                input_symbol,
+                // The states for the next character get accumulated here, they'll need to be deduplicated
+                // before we actually process the next character
+                next_states: std::mem::take(next_states),
+                // If any state transition is a prediction, we remember the completion for it to use later
+                completions_tx: completions.add_group(),
+                trace: trace.at(cursor),
+            };
+            // As we expand the states, we'll generate more states that need to be processed.
+            // we keep track of all generated states here to deduplicate them
+            let mut transfer = Transfer {
+                states: &*states,
+                new_states: std::mem::take(new_states),
+            };
+            // First we transfer out of the states from the last character.
+            step.expand_states(&mut transfer);
+            *new_states = transfer.new_states;
+
+            // Then the new states are expanded until no more turn up, each of them once
+            grow_set(new_states, seen, |states| {
+                step.expand_states(states);
+            });
+            new_states.clear();
+            // TODO: The rules could be prepared ahead of time to deduplicate identical suffixes, so that
+            // A ::= B C. and A :: C D. share their states after the dot, and the deduplication step can
+            // merge them to a state that completes as rule_1 *and* rule_2.
+            sorted_set(&mut step.next_states);
+            // Only the states that can go on with the next symbol are worth carrying over. If none
+            // can, there's no parse past the next position, just like before they were dropped.
+            let mut stuck = None;
+            if let Some(lookahead) = src.get(cursor + 1)
+                && !step.next_states.is_empty()
+            {
+                step.next_states.retain(|state| state.can_continue_with(cfg, lookahead));
+                if step.next_states.is_empty() {
+                    stuck = Some(cursor + 1);
+                }
+            }
+
+            // The used up states are kept for double buffering
+            std::mem::swap(states, &mut step.next_states);
+            step.next_states.clear();
+            *next_states = step.next_states;
+            if let Some(position) = stuck {
+                return Err(ParseError { position });
+            }
+            if states.is_empty() {
+                return Err(ParseError { position: cursor });
+            }
         }
-    }
-    let mut completions_tx = completions.add_group();
+        let mut completions_tx = completions.add_group();
 
-    grow_ordered_set(&mut states, |mut states| {
-        for i in 0..states.read().len() {
-            let state = states.read()[i].clone();
-            if state.remaining.is_empty() {
-                // This state has recognized its nontermininal starting at state.back_ref
-                trace.at(src.len()).completed(state.back_ref, state.sym, state.rule);
-                // println!("completed state report: {:?}", state);
-                states
-                    .write()
-                    .extend(completions_tx.query(state.back_ref, state.sym)
-                    // .inspect(|c| println!("have completion {c:?}"))
-                );
-                continue;
-            } else {
-                let sym = state.remaining.first().unwrap();
+        grow_set(states, seen, |mut states| {
+            for i in 0..states.read().len() {
+                let state = states.read()[i];
+                let Some(sym) = state.remaining(cfg).first() else {
+                    // This state has recognized its nontermininal starting at state.origin
+                    let (back_ref, sym, rule) = (state.origin as usize, state.sym(cfg), state.parts(cfg));
+                    trace.at(src.len()).completed(back_ref, sym, rule);
+                    completions_tx.complete(back_ref, sym, state.rule);
+                    // println!("completed state report: {:?}", state);
+                    states
+                        .write()
+                        .extend(completions_tx.query(back_ref, sym)
+                        // .inspect(|c| println!("have completion {c:?}"))
+                    );
+                    continue;
+                };
                 match sym.as_part() {
                     super::Either::Ok(_) => (),
                     super::Either::Err(nt) => {
                         // Synthesize a completion that'll never be used,
                         // we still need to indicate that ws is a valid child for us
-                        completions_tx.push(
-                            nt,
-                            mk_state(state.back_ref, state.sym,
-                                state.rule, &state.remaining[1..]),
-                        );
-                        // FIXME: transitive please
-                        let can_skip = cfg.rules_for(nt).any(|rule| rule.parts.is_empty());
-                        if can_skip {
-                            trace.at(src.len()).completed(src.len(), nt, &[]);
-                            states.write().push(mk_state(
-                                state.back_ref,
-                                state.sym,
-                                state.rule,
-                                &state.remaining[1..],
-                            ))
+                        completions_tx.push(nt, state.advance());
+                        if cfg.nt_is_nullable(nt) {
+                            // The nonterminals inside its empty matches are completed (and
+                            // waited for) too, so `trace_to_ast` finds the children of the rules
+                            let mut todo = vec![nt];
+                            let mut visited = vec![nt];
+                            while let Some(nt) = todo.pop() {
+                                for &rule in &cfg.nt_to_nullable_rules_index[cfg.query_nullable(nt).unwrap()] {
+                                    let parts = &cfg.rules[rule].parts;
+                                    trace.at(src.len()).completed(src.len(), nt, parts);
+                                    completions_tx.complete(src.len(), nt, rule as u32);
+                                    for (dot, part) in parts.iter().enumerate() {
+                                        let Err(child) = part.as_part() else {
+                                            unreachable!("a nullable rule only has nonterminals")
+                                        };
+                                        completions_tx.push(child, State::new(rule, dot, src.len()).advance());
+                                        if !visited.contains(&child) {
+                                            visited.push(child);
+                                            todo.push(child);
+                                        }
+                                    }
+                                }
+                            }
+                            states.write().push(state.advance())
                         }
                     }
                 }
             }
+        });
+        drop(completions_tx);
+        // The input may have run out partway through the rule we were after
+        if !completions.recognized(init_sym, 0, src.len(), accept_todo, accept_seen) {
+            return Err(ParseError { position: src.len() });
         }
-    });
-    drop(completions_tx);
-    // the match state is (back_ref: 0, sym: 256), so will always be at the start
-    // println!("final states: {:?}", states);
-    assert_eq!(
-        states.first().map(|s| (s.back_ref, s.sym)),
-        Some((0, init_sym))
-    );
-    completions
-}
-
-struct PrintRemainingList<'a, Symbol>(
-    &'a [(u32, (usize, u32, &'a [Symbol], crate::completions::Remaining<'a, Symbol>))],
-    &'a [crate::recognizer::State<'a, Symbol>],
-);
-impl<Symbol: core::fmt::Debug> core::fmt::Debug for PrintRemainingList<'_, Symbol> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut list = f.debug_list();
-        for &(ntsym, (back_ref, sym_here, rule, ref rem)) in self.0 {
-            match rem {
-                crate::completions::Remaining::EmptyAndForwardingTo(start, end) => {
-                    list.entry(&format_args!(
-                        "\n  ({}, ({}, {}, {:?}, bypass[{}..{}]({:?})))",
-                        ntsym,
-                        back_ref,
-                        sym_here,
-                        rule,
-                        start,
-                        end,
-                        &self.1[*start..*end]
-                    ));
-                }
-                crate::completions::Remaining::More(syms) => {
-                    list.entry(&format_args!("\n  {:?}", (ntsym, (back_ref, sym_here, rule, syms))));
-                }
-            }
-        }
-        list.finish()
+        Ok(())
     }
 }
-impl<'c, T: TraceAt<'c, Symbol>, Symbol: super::CfgSymbol + Ord> EarleyStep<'c, '_, T, Symbol> {
-    fn expand_states(&mut self, mut transfer: impl BufferPair<State<'c, Symbol>>) {
+
+impl<'c, T: TraceAt<'c, Symbol>, Symbol: super::CfgSymbol + Ord> EarleyStep<'c, '_, '_, T, Symbol> {
+    fn expand_states(&mut self, mut transfer: impl BufferPair<State>) {
         for i in 0..transfer.read().len() {
-            let state = transfer.read()[i].clone();
+            let state = transfer.read()[i];
             self.expand_state(state, transfer.write());
         }
     }
-    fn expand_state(&mut self, state: State<'c, Symbol>, new: &mut Vec<State<'c, Symbol>>) {
-        let Some(sym) = state.remaining.first() else {
-            // This state has recognized its nontermininal starting at state.back_ref
-            self.trace.completed(state.back_ref, state.sym, state.rule);
-            new.extend(self.completions_tx.query(state.back_ref, state.sym));
+    fn expand_state(&mut self, state: State, new: &mut Vec<State>) {
+        let Some(sym) = state.remaining(self.cfg).first() else {
+            // This state has recognized its nontermininal starting at state.origin
+            let (back_ref, sym, rule) = (state.origin as usize, state.sym(self.cfg), state.parts(self.cfg));
+            self.trace.completed(back_ref, sym, rule);
+            self.completions_tx.complete(back_ref, sym, state.rule);
+            new.extend(self.completions_tx.query(back_ref, sym));
             return;
         };
         match sym.as_part() {
@@ -246,12 +364,7 @@ impl<'c, T: TraceAt<'c, Symbol>, Symbol: super::CfgSymbol + Ord> EarleyStep<'c,
                 if self.input_symbol == sym.borrow() {
                     // println!("matches {:?}", *sym.borrow());
 
-                    self.next_states.push(mk_state(
-                        state.back_ref,
-                        state.sym,
-                        state.rule,
-                        &state.remaining[1..],
-                    ));
+                    self.next_states.push(state.advance());
                 }
             }
             super::Either::Err(nt) => {
@@ -259,11 +372,7 @@ impl<'c, T: TraceAt<'c, Symbol>, Symbol: super::CfgSymbol + Ord> EarleyStep<'c,
                 // To match a nonterminal, expand all the rules for it,
                 // and remember our state as a completion if the nonterminal successfully
                 // parses.
-                self.completions_tx.push(
-                    nt,
-                    mk_state(state.back_ref, state.sym, 
-                                state.rule,&state.remaining[1..]),
-                );
+                self.completions_tx.push(nt, state.advance());
 
                 // We are about to predict a nonterminal.
                 // When an eta rule exists for it, it would attempt to dereference a back_ref
@@ -277,7 +386,7 @@ impl<'c, T: TraceAt<'c, Symbol>, Symbol: super::CfgSymbol + Ord> EarleyStep<'c,
                 // Then the later expansions can be skipped and rely on already being
                 // performed further up.
 
-                if self.cfg.nt_nullable[nt as usize] {
+                if self.cfg.nt_is_nullable(nt) {
                     // If the nonterminal is nullable, we can also skip it directly
                     #[allow(clippy::never_loop)]
                     for &rule in &self.cfg.nt_to_nullable_rules_index[self.cfg.query_nullable(nt).unwrap()] {
@@ -287,6 +396,7 @@ impl<'c, T: TraceAt<'c, Symbol>, Symbol: super::CfgSymbol + Ord> EarleyStep<'c,
                         // println!("{:?}", &self.cfg.nt_to_nullable_rules_index[self.cfg.query_nullable(nt + 1).unwrap()].iter().map(|&i| &self.cfg.rules[i]).collect::<Vec<_>>());
                         // panic!();
                         self.trace.completed(self.completions_tx.batch_id(), nt, &self.cfg.rules[rule].parts);
+                        self.completions_tx.complete(self.completions_tx.batch_id(), nt, rule as u32);
                     }
                     // let mut visited = std::collections::HashSet::new();
                     // visited.insert(nt);
@@ -314,17 +424,22 @@ impl<'c, T: TraceAt<'c, Symbol>, Symbol: super::CfgSymbol + Ord> EarleyStep<'c,
                     //         }
                     //     }
                     // }
-                    if state.remaining.len() != 1 || state.back_ref < self.completions_tx.batch_id()
+                    if state.remaining(self.cfg).len() != 1
+                        || (state.origin as usize) < self.completions_tx.batch_id()
                     {
-                        self.expand_state(
-                            mk_state(state.back_ref, state.sym,
-                                state.rule, &state.remaining[1..]),
-                            new,
-                        );
+                        self.expand_state(state.advance(), new);
                     }
                 }
 
-                for rule in self.cfg.rules_for(nt) {
+                for rule_idx in self.cfg.query_nt(nt).unwrap() {
+                    let rule = &self.cfg.rules[rule_idx];
+                    // Rules that can't start with the input symbol would be dropped when they scan it.
+                    // The nullable ones are still needed for the empty matches.
+                    if !self.cfg.rule_nullable[rule_idx]
+                        && !self.cfg.rule_can_start_with(rule_idx, self.input_symbol)
+                    {
+                        continue;
+                    }
                     // FIXME: This also needs to be done transitively:
                     //
                     if rule.parts.is_empty() {
@@ -343,12 +458,7 @@ impl<'c, T: TraceAt<'c, Symbol>, Symbol: super::CfgSymbol + Ord> EarleyStep<'c,
                         //         new,
                         //     );
                     } else {
-                        new.push(mk_state(
-                            self.completions_tx.batch_id(),
-                            nt,
-                            &rule.parts[..],
-                            &rule.parts[..],
-                        ));
+                        new.push(State::new(rule_idx, 0, self.completions_tx.batch_id()));
                     }
                 }
             }
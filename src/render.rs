@@ -0,0 +1,154 @@
+//! Rendering trees as indented text, for logs and golden files.
+//!
+//! ```text
+//! expr ::= and_expr or expr  0..14  "true or  f…"
+//! ├── and_expr > primary ::= "true"  0..4  "true"
+//! ├── or ::= gap "or" gap  4..9  " or  "
+//! │   ├── gap > ws ::= " "  4..5  " "
+//! │   └── gap ::= ws gap  7..9  "  "
+//! │       ├── ws ::= " "  7..8  " "
+//! │       └── gap > ws ::= " "  8..9  " "
+//! └── expr > and_expr > primary ::= "false"  9..14  "false"
+//! ```
+//!
+//! This is the configurable version of [`print_ast`](crate::print_ast): it writes anywhere,
+//! and its simplifications of the tree can each be turned off.
+use std::fmt::{self, Write};
+
+use crate::codegen::{describe_rule, excerpt, nt_name};
+use crate::grammar::Cfg;
+use crate::{CfgSymbol, Node};
+
+pub struct TreeRenderer<'a, Symbol: CfgSymbol> {
+    cfg: &'a Cfg<Symbol>,
+    names: &'a [&'a str],
+    source: Option<&'a [Symbol::Terminal]>,
+    excerpt_len: usize,
+    box_drawing: bool,
+    collapse_lists: bool,
+    elide_single_children: bool,
+}
+impl<'a, Symbol: CfgSymbol> TreeRenderer<'a, Symbol> {
+    /// A renderer for trees of `cfg`, whose nonterminals from 256 upwards are called `names`.
+    ///
+    /// By default it draws boxes, and doesn't simplify the tree or show any source.
+    pub fn new(cfg: &'a Cfg<Symbol>, names: &'a [&'a str]) -> Self {
+        Self {
+            cfg,
+            names,
+            source: None,
+            excerpt_len: 40,
+            box_drawing: true,
+            collapse_lists: false,
+            elide_single_children: false,
+        }
+    }
+    /// Show the text each node was parsed from, cut off after `max_len` terminals.
+    pub fn source(mut self, src: &'a [Symbol::Terminal], max_len: usize) -> Self {
+        self.source = Some(src);
+        self.excerpt_len = max_len;
+        self
+    }
+    /// Indent with box drawing characters, or with plain spaces.
+    pub fn box_drawing(mut self, enabled: bool) -> Self {
+        self.box_drawing = enabled;
+        self
+    }
+    /// Flatten recursive lists: a child for the same nonterminal as its parent, at the
+    /// start or the end of its parent (as in `list ::= item list`), is replaced by its children.
+    pub fn collapse_lists(mut self, enabled: bool) -> Self {
+        self.collapse_lists = enabled;
+        self
+    }
+    /// Put chains of nodes that only have a single child spanning all of them on one
+    /// line, like `expr > and_expr > primary ::= "true"`.
+    pub fn elide_single_children(mut self, enabled: bool) -> Self {
+        self.elide_single_children = enabled;
+        self
+    }
+
+    /// Render the tree rooted at `ast[0]`, with a line for each node.
+    pub fn render(&self, ast: &[Node<'_, Symbol>], out: &mut impl Write) -> fmt::Result {
+        // (node, depth, whether it's the last of its siblings)
+        let mut stack = vec![(0, 0, true)];
+        // Whether the ancestors at each depth were the last of their siblings
+        let mut last = vec![];
+        while let Some((mut idx, depth, is_last)) = stack.pop() {
+            last.truncate(depth);
+            if depth > 0 {
+                for &ancestor_last in &last[1..] {
+                    out.write_str(match (self.box_drawing, ancestor_last) {
+                        (true, false) => "│   ",
+                        _ => "    ",
+                    })?;
+                }
+                out.write_str(match (self.box_drawing, is_last) {
+                    (true, false) => "├── ",
+                    (true, true) => "└── ",
+                    (false, _) => "    ",
+                })?;
+            }
+            last.push(is_last);
+
+            let mut children = self.children(ast, idx);
+            while self.elide_single_children
+                && let [child] = children[..]
+                && (ast[child].start, ast[child].end) == (ast[idx].start, ast[idx].end)
+            {
+                write!(out, "{} > ", self.name(&ast[idx]))?;
+                idx = child;
+                children = self.children(ast, idx);
+            }
+            let node = &ast[idx];
+            write!(out, "{}  {}..{}", describe_rule(self.cfg, self.names, node.rule), node.start, node.end)?;
+            if let Some(src) = self.source {
+                let text = excerpt::<Symbol>(&src[node.start..node.end], self.excerpt_len);
+                write!(out, "  {text:?}")?;
+            }
+            writeln!(out)?;
+            for (i, &child) in children.iter().enumerate().rev() {
+                stack.push((child, depth + 1, i + 1 == children.len()));
+            }
+        }
+        Ok(())
+    }
+    /// Render to an `io::Write`, like a file or stdout.
+    pub fn render_io(&self, ast: &[Node<'_, Symbol>], out: &mut impl std::io::Write) -> std::io::Result<()> {
+        out.write_all(self.render_to_string(ast).as_bytes())
+    }
+    pub fn render_to_string(&self, ast: &[Node<'_, Symbol>]) -> String {
+        let mut out = String::new();
+        self.render(ast, &mut out).unwrap();
+        out
+    }
+
+    fn name(&self, node: &Node<'_, Symbol>) -> &str {
+        nt_name(self.names, self.cfg.rules[node.rule].for_nt)
+    }
+    fn children(&self, ast: &[Node<'_, Symbol>], idx: usize) -> Vec<usize> {
+        let direct = |idx: usize| {
+            let mut children = vec![];
+            let mut child = idx + 1;
+            for _ in 0..ast[idx].children {
+                children.push(child);
+                child += 1 + ast[child].transitive_children;
+            }
+            children
+        };
+        let mut children = direct(idx);
+        if !self.collapse_lists {
+            return children;
+        }
+        let nt = self.cfg.rules[ast[idx].rule].for_nt;
+        let is_list = |child: usize| self.cfg.rules[ast[child].rule].for_nt == nt;
+        // Both ends are unrolled in loops, so long lists don't recurse
+        while let Some(&child) = children.last().filter(|&&child| is_list(child)) {
+            children.pop();
+            children.extend(direct(child));
+        }
+        while let Some(&child) = children.first().filter(|&&child| is_list(child)) {
+            children.splice(0..1, direct(child));
+        }
+        children
+    }
+}
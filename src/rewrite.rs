@@ -0,0 +1,426 @@
+//! Rewriting trees with pattern → template rules.
+//!
+//! Patterns are written like [queries](crate::query), and templates build the tree to put
+//! in place of each match out of the captured subtrees:
+//!
+//! ```text
+//! ; not not X => X
+//! (primary (not) (primary (not) (_) @x)) -> @x
+//! ```
+//!
+//! `(name p...)` matches a node for `name` whose children match `p...` exactly, in order.
+//! Without any children listed, `(name)` matches the node whatever its children are, and
+//! `(_)` matches any node. Strings pick out the rule by its terminals, like `(primary "true")`.
+//! `@name` after a pattern captures its node.
+//!
+//! In a template, `@name` is a copy of the captured subtree and `(name t...)` is a new node
+//! for `name`, made with the rule whose nonterminals are those of `t...` (and whose
+//! terminals are the strings, if there are any). Every rule is checked against the grammar
+//! when it's compiled, so a rewrite can only build trees the grammar could have parsed.
+//!
+//! This relies on the children lining up with the nonterminals in the rule, like
+//! [`child_by_field`](crate::visit::child_by_field), so it doesn't see into nodes that
+//! have been reshaped by the grammar's [`Shape`](crate::grammar::Shape)s.
+use std::borrow::Borrow;
+
+use crate::codegen::nt_name;
+use crate::grammar::Cfg;
+use crate::lexer::{Lexer, SyntaxError};
+use crate::recognizer::NtSymbol;
+use crate::{CfgSymbol, Node};
+
+/// A rewrite failed to compile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RewriteError {
+    /// The byte offset in the rewrite's source
+    pub offset: usize,
+    pub message: String,
+}
+impl std::fmt::Display for RewriteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at offset {}", self.message, self.offset)
+    }
+}
+impl std::error::Error for RewriteError {}
+impl SyntaxError for RewriteError {
+    fn new(offset: usize, message: String) -> Self {
+        Self { offset, message }
+    }
+}
+
+pub struct Rewrite<'c, Symbol> {
+    cfg: &'c Cfg<Symbol>,
+    rules: Vec<RewriteRule>,
+}
+struct RewriteRule {
+    pattern: Pattern,
+    template: Template,
+    captures: usize,
+}
+struct Pattern {
+    /// The rules the node can have been made with, `None` for the wildcard
+    rules: Option<Vec<usize>>,
+    /// `None` when the children weren't listed
+    children: Option<Vec<Pattern>>,
+    capture: Option<usize>,
+}
+enum Template {
+    Capture(usize),
+    Node { rule: usize, children: Vec<Template> },
+}
+
+// A part of a rule, as written in a pattern or a template
+#[derive(Clone, Copy, PartialEq)]
+enum Part {
+    Terminal(u8),
+    /// `None` when it could be any nonterminal
+    Nt(Option<NtSymbol>),
+}
+
+impl<'c, Symbol: CfgSymbol<Terminal = u8>> Rewrite<'c, Symbol> {
+    /// Compile the rules in `source` for the grammar `cfg`, whose nonterminals from 256
+    /// upwards are called `names` (as returned by `cfg!`).
+    ///
+    /// Each rule is a pattern and a template separated by `->`, and `;` starts a comment.
+    pub fn new(cfg: &'c Cfg<Symbol>, names: &[&str], source: &str) -> Result<Self, RewriteError> {
+        let mut compiler = Compiler {
+            cfg,
+            names,
+            lexer: Lexer::new(source),
+            captures: vec![],
+        };
+        let mut rules = vec![];
+        while compiler.lexer.skip_trivia() < source.len() {
+            rules.push(compiler.rule()?);
+        }
+        Ok(Self { cfg, rules })
+    }
+
+    /// Rewrite the tree in `ast` bottom up, returning how many nodes were replaced.
+    ///
+    /// Each node is tried once, after its children have been rewritten, against the rules
+    /// in order. The first one to match replaces it, and the tree that was put in its
+    /// place isn't tried again. New nodes get the span of the node they replaced.
+    pub fn apply(&self, ast: &mut Vec<Node<'c, Symbol>>) -> usize {
+        let mut replaced = 0;
+        // The tree is copied over in pre-order. Once all of a node's children are copied,
+        // its subtree is at the end of the copy, so it can be replaced there.
+        let mut out: Vec<Node<'c, Symbol>> = Vec::with_capacity(ast.len());
+        let mut replacement = vec![];
+        // The nodes being copied, as their index in `out` and where their subtree ends in `ast`
+        let mut open: Vec<(usize, usize)> = vec![];
+        for idx in 0..=ast.len() {
+            while let Some(&(start, _)) = open.last().filter(|&&(_, end)| end <= idx) {
+                open.pop();
+                out[start].transitive_children = out.len() - start - 1;
+                for rule in &self.rules {
+                    let mut captures = vec![None; rule.captures];
+                    if !self.match_pattern(&rule.pattern, &out, start, &mut captures) {
+                        continue;
+                    }
+                    self.instantiate(&rule.template, &out, start, &captures, &mut replacement);
+                    out.truncate(start);
+                    out.append(&mut replacement);
+                    replaced += 1;
+                    break;
+                }
+            }
+            let Some(node) = ast.get(idx) else {
+                break;
+            };
+            open.push((out.len(), idx + 1 + node.transitive_children));
+            out.push(node.clone());
+        }
+        *ast = out;
+        replaced
+    }
+
+    fn match_pattern(
+        &self,
+        pattern: &Pattern,
+        ast: &[Node<'c, Symbol>],
+        idx: usize,
+        captures: &mut [Option<usize>],
+    ) -> bool {
+        let node = &ast[idx];
+        if pattern.rules.as_ref().is_some_and(|rules| !rules.contains(&node.rule)) {
+            return false;
+        }
+        if let Some(children) = &pattern.children {
+            if children.len() != node.children {
+                return false;
+            }
+            let mut child = idx + 1;
+            for child_pattern in children {
+                if !self.match_pattern(child_pattern, ast, child, captures) {
+                    return false;
+                }
+                child += 1 + ast[child].transitive_children;
+            }
+        }
+        if let Some(capture) = pattern.capture {
+            captures[capture] = Some(idx);
+        }
+        true
+    }
+
+    fn instantiate(
+        &self,
+        template: &Template,
+        ast: &[Node<'c, Symbol>],
+        replaced: usize,
+        captures: &[Option<usize>],
+        out: &mut Vec<Node<'c, Symbol>>,
+    ) {
+        // The templates left to put out, and `Err` with the index of a new node
+        // once its children are done
+        let mut todo = vec![Ok(template)];
+        while let Some(next) = todo.pop() {
+            match next {
+                Ok(&Template::Capture(capture)) => {
+                    let idx = captures[capture].expect("every capture in a template is in its pattern");
+                    out.extend_from_slice(&ast[idx..idx + 1 + ast[idx].transitive_children]);
+                }
+                Ok(&Template::Node { rule, ref children }) => {
+                    todo.push(Err(out.len()));
+                    out.push(Node {
+                        transition: &self.cfg.rules[rule].parts,
+                        rule,
+                        fields: &self.cfg.rules[rule].fields,
+                        start: ast[replaced].start,
+                        end: ast[replaced].end,
+                        children: children.len(),
+                        transitive_children: 0,
+                    });
+                    todo.extend(children.iter().rev().map(Ok));
+                }
+                Err(idx) => out[idx].transitive_children = out.len() - idx - 1,
+            }
+        }
+    }
+}
+
+struct Compiler<'a, Symbol> {
+    cfg: &'a Cfg<Symbol>,
+    names: &'a [&'a str],
+    lexer: Lexer<'a>,
+    // The captures of the rule being compiled, and their nonterminals if they're known
+    captures: Vec<(&'a str, Option<NtSymbol>)>,
+}
+impl<'a, Symbol: CfgSymbol<Terminal = u8>> Compiler<'a, Symbol> {
+    fn nonterminal(&mut self) -> Result<Option<NtSymbol>, RewriteError> {
+        let name_pos = self.lexer.skip_trivia();
+        let Some(name) = self.lexer.identifier() else {
+            return self.lexer.error(name_pos, "expected the name of a nonterminal");
+        };
+        if name == "_" {
+            return Ok(None);
+        }
+        match self.names.iter().position(|&n| n == name) {
+            Some(idx) => Ok(Some(256 + idx as NtSymbol)),
+            None => self.lexer.error(name_pos, format!("there's no nonterminal named `{name}`")),
+        }
+    }
+    // After the opening `"`
+    fn string(&mut self, out: &mut Vec<Part>) -> Result<(), RewriteError> {
+        let start = self.lexer.pos - 1;
+        let mut chars = self.lexer.rest().char_indices();
+        while let Some((i, c)) = chars.next() {
+            let c = match c {
+                '"' => {
+                    self.lexer.pos += i + 1;
+                    return Ok(());
+                }
+                '\\' => match chars.next() {
+                    Some((_, 'n')) => '\n',
+                    Some((_, 't')) => '\t',
+                    Some((_, c @ ('\\' | '"'))) => c,
+                    _ => return self.lexer.error(self.lexer.pos + i, "unknown escape"),
+                },
+                c => c,
+            };
+            out.extend(c.encode_utf8(&mut [0; 4]).bytes().map(Part::Terminal));
+        }
+        self.lexer.error(start, "unclosed string")
+    }
+    fn capture_name(&mut self) -> Result<(usize, &'a str), RewriteError> {
+        let pos = self.lexer.pos;
+        match self.lexer.identifier() {
+            Some(name) => Ok((pos, name)),
+            None => self.lexer.error(pos, "expected the name of the capture"),
+        }
+    }
+
+    fn rule(&mut self) -> Result<RewriteRule, RewriteError> {
+        self.captures.clear();
+        let start = self.lexer.skip_trivia();
+        let (pattern, nt) = self.pattern()?;
+        let Some(nt) = nt else {
+            return self.lexer.error(start, "the pattern needs to say which nonterminal it matches");
+        };
+        let arrow = self.lexer.skip_trivia();
+        if !self.lexer.eat("->") {
+            return self.lexer.error(arrow, "expected `->`");
+        }
+        let template_start = self.lexer.skip_trivia();
+        let (template, template_nt) = self.template()?;
+        if template_nt != nt {
+            return self.lexer.error(
+                template_start,
+                format!(
+                    "the template makes a `{}`, but the pattern matches a `{}`",
+                    self.name(template_nt),
+                    self.name(nt)
+                ),
+            );
+        }
+        Ok(RewriteRule {
+            pattern,
+            template,
+            captures: self.captures.len(),
+        })
+    }
+    fn name(&self, nt: NtSymbol) -> &'a str {
+        nt_name(self.names, nt)
+    }
+
+    /// The pattern, and the nonterminal it matches if it's known
+    fn pattern(&mut self) -> Result<(Pattern, Option<NtSymbol>), RewriteError> {
+        let start = self.lexer.skip_trivia();
+        if !self.lexer.eat("(") {
+            return self.lexer.error(start, "expected `(`");
+        }
+        let nt = self.nonterminal()?;
+        let mut parts = vec![];
+        let mut children = vec![];
+        let mut has_terminals = false;
+        while !self.lexer.eat(")") {
+            let child_pos = self.lexer.skip_trivia();
+            if self.lexer.eat("\"") {
+                self.string(&mut parts)?;
+                has_terminals = true;
+            } else if self.lexer.rest().starts_with('(') {
+                let (child, child_nt) = self.pattern()?;
+                children.push(child);
+                parts.push(Part::Nt(child_nt));
+            } else if self.lexer.rest().is_empty() {
+                return self.lexer.error(start, "unclosed `(`");
+            } else {
+                return self.lexer.error(child_pos, "expected a pattern or a string");
+            }
+        }
+        let listed = !parts.is_empty();
+        let rules = match nt {
+            Some(nt) => {
+                let rules = self.cfg.query_nt(nt).into_iter().flatten();
+                let rules = rules.filter(|&rule| !listed || self.fits(rule, &parts, has_terminals)).collect::<Vec<_>>();
+                if rules.is_empty() {
+                    return self.lexer.error(start, format!("`{}` has no rule like this", self.name(nt)));
+                }
+                Some(rules)
+            }
+            None if has_terminals => return self.lexer.error(start, "`_` can't be given terminals"),
+            None => None,
+        };
+        // The nonterminals of captured wildcards, when all the rules agree on them
+        if let Some(rules) = &rules {
+            let written = parts.iter().filter_map(|&part| match part {
+                Part::Nt(nt) => Some(nt),
+                Part::Terminal(_) => None,
+            });
+            for (child, (child_pattern, written)) in children.iter().zip(written).enumerate() {
+                if let (Some(capture), None) = (child_pattern.capture, written) {
+                    let mut nts = rules.iter().map(|&rule| self.nts(rule).nth(child));
+                    let first = nts.next().flatten();
+                    if nts.all(|nt| nt == first) {
+                        self.captures[capture].1 = first;
+                    }
+                }
+            }
+        }
+        let mut pattern = Pattern {
+            rules,
+            children: listed.then_some(children),
+            capture: None,
+        };
+        if self.lexer.eat("@") {
+            let (pos, name) = self.capture_name()?;
+            if self.captures.iter().any(|&(capture, _)| capture == name) {
+                return self.lexer.error(pos, format!("`@{name}` is captured more than once"));
+            }
+            pattern.capture = Some(self.captures.len());
+            self.captures.push((name, nt));
+        }
+        Ok((pattern, nt))
+    }
+
+    /// The template, and the nonterminal of the tree it makes
+    fn template(&mut self) -> Result<(Template, NtSymbol), RewriteError> {
+        let start = self.lexer.skip_trivia();
+        if self.lexer.eat("@") {
+            let (pos, name) = self.capture_name()?;
+            return match self.captures.iter().position(|&(capture, _)| capture == name) {
+                Some(capture) => match self.captures[capture].1 {
+                    Some(nt) => Ok((Template::Capture(capture), nt)),
+                    None => self.lexer.error(pos, format!("can't tell which nonterminal `@{name}` is")),
+                },
+                None => self.lexer.error(pos, format!("`@{name}` isn't captured by the pattern")),
+            };
+        }
+        if !self.lexer.eat("(") {
+            return self.lexer.error(start, "expected `(` or a capture");
+        }
+        let name_pos = self.lexer.skip_trivia();
+        let Some(nt) = self.nonterminal()? else {
+            return self.lexer.error(name_pos, "a template can't make `_`");
+        };
+        let mut parts = vec![];
+        let mut children = vec![];
+        let mut has_terminals = false;
+        while !self.lexer.eat(")") {
+            let child_pos = self.lexer.skip_trivia();
+            if self.lexer.eat("\"") {
+                self.string(&mut parts)?;
+                has_terminals = true;
+            } else if self.lexer.rest().starts_with('(') || self.lexer.rest().starts_with('@') {
+                let (child, child_nt) = self.template()?;
+                children.push(child);
+                parts.push(Part::Nt(Some(child_nt)));
+            } else if self.lexer.rest().is_empty() {
+                return self.lexer.error(start, "unclosed `(`");
+            } else {
+                return self.lexer.error(child_pos, "expected a template or a string");
+            }
+        }
+        let rules = self.cfg.query_nt(nt).into_iter().flatten();
+        let rules = rules.filter(|&rule| self.fits(rule, &parts, has_terminals)).collect::<Vec<_>>();
+        match rules[..] {
+            [rule] => Ok((Template::Node { rule, children }, nt)),
+            [] => self.lexer.error(start, format!("`{}` has no rule like this", self.name(nt))),
+            _ => self.lexer.error(
+                start,
+                format!("`{}` has more than one rule like this, write out its terminals", self.name(nt)),
+            ),
+        }
+    }
+
+    fn nts(&self, rule: usize) -> impl Iterator<Item = NtSymbol> + 'a {
+        let cfg = self.cfg;
+        cfg.rules[rule].parts.iter().filter_map(|part| part.as_part().err())
+    }
+    // Whether the rule has these parts, or just these nonterminals without any terminals
+    fn fits(&self, rule: usize, parts: &[Part], has_terminals: bool) -> bool {
+        let fits = |written: &Part, part: &Symbol| match (written, part.as_part()) {
+            (Part::Terminal(written), Ok(terminal)) => written == terminal.borrow(),
+            (&Part::Nt(written), Err(nt)) => written.is_none_or(|written| written == nt),
+            _ => false,
+        };
+        let rule_parts = &self.cfg.rules[rule].parts;
+        if has_terminals {
+            rule_parts.len() == parts.len() && parts.iter().zip(rule_parts).all(|(written, part)| fits(written, part))
+        } else {
+            let rule_nts = rule_parts.iter().filter(|part| part.as_part().is_err());
+            rule_nts.clone().count() == parts.len() && parts.iter().zip(rule_nts).all(|(written, part)| fits(written, part))
+        }
+    }
+}
@@ -0,0 +1,258 @@
+//! Writing trees out for other tools, as JSON or S-expressions.
+//!
+//! Nodes are written with their nonterminal's name (from the names returned by `cfg!`):
+//!
+//! ```text
+//! {"type": "expr", "span": [0, 9], "children": [...]}
+//! (expr (and_expr (primary)) (or) (expr ...))
+//! ```
+//!
+//! The S-expressions only have the structure of the tree, and can be read back with
+//! [`SExpr::parse`] so expected trees can be written in tests and compared to parses.
+use std::fmt::Write;
+
+use crate::codegen::nt_name;
+use crate::grammar::Cfg;
+use crate::lexer::{Lexer, SyntaxError};
+use crate::visit::{Visitor, walk};
+use crate::{CfgSymbol, Node};
+
+/// The tree rooted at `ast[0]` as JSON.
+pub fn to_json<Symbol: CfgSymbol>(cfg: &Cfg<Symbol>, names: &[&str], ast: &[Node<'_, Symbol>]) -> String {
+    let mut writer = Writer::new(cfg, names);
+    walk(ast, &mut writer);
+    writer.out
+}
+
+/// The tree rooted at `ast[0]` as an S-expression.
+pub fn to_sexpr<Symbol: CfgSymbol>(cfg: &Cfg<Symbol>, names: &[&str], ast: &[Node<'_, Symbol>]) -> String {
+    let mut writer = Writer::new(cfg, names);
+    writer.sexpr = true;
+    walk(ast, &mut writer);
+    writer.out
+}
+
+// Writes as it walks, rather than building strings for each subtree
+struct Writer<'a, Symbol> {
+    cfg: &'a Cfg<Symbol>,
+    names: &'a [&'a str],
+    sexpr: bool,
+    out: String,
+    // For each open node, whether it's had a child written yet
+    first_child: Vec<bool>,
+}
+impl<'a, Symbol> Writer<'a, Symbol> {
+    fn new(cfg: &'a Cfg<Symbol>, names: &'a [&'a str]) -> Self {
+        Self {
+            cfg,
+            names,
+            sexpr: false,
+            out: String::new(),
+            first_child: vec![],
+        }
+    }
+}
+impl<'c, Symbol> Visitor<'c, Symbol> for Writer<'_, Symbol> {
+    fn enter(&mut self, node: &Node<'c, Symbol>) {
+        let name = nt_name(self.names, self.cfg.rules[node.rule].for_nt);
+        // S-expressions separate the children from the name too
+        if let Some(first) = self.first_child.last_mut()
+            && (!std::mem::take(first) || self.sexpr)
+        {
+            self.out.push_str(if self.sexpr { " " } else { ", " });
+        }
+        self.first_child.push(true);
+        if self.sexpr {
+            write!(self.out, "({name}").unwrap();
+        } else {
+            write!(self.out, "{{\"type\": ").unwrap();
+            write_json_string(&mut self.out, name);
+            write!(self.out, ", \"span\": [{}, {}], \"children\": [", node.start, node.end).unwrap();
+        }
+    }
+    fn exit(&mut self, _node: &Node<'c, Symbol>) {
+        self.first_child.pop();
+        self.out.push_str(if self.sexpr { ")" } else { "]}" });
+    }
+}
+
+fn write_json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// A tree read from an S-expression, or built from an AST.
+///
+/// Comparing, cloning, printing and dropping them don't recurse, so they can be as deeply
+/// nested as the trees they're compared to. `Debug` prints the S-expression too.
+pub struct SExpr {
+    pub name: String,
+    pub children: Vec<SExpr>,
+}
+impl PartialEq for SExpr {
+    fn eq(&self, other: &Self) -> bool {
+        let mut todo = vec![(self, other)];
+        while let Some((a, b)) = todo.pop() {
+            if a.name != b.name || a.children.len() != b.children.len() {
+                return false;
+            }
+            todo.extend(a.children.iter().zip(&b.children));
+        }
+        true
+    }
+}
+impl Eq for SExpr {}
+impl Clone for SExpr {
+    fn clone(&self) -> Self {
+        let leaf = |tree: &SExpr| SExpr {
+            name: tree.name.clone(),
+            children: Vec::with_capacity(tree.children.len()),
+        };
+        // The trees being copied, with their copies so far
+        let mut open = vec![(self, leaf(self))];
+        loop {
+            let (tree, copy) = open.last().unwrap();
+            if let Some(child) = tree.children.get(copy.children.len()) {
+                open.push((child, leaf(child)));
+                continue;
+            }
+            let (_, copy) = open.pop().unwrap();
+            match open.last_mut() {
+                Some((_, parent)) => parent.children.push(copy),
+                None => return copy,
+            }
+        }
+    }
+}
+impl Drop for SExpr {
+    fn drop(&mut self) {
+        // Each subtree is dropped once its children have been taken out of it
+        let mut todo = std::mem::take(&mut self.children);
+        while let Some(mut tree) = todo.pop() {
+            todo.append(&mut tree.children);
+        }
+    }
+}
+
+/// An S-expression couldn't be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SExprError {
+    /// The byte offset in the input
+    pub offset: usize,
+    pub message: String,
+}
+impl std::fmt::Display for SExprError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at offset {}", self.message, self.offset)
+    }
+}
+impl std::error::Error for SExprError {}
+impl SyntaxError for SExprError {
+    fn new(offset: usize, message: String) -> Self {
+        Self { offset, message }
+    }
+}
+
+impl SExpr {
+    /// Build the tree for `ast[0]`, to compare with ones that have been read.
+    pub fn from_ast<Symbol: CfgSymbol>(cfg: &Cfg<Symbol>, names: &[&str], ast: &[Node<'_, Symbol>]) -> Self {
+        crate::visit::fold(ast, |node: &Node<'_, Symbol>, children| SExpr {
+            name: nt_name(names, cfg.rules[node.rule].for_nt).to_string(),
+            children,
+        })
+    }
+
+    /// Read a single tree, like `(expr (and_expr (primary)) (or) (expr))`.
+    ///
+    /// A bare name stands for a node without children, so `(or)` can also be written `or`.
+    /// `;` starts a comment that runs to the end of the line.
+    pub fn parse(src: &str) -> Result<Self, SExprError> {
+        let mut reader = Reader { lexer: Lexer::new(src) };
+        let tree = reader.tree()?;
+        if reader.lexer.skip_trivia() < src.len() {
+            return reader.lexer.error(reader.lexer.pos, "expected the end of the input");
+        }
+        Ok(tree)
+    }
+}
+impl std::fmt::Display for SExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // `None` closes the tree that was opened last
+        let mut todo = vec![Some(self)];
+        let mut first = true;
+        while let Some(next) = todo.pop() {
+            let Some(tree) = next else {
+                write!(f, ")")?;
+                continue;
+            };
+            if !std::mem::take(&mut first) {
+                write!(f, " ")?;
+            }
+            write!(f, "({}", tree.name)?;
+            todo.push(None);
+            todo.extend(tree.children.iter().rev().map(Some));
+        }
+        Ok(())
+    }
+}
+impl std::fmt::Debug for SExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SExpr({self})")
+    }
+}
+
+struct Reader<'a> {
+    lexer: Lexer<'a>,
+}
+impl Reader<'_> {
+    fn name(&mut self) -> Result<String, SExprError> {
+        self.lexer.skip_trivia();
+        let rest = self.lexer.rest();
+        let len = rest
+            .find(|c: char| c.is_whitespace() || matches!(c, '(' | ')' | ';'))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return self.lexer.error(self.lexer.pos, "expected a name");
+        }
+        self.lexer.pos += len;
+        Ok(rest[..len].to_string())
+    }
+    // Uses its own stack, so deeply nested input is fine
+    fn tree(&mut self) -> Result<SExpr, SExprError> {
+        let mut open: Vec<SExpr> = vec![];
+        loop {
+            self.lexer.skip_trivia();
+            let tree = if self.lexer.rest().starts_with('(') {
+                self.lexer.pos += 1;
+                let name = self.name()?;
+                open.push(SExpr { name, children: vec![] });
+                continue;
+            } else if self.lexer.rest().starts_with(')') {
+                let Some(tree) = open.pop() else {
+                    return self.lexer.error(self.lexer.pos, "unexpected `)`");
+                };
+                self.lexer.pos += 1;
+                tree
+            } else if self.lexer.rest().is_empty() {
+                return self.lexer.error(self.lexer.pos, if open.is_empty() { "expected a tree" } else { "unclosed `(`" });
+            } else {
+                SExpr {
+                    name: self.name()?,
+                    children: vec![],
+                }
+            };
+            match open.last_mut() {
+                Some(parent) => parent.children.push(tree),
+                None => return Ok(tree),
+            }
+        }
+    }
+}
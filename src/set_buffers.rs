@@ -4,7 +4,8 @@
 
 /// Create a new sorted set in a vector.
 pub fn sorted_set<T: PartialEq + Ord>(vec: &mut Vec<T>) {
-    vec.sort();
+    // Equal elements are dropped anyway, and the stable sort would allocate
+    vec.sort_unstable();
     retain_with_context(vec, |cx, v| cx.last() != Some(v));
 }
 
@@ -22,23 +23,22 @@ impl<'a, T> super::buffer_pair::BufferPair<T> for InternalSlice<'a, T> {
         self.slice
     }
 }
-// Find the transitive closure of a relation
-pub fn grow_ordered_set<T: Ord>(states: &mut Vec<T>, mut rel: impl FnMut(InternalSlice<'_, T>)) {
-    // Just putting an arbitrary bound on the number of iterations we'll
-    // try to saturate the reachable set in. Could be way higher
-    let mut loop_check = {
-        let mut iters = 0;
-        move || {
-            iters += 1;
-            if 12000 <= iters {
-                panic!("recursion limit?");
-            }
-        }
-    };
+/// Find the transitive closure of a relation.
+///
+/// `states` starts out with the states to begin from. `rel` is given the batch of states that
+/// haven't been expanded yet, and pushes the states they lead to onto the end of the vector.
+/// The ones that were seen before are dropped straight away, so each state is expanded once.
+/// `seen` is cleared first, it's only passed in so its allocation can be reused.
+pub fn grow_set<T: Copy + Eq + std::hash::Hash>(
+    states: &mut Vec<T>,
+    seen: &mut StateSet<T>,
+    mut rel: impl FnMut(InternalSlice<'_, T>),
+) {
+    seen.clear();
+    states.retain(|&state| seen.insert(state));
     let mut pending_start = 0;
     // as long as there are pending states to process,
     while pending_start < states.len() {
-        loop_check();
         let pending_end = states.len();
         //   for every pending state,
         //     generate all the states reachable from it in one step
@@ -46,36 +46,50 @@ pub fn grow_ordered_set<T: Ord>(states: &mut Vec<T>, mut rel: impl FnMut(Interna
             slice: states,
             range: pending_start..pending_end,
         });
-        states[..pending_end].sort();
-        //   for every new state, deduplicate
-        isolate_new_elements(states, pending_end);
-        //   the new states are now pending being processed
+        //   the ones that are new are now pending being processed
+        let mut kept = pending_end;
+        for i in pending_end..states.len() {
+            if seen.insert(states[i]) {
+                states[kept] = states[i];
+                kept += 1;
+            }
+        }
+        states.truncate(kept);
         pending_start = pending_end;
     }
 }
-/// This implements the ability to extend a sorted set with new elements,
-/// you should insert a batch of new elements at the end, and give the index
-/// where the new elements start. They'll be deduplicated against the old elements.
-///
-/// However, this function doesn't yet sort the new elements *into* the rest of the set,
-/// meaning the `set` vector is left with two sets in 0..old_len and old_len..end.
-/// This allows the caller to process the added elements.
-pub fn isolate_new_elements<T: Ord>(set: &mut Vec<T>, old_len: usize) {
-    let (old, new) = set.split_at_mut(old_len);
-    new.sort();
-    let mut check = 0;
-    let new_len = slice_retain_with_context(new, |cx, new_val| {
-        while check < old.len() && old[check] < *new_val {
-            check += 1;
+/// A hash set for the states, which are a few small integers, so they don't need
+/// the default hasher's protection against collisions.
+pub(crate) type StateSet<T> = std::collections::HashSet<T, std::hash::BuildHasherDefault<StateHasher>>;
+#[derive(Default)]
+pub(crate) struct StateHasher(u64);
+impl std::hash::Hasher for StateHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write_u64(byte as u64);
         }
-        cx.last() != Some(new_val) && (check == old.len() || old[check] != *new_val)
-    });
-    set.truncate(old_len + new_len);
+    }
+    // This is the hash from rustc (`FxHasher`)
+    fn write_u64(&mut self, i: u64) {
+        self.0 = (self.0.rotate_left(5) ^ i).wrapping_mul(0x51_7c_c1_b7_27_22_0a_95);
+    }
+    fn write_u32(&mut self, i: u32) {
+        self.write_u64(i as u64);
+    }
+    fn write_u16(&mut self, i: u16) {
+        self.write_u64(i as u64);
+    }
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
 }
 
 /// This is `Vec::retain`, but the predicate gets a mutable slice of the
 /// already retained elements, so it can do more complex checks.
-fn slice_retain_with_context<T>(
+pub(crate) fn slice_retain_with_context<T>(
     vec: &mut [T],
     mut f: impl FnMut(&mut [T], &mut T) -> bool,
 ) -> usize {
@@ -0,0 +1,269 @@
+//! Turning trees back into source text, using the grammar.
+//!
+//! Every terminal is written down in a rule, so the text of a tree follows from the rules
+//! of its nodes alone. This works for trees that were changed after parsing too.
+//! Layout nonterminals (like `gap`) are replaced with the layout they're given instead,
+//! which makes an unparser a formatter:
+//!
+//! ```ignore
+//! let formatted = Unparser::new(&cfg)
+//!     .layout(gap, Layout::Space)
+//!     .unparse(&ast);
+//! ```
+//!
+//! When a tree has been changed, a node can end up somewhere its nonterminal can't be
+//! parsed, like an `or` expression as the operand of `not`. If the levels of precedence
+//! are declared with [`Unparser::precedence`], these are wrapped in parentheses, and
+//! parentheses that aren't needed anymore are left out.
+//!
+//! Terminals are written as their [`CfgSymbol::terminal_char`], or with their `Debug` impl
+//! if they don't have one. For the grammars over bytes that's each byte as a `char`, so
+//! only ASCII text comes back exactly as it was.
+//!
+//! The text of a node is found from its rule, so this fails for trees with nodes that have
+//! been reshaped by the grammar's [`Shape`](crate::grammar::Shape)s.
+use std::fmt::Write;
+
+use crate::grammar::{Cfg, ReshapedError};
+use crate::recognizer::NtSymbol;
+use crate::{CfgSymbol, Node};
+
+/// What to write instead of a layout nonterminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    Nothing,
+    Space,
+    /// A line break, and the indentation on the next line
+    Newline,
+    Text(&'static str),
+}
+
+pub struct Unparser<'a, Symbol> {
+    cfg: &'a Cfg<Symbol>,
+    layouts: Vec<(NtSymbol, Layout)>,
+    // Overrides for (rule, part)
+    layouts_in: Vec<(usize, usize, Layout)>,
+    indented: Vec<NtSymbol>,
+    indent: &'static str,
+    // From the loosest binding to the tightest
+    levels: Vec<NtSymbol>,
+    parens: Option<usize>,
+}
+
+enum Task<'c, Symbol> {
+    Node(usize, Slot),
+    Terminal(&'c Symbol),
+    Layout(Layout),
+    Indent,
+    Dedent,
+}
+/// Where a node is being written.
+#[derive(Clone, Copy)]
+enum Slot {
+    /// Expects a nonterminal at this precedence level
+    Level(usize),
+    Any,
+}
+
+impl<'a, Symbol: CfgSymbol> Unparser<'a, Symbol> {
+    pub fn new(cfg: &'a Cfg<Symbol>) -> Self {
+        Self {
+            cfg,
+            layouts: vec![],
+            layouts_in: vec![],
+            indented: vec![],
+            indent: "    ",
+            levels: vec![],
+            parens: None,
+        }
+    }
+    /// Write `layout` instead of every node for `nt`.
+    pub fn layout(mut self, nt: NtSymbol, layout: Layout) -> Self {
+        self.layouts.push((nt, layout));
+        self
+    }
+    /// Write `layout` for the layout nonterminal at `part` of the rule with index `rule`,
+    /// instead of the one given to [`Self::layout`].
+    pub fn layout_in(mut self, rule: usize, part: usize, layout: Layout) -> Self {
+        self.layouts_in.push((rule, part, layout));
+        self
+    }
+    /// Indent the lines inside the nodes for `nt` one level further: everything after
+    /// the first part of the rule and before the last, like the insides of `{ ... }`.
+    pub fn indent(mut self, nt: NtSymbol) -> Self {
+        self.indented.push(nt);
+        self
+    }
+    /// What each level of indentation is written as (4 spaces by default).
+    pub fn indent_with(mut self, indent: &'static str) -> Self {
+        self.indent = indent;
+        self
+    }
+    /// Declare the levels of precedence, from the loosest binding nonterminal to the
+    /// tightest, like `[expr, and_expr, primary]`. `parens` is the index of the rule that
+    /// parenthesizes the loosest level, like `primary ::= "(" expr ")"`.
+    pub fn precedence(mut self, levels: &[NtSymbol], parens: usize) -> Self {
+        let rule = &self.cfg.rules[parens];
+        let nts = rule.parts.iter().filter_map(|part| part.as_part().err()).collect::<Vec<_>>();
+        assert_eq!(nts, [levels[0]], "the parentheses need to contain the loosest level");
+        self.levels = levels.to_vec();
+        self.parens = Some(parens);
+        self
+    }
+
+    /// Write out the tree rooted at `ast[0]`.
+    pub fn unparse(&self, ast: &[Node<'_, Symbol>]) -> Result<String, ReshapedError> {
+        let end = ast[0].transitive_children + 1;
+        if let Some(node) = ast[..end].iter().position(|node| self.cfg.rule_is_reshaped(node.rule)) {
+            return Err(ReshapedError { node });
+        }
+        let mut out = String::new();
+        let mut indent = 0;
+        // The indentation is written when the line gets something on it,
+        // so the indentation level can change after the line break
+        let mut line_start = false;
+        let mut tasks: Vec<Task<'_, Symbol>> = vec![Task::Node(0, self.slot(self.cfg.rules[ast[0].rule].for_nt))];
+        while let Some(task) = tasks.pop() {
+            let (idx, slot) = match task {
+                Task::Node(idx, slot) => (idx, slot),
+                Task::Terminal(part) => {
+                    if std::mem::take(&mut line_start) {
+                        out.push_str(&self.indent.repeat(indent));
+                    }
+                    let Ok(terminal) = part.as_part() else {
+                        unreachable!("only terminals are written as they are")
+                    };
+                    let terminal = std::borrow::Borrow::borrow(&terminal);
+                    match Symbol::terminal_char(terminal) {
+                        Some(c) => out.push(c),
+                        None => write!(out, "{terminal:?}").unwrap(),
+                    }
+                    continue;
+                }
+                Task::Layout(layout) => {
+                    match layout {
+                        Layout::Nothing => {}
+                        Layout::Space => out.push(' '),
+                        Layout::Newline => {
+                            out.push('\n');
+                            line_start = true;
+                        }
+                        Layout::Text(text) => out.push_str(text),
+                    }
+                    continue;
+                }
+                Task::Indent => {
+                    indent += 1;
+                    continue;
+                }
+                Task::Dedent => {
+                    indent -= 1;
+                    continue;
+                }
+            };
+            let idx = self.unwrap_parens(ast, idx, slot);
+            let node = &ast[idx];
+            let nt = self.cfg.rules[node.rule].for_nt;
+            // The tasks for this node, in reverse
+            let mut node_tasks = vec![];
+            let needs_parens = match slot {
+                Slot::Level(expected) => self.level(ast, idx).is_some_and(|level| level < expected),
+                Slot::Any => false,
+            };
+            if needs_parens {
+                let parens = self.parens.unwrap();
+                for part in self.cfg.rules[parens].parts.iter().rev() {
+                    node_tasks.push(match part.as_part() {
+                        Ok(_) => Task::Terminal(part),
+                        Err(_) => Task::Node(idx, Slot::Any),
+                    });
+                }
+                tasks.extend(node_tasks);
+                continue;
+            }
+
+            // The parts between the first and the last are indented, like the insides of `{ ... }`
+            let indented = self.indented.contains(&nt) && node.transition.len() >= 2;
+            let mut children = vec![];
+            let mut child = idx + 1;
+            for _ in 0..node.children {
+                children.push(child);
+                child += 1 + ast[child].transitive_children;
+            }
+            for (part_idx, part) in node.transition.iter().enumerate().rev() {
+                if indented && part_idx == 0 {
+                    node_tasks.push(Task::Indent);
+                }
+                node_tasks.push(match part.as_part() {
+                    Ok(_) => Task::Terminal(part),
+                    Err(part_nt) => {
+                        // None of the nodes are reshaped, so there's a child for each nonterminal
+                        let child = children.pop().unwrap();
+                        let child_nt = self.cfg.rules[ast[child].rule].for_nt;
+                        let layout = self
+                            .layouts_in
+                            .iter()
+                            .find(|&&(rule, part, _)| (rule, part) == (node.rule, part_idx))
+                            .map(|&(.., layout)| layout)
+                            .or_else(|| {
+                                let layout = self.layouts.iter().find(|&&(nt, _)| nt == child_nt);
+                                layout.map(|&(_, layout)| layout)
+                            });
+                        match layout {
+                            Some(layout) => Task::Layout(layout),
+                            None => Task::Node(child, self.slot(part_nt)),
+                        }
+                    }
+                });
+                if indented && part_idx + 1 == node.transition.len() {
+                    node_tasks.push(Task::Dedent);
+                }
+            }
+            tasks.extend(node_tasks);
+        }
+        Ok(out)
+    }
+
+    fn slot(&self, nt: NtSymbol) -> Slot {
+        match self.levels.iter().position(|&level| level == nt) {
+            Some(level) => Slot::Level(level),
+            None => Slot::Any,
+        }
+    }
+    /// The precedence level of the node, looking through rules that only pass on a
+    /// single nonterminal (like `expr ::= and_expr`) to the node that actually binds.
+    fn level(&self, ast: &[Node<'_, Symbol>], mut idx: usize) -> Option<usize> {
+        loop {
+            let node = &ast[idx];
+            let level = self.levels.iter().position(|&nt| nt == self.cfg.rules[node.rule].for_nt)?;
+            let is_chain = node.children == 1 && node.transition.len() == 1;
+            if !is_chain || Some(node.rule) == self.parens {
+                return Some(level);
+            }
+            idx += 1;
+            if self.levels.iter().all(|&nt| nt != self.cfg.rules[ast[idx].rule].for_nt) {
+                return Some(level);
+            }
+        }
+    }
+    /// Skip over parentheses that don't need to be there in `slot`, returning the node to write.
+    fn unwrap_parens(&self, ast: &[Node<'_, Symbol>], idx: usize, slot: Slot) -> usize {
+        // Without a level to compare to, we can't tell whether they're needed
+        let (Some(parens), Slot::Level(expected)) = (self.parens, slot) else {
+            return idx;
+        };
+        // The parenthesized node is the last one in a chain of single child rules
+        let mut inner = idx;
+        while ast[inner].rule != parens {
+            if ast[inner].children != 1 || ast[inner].transition.len() != 1 {
+                return idx;
+            }
+            inner += 1;
+        }
+        let content = inner + 1;
+        match self.level(ast, content) {
+            Some(level) if level >= expected => content,
+            _ => idx,
+        }
+    }
+}
@@ -0,0 +1,98 @@
+//! Walkers over the flat ASTs produced by [`trace_to_ast`](crate::trace_to_ast).
+//!
+//! The AST is stored in pre-order, with each node knowing how many nodes its subtree
+//! spans, so both of these walk it with explicit stacks instead of recursing.
+//! Deeply nested inputs (like long right recursive lists) can't overflow the call stack.
+use crate::{CfgSymbol, Node};
+
+/// Callbacks for a depth-first walk of an AST.
+///
+/// `enter` is called before any of a node's children are visited, and `exit` after all of them.
+pub trait Visitor<'c, Symbol> {
+    fn enter(&mut self, node: &Node<'c, Symbol>) {
+        _ = node;
+    }
+    fn exit(&mut self, node: &Node<'c, Symbol>) {
+        _ = node;
+    }
+}
+
+/// Computes a value for a node from the values of its children.
+///
+/// The children are the nodes for each nonterminal in `node.transition`, in order.
+/// It's implemented for closures, so `fold(&ast, |node, children| ...)` works.
+pub trait Fold<'c, Symbol, T> {
+    fn fold(&mut self, node: &Node<'c, Symbol>, children: Vec<T>) -> T;
+}
+impl<'c, Symbol, T, F: FnMut(&Node<'c, Symbol>, Vec<T>) -> T> Fold<'c, Symbol, T> for F {
+    fn fold(&mut self, node: &Node<'c, Symbol>, children: Vec<T>) -> T {
+        self(node, children)
+    }
+}
+
+/// Visit every node of the tree rooted at `ast[0]`.
+pub fn walk<'c, Symbol>(ast: &[Node<'c, Symbol>], visitor: &mut impl Visitor<'c, Symbol>) {
+    // The nodes we're inside of, paired with the index their subtree ends at
+    let mut open: Vec<(usize, usize)> = vec![];
+    let end = ast.first().map_or(0, |root| root.transitive_children + 1);
+    for (i, node) in ast[..end].iter().enumerate() {
+        while let Some(&(parent, _)) = open.last().filter(|&&(_, subtree_end)| subtree_end <= i) {
+            visitor.exit(&ast[parent]);
+            open.pop();
+        }
+        visitor.enter(node);
+        open.push((i, i + 1 + node.transitive_children));
+    }
+    while let Some((parent, _)) = open.pop() {
+        visitor.exit(&ast[parent]);
+    }
+}
+
+/// Compute a value for the tree rooted at `ast[0]` bottom up.
+///
+/// Every node is folded exactly once, after all of its children.
+pub fn fold<'c, Symbol, T>(ast: &[Node<'c, Symbol>], mut folder: impl Fold<'c, Symbol, T>) -> T {
+    let end = ast[0].transitive_children + 1;
+    // Walking the pre-order backwards reaches every child before its parent.
+    // The values are stacked so that a node's first child is on top.
+    let mut values: Vec<T> = vec![];
+    for node in ast[..end].iter().rev() {
+        let children = values.split_off(values.len() - node.children);
+        let value = folder.fold(node, children.into_iter().rev().collect());
+        values.push(value);
+    }
+    assert_eq!(values.len(), 1);
+    values.pop().unwrap()
+}
+
+/// The subtrees for each child of `ast[0]`, in order.
+pub fn children<'a, 'c, Symbol>(
+    ast: &'a [Node<'c, Symbol>],
+) -> impl Iterator<Item = &'a [Node<'c, Symbol>]> {
+    let mut rest = &ast[1..ast[0].transitive_children + 1];
+    std::iter::from_fn(move || {
+        let (child, tail) = rest.split_at(1 + rest.first()?.transitive_children);
+        rest = tail;
+        Some(child)
+    })
+}
+
+/// The subtree for the child of `ast[0]` labelled `name` in its rule.
+///
+/// This relies on the children lining up with the nonterminals in the rule, so it's
+/// `None` for nodes that have been reshaped by the grammar's [`Shape`](crate::grammar::Shape)s.
+pub fn child_by_field<'a, 'c, Symbol: CfgSymbol>(
+    ast: &'a [Node<'c, Symbol>],
+    name: &str,
+) -> Option<&'a [Node<'c, Symbol>]> {
+    children(ast).nth(field_child(&ast[0], name)?)
+}
+/// Which of the node's children is the one labelled `name`.
+pub(crate) fn field_child<Symbol: CfgSymbol>(node: &Node<'_, Symbol>, name: &str) -> Option<usize> {
+    let &(_, part) = node.fields.iter().find(|&&(label, _)| label == name)?;
+    let is_nt = |part: &Symbol| part.as_part().is_err();
+    if node.transition.iter().filter(|part| is_nt(part)).count() != node.children {
+        return None;
+    }
+    Some(node.transition[..part].iter().filter(|part| is_nt(part)).count())
+}
@@ -0,0 +1,53 @@
+#[test]
+fn evaluate_logic() {
+    // The shared logic grammar without identifiers, with its actions declared inline
+    let (cfg, _, actions) = cfg_toy::cfg! {
+        expr and_expr primary ws gap and or not => bool;
+
+        ws ::= " " .
+        gap ::= ws.
+        gap ::= ws gap.
+
+        and ::= gap "and" gap.
+        or ::= gap "or" gap.
+        not ::= "not" gap.
+
+        expr ::= and_expr or expr => |l, _, r| l || r .
+        expr ::= and_expr.
+        and_expr ::= primary and and_expr => |l, _, r| l && r .
+        and_expr ::= primary.
+        primary ::= not primary => |_, p| !p .
+        primary ::= "(" expr ")".
+        primary ::= "true" => || true .
+        primary ::= "false".
+    };
+    for (src, expected) in [
+        ("true", true),
+        ("false", false),
+        ("true or false and not false", true),
+        ("not (true or false) or false", false),
+        ("(true and not false) and not not true", true),
+    ] {
+        let completions = cfg_toy::parse_earley(&cfg, src.as_bytes(), 256, ());
+        let ast = cfg_toy::trace_to_ast(&cfg, src.as_bytes(), &completions, &256);
+        assert_eq!(cfg_toy::evaluate(&cfg, &ast, &actions).unwrap(), expected, "{src}");
+    }
+}
+
+#[test]
+fn actions_follow_their_rules() {
+    // The rules are declared out of order, so `Cfg::new` has to move them
+    let (cfg, _, actions) = cfg_toy::cfg! {
+        sum digit => u32;
+
+        digit ::= "1" => || 1 .
+        sum ::= sum "+" digit => |l, r| l + r .
+        digit ::= "2" => || 2 .
+        sum ::= digit .
+        digit ::= "3" => || 3 .
+    };
+    let src = b"1+2+3+3";
+    let completions = cfg_toy::parse_earley(&cfg, src, 256, ());
+    let ast = cfg_toy::trace_to_ast(&cfg, src, &completions, &256);
+    assert_eq!(cfg_toy::evaluate(&cfg, &ast, &actions).unwrap(), 9);
+}
@@ -0,0 +1,65 @@
+use cfg_toy::bounded::{Recognizer, recognize};
+use cfg_toy::grammar::Cfg;
+
+mod common;
+use common::json_grammar;
+
+#[test]
+fn same_as_parse_earley() {
+    let cfg = json_grammar();
+    for src in [
+        r#"[{"a": 10.01, "b" :[ ]}, "ab ba", null,{ }]"#,
+        "  true ",
+        r#"{"a": [1, [0, [true]]], "": "", "b": {"a": 1.}}"#,
+        "[1, 2]",
+        r#"{"a" 1}"#,
+        "[1, 0",
+        "",
+        "1 1",
+    ] {
+        let expected = cfg_toy::try_parse_earley(&cfg, src.as_bytes(), 256, ()).err();
+        assert_eq!(recognize(&cfg, src.bytes(), 256).err(), expected, "{src:?}");
+    }
+}
+
+// The most positions that were kept around at once
+fn peak_live_positions(cfg: &Cfg<u32>, src: &[u8]) -> usize {
+    let mut recognizer = Recognizer::new(cfg, 256);
+    let mut peak = 0;
+    for symbol in src {
+        recognizer.push(symbol).unwrap();
+        peak = peak.max(recognizer.live_positions());
+    }
+    recognizer.finish().unwrap();
+    peak
+}
+
+#[test]
+fn memory_follows_nesting() {
+    let cfg = json_grammar();
+    let element = r#"{"a": [1.01, "ab"], "b": null}"#;
+    let short = format!("[{element}]");
+    let long = format!("[{}{element}]", format!("{element}, ").repeat(20_000));
+    // Only a few more positions than for a single element
+    assert!(peak_live_positions(&cfg, long.as_bytes()) <= 2 * peak_live_positions(&cfg, short.as_bytes()) + 64);
+
+    let nested = |depth: usize| format!("{}1{}", "[".repeat(depth), "]".repeat(depth));
+    let shallow = peak_live_positions(&cfg, nested(100).as_bytes());
+    let deep = peak_live_positions(&cfg, nested(1000).as_bytes());
+    // Every open bracket is waiting for its close
+    assert!(shallow < 500, "{shallow}");
+    assert!((1000..5000).contains(&deep), "{deep}");
+}
+
+#[test]
+fn trailing_nullable() {
+    let (cfg, _) = cfg_toy::cfg! {
+        s a b;
+
+        s ::= "x" a .
+        a ::= b .
+        b ::= .
+    };
+    assert_eq!(recognize(&cfg, "x".bytes(), 256), Ok(()));
+    assert_eq!(recognize(&cfg, "xx".bytes(), 256).err().map(|e| e.position), Some(1));
+}
@@ -0,0 +1,85 @@
+mod logic_ast {
+    include!("generated/logic_ast.rs");
+}
+// The nonterminals are named like types from the prelude, which this has to compile despite
+#[allow(dead_code)]
+mod prelude_names_ast {
+    include!("generated/prelude_names_ast.rs");
+}
+use logic_ast::{AndExpr, Expr, Primary};
+
+mod common;
+use common::logic_grammar;
+
+fn prelude_names_grammar() -> (cfg_toy::grammar::Cfg<u32>, Vec<&'static str>) {
+    let (cfg, _) = cfg_toy::cfg! {
+        result boxed try_from option;
+
+        result ::= boxed "," try_from .
+        result ::= option .
+        boxed ::= "b" .
+        try_from ::= "t" result .
+        try_from ::= .
+        option ::= boxed .
+    };
+    // `box` is a keyword, so it can't be named in `cfg!`
+    (cfg, vec!["result", "box", "try_from", "option"])
+}
+
+#[test]
+fn generated_code_is_up_to_date() {
+    for ((cfg, names), file) in [(logic_grammar(), "logic_ast.rs"), (prelude_names_grammar(), "prelude_names_ast.rs")] {
+        let generated = cfg_toy::codegen::generate_ast_types(&cfg, &names);
+        let path = format!("{}/tests/generated/{file}", env!("CARGO_MANIFEST_DIR"));
+        if std::env::var_os("CFG_TOY_BLESS").is_some() {
+            std::fs::write(&path, &generated).unwrap();
+        }
+        assert_eq!(generated, std::fs::read_to_string(&path).unwrap());
+    }
+}
+
+#[test]
+fn convert_to_typed_ast() {
+    let (cfg, _) = logic_grammar();
+    let src = b"not true or (false)";
+    let completions = cfg_toy::parse_earley(&cfg, src, 256, ());
+    let ast = cfg_toy::trace_to_ast(&cfg, src, &completions, &256);
+    let Expr::AndExprOrExpr(lhs, _, rhs) = Expr::try_from(&ast[..]).unwrap() else {
+        panic!("expected an `or`");
+    };
+    let AndExpr::Primary(lhs) = *lhs else { panic!() };
+    assert!(matches!(*lhs, Primary::NotPrimary(_, ref p) if **p == Primary::True));
+    let Expr::AndExpr(rhs) = *rhs else { panic!() };
+    let AndExpr::Primary(rhs) = *rhs else { panic!() };
+    let Primary::Expr(inner) = *rhs else { panic!() };
+    assert_eq!(*inner, Expr::AndExpr(Box::new(AndExpr::Primary(Box::new(Primary::False)))));
+}
+
+#[test]
+fn convert_errors() {
+    let (cfg, _) = logic_grammar();
+    let src = b"true";
+    let completions = cfg_toy::parse_earley(&cfg, src, 256, ());
+    let ast = cfg_toy::trace_to_ast(&cfg, src, &completions, &256);
+    let and_expr = cfg_toy::visit::children(&ast).next().unwrap();
+    let error = Expr::try_from(and_expr).unwrap_err();
+    assert_eq!((error.rule, error.nonterminal), (Some(3), "expr"));
+    assert_eq!(error.to_string(), "the node for rule 3 isn't a `expr` in this grammar");
+    assert_eq!(Primary::try_from(&ast[..0]).unwrap_err().rule, None);
+}
+
+#[test]
+fn nonterminal_without_rules() {
+    let (cfg, names) = cfg_toy::cfg! {
+        list never item;
+        list ::= item list .
+        list ::= .
+        item ::= "a" .
+        item ::= never .
+    };
+    let generated = cfg_toy::codegen::generate_ast_types(&cfg, &names);
+    assert!(generated.contains("/// `never` has no rules, so there's no tree for it.\n"));
+    assert!(generated.contains("pub enum Never {\n}\n"));
+    assert!(generated.contains("Err(::cfg_toy::codegen::ConvertError { rule: ast.first().map(|node| node.rule), nonterminal: \"never\" })"));
+    assert!(generated.contains("Never(::std::boxed::Box<Never>),"));
+}
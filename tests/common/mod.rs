@@ -0,0 +1,124 @@
+// Fixtures shared by the integration tests, not every test uses all of them
+#![allow(dead_code)]
+use cfg_toy::Node;
+use cfg_toy::completions::Completions;
+use cfg_toy::grammar::Cfg;
+use cfg_toy::recognizer::ParseError;
+
+/// Boolean expressions with whitespace, and identifiers made of "a" and "b".
+/// The nonterminals from 256 are `expr and_expr primary ws gap and or not alpha ident`.
+pub fn logic_grammar() -> (Cfg<u32>, Vec<&'static str>) {
+    cfg_toy::cfg! {
+        expr and_expr primary ws gap and or not alpha ident;
+
+        ws ::= " " .
+        ws ::= "\n" .
+        gap ::= ws.
+        gap ::= ws gap.
+
+        alpha ::= "a".
+        alpha ::= "b".
+        ident ::= alpha ident.
+        ident ::= alpha.
+
+        and ::= gap "and" gap.
+        or ::= gap "or" gap.
+        not ::= "not" gap.
+
+        expr ::= and_expr or expr.
+        expr ::= and_expr.
+        and_expr ::= primary and and_expr.
+        and_expr ::= primary.
+        primary ::= not primary.
+        primary ::= "(" expr ")".
+        primary ::= "true".
+        primary ::= "false".
+        primary ::= ident.
+    }
+}
+
+/// Parse `src` as the grammar's first nonterminal and build its tree.
+pub fn parse<'c>(cfg: &'c Cfg<u32>, src: &'c str) -> Vec<Node<'c, u32>> {
+    let completions = cfg_toy::parse_earley(cfg, src.as_bytes(), 256, ());
+    cfg_toy::trace_to_ast(cfg, src.as_bytes(), &completions, &256)
+}
+
+/// Everything recognized, as (sym, start, end, rule)
+pub fn chart(src: &[u8], completions: &Completions<'_, u32>) -> Vec<(u32, usize, usize, u32)> {
+    let mut chart = (0..=src.len())
+        .flat_map(|end| completions.completed_ending_at(end).into_iter().map(move |(sym, start, rule)| (sym, start, end, rule)))
+        .collect::<Vec<_>>();
+    chart.sort();
+    chart
+}
+
+/// Check that two parsers of `src` as nonterminal 256 agree: they fail at the same
+/// position, or they recognize the same items and build the same tree from them.
+pub fn assert_same_parse<'c>(
+    cfg: &'c Cfg<u32>,
+    src: &'c [u8],
+    expected: Result<Completions<'c, u32>, ParseError>,
+    actual: Result<Completions<'c, u32>, ParseError>,
+) {
+    match (expected, actual) {
+        (Ok(expected), Ok(actual)) => {
+            assert_eq!(chart(src, &actual), chart(src, &expected));
+            let shape = |completions| {
+                cfg_toy::trace_to_ast(cfg, src, completions, &256)
+                    .iter()
+                    .map(|node| (node.rule, node.start, node.end, node.children))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(shape(&actual), shape(&expected));
+        }
+        (expected, actual) => assert_eq!(actual.err(), expected.err()),
+    }
+}
+
+/// A small JSON grammar, with a few letters and digits to keep the tables small
+pub fn json_grammar() -> Cfg<u32> {
+    cfg_toy::cfg! {
+        json value object members member array elements element string characters character
+        number digits digit fraction ws;
+
+        json ::= element.
+
+        value ::= object.
+        value ::= array.
+        value ::= string.
+        value ::= number.
+        value ::= "true".
+        value ::= "null".
+
+        object ::= "{" ws "}".
+        object ::= "{" members "}".
+        members ::= member.
+        members ::= member "," members.
+        member ::= ws string ws ":" element.
+
+        array ::= "[" ws "]".
+        array ::= "[" elements "]".
+        elements ::= element.
+        elements ::= element "," elements.
+        element ::= ws value ws.
+
+        string ::= "\"" characters "\"".
+        characters ::= .
+        characters ::= character characters.
+        character ::= "a".
+        character ::= "b".
+        character ::= " ".
+
+        number ::= digit digits fraction.
+        digits ::= .
+        digits ::= digit digits.
+        digit ::= "0".
+        digit ::= "1".
+        fraction ::= .
+        fraction ::= "." digit digits.
+
+        ws ::= .
+        ws ::= " " ws.
+    }
+    .0
+}
@@ -0,0 +1,79 @@
+use cfg_toy::compiled::CompiledGrammar;
+
+mod common;
+use common::{assert_same_parse, logic_grammar};
+
+#[test]
+fn same_as_parse_earley() {
+    // `a` is only nullable through `b`, which has to be skipped at the end of the input too
+    let (nullable_chain, _) = cfg_toy::cfg! {
+        s a b;
+
+        s ::= "x" a .
+        a ::= b .
+        b ::= .
+    };
+    assert!(cfg_toy::try_parse_earley(&nullable_chain, b"x", 256, ()).is_ok());
+    let logic = ["true", "not  not false", "true or (ab and false)or  baba", "(true or", "true andfalse"];
+    for (cfg, srcs) in [(logic_grammar().0, &logic[..]), (nullable_chain, &["x", "", "xx"])] {
+        let compiled = CompiledGrammar::new(&cfg);
+        for src in srcs {
+            let src = src.as_bytes();
+            assert_same_parse(&cfg, src, cfg_toy::try_parse_earley(&cfg, src, 256, ()), compiled.try_parse(src, 256, ()));
+        }
+    }
+}
+
+#[test]
+fn trailing_nullable() {
+    let (cfg, _) = cfg_toy::cfg! {
+        list item;
+
+        list ::= item "," list .
+        list ::= item .
+        item ::= "a" item .
+        item ::= .
+    };
+    let compiled = CompiledGrammar::new(&cfg);
+    for src in ["", ",", "a,", "a,a,,"] {
+        let completions = compiled.parse(src.as_bytes(), 256, ());
+        let ast = cfg_toy::trace_to_ast(&cfg, src.as_bytes(), &completions, &256);
+        assert_eq!((ast[0].start, ast[0].end), (0, src.len()));
+        assert_eq!(ast.iter().filter(|node| node.rule == 0).count(), src.matches(',').count());
+    }
+    assert_eq!(compiled.try_parse(b"a,b", 256, ()).err().map(|e| e.position), Some(2));
+}
+
+#[test]
+fn terminal_classes() {
+    let (cfg, _) = cfg_toy::cfg! {
+        list item number digit pair;
+
+        list ::= item "," list .
+        list ::= item .
+        item ::= number .
+        item ::= pair .
+        number ::= digit number .
+        number ::= digit .
+        digit ::= "0" . digit ::= "1" . digit ::= "2" . digit ::= "3" . digit ::= "4" .
+        digit ::= "5" . digit ::= "6" . digit ::= "7" . digit ::= "8" . digit ::= "9" .
+        // Every combination of "a" and "b" is there, but not "c" "c"
+        pair ::= "(" "a" "a" ")" . pair ::= "(" "a" "b" ")" . pair ::= "(" "a" "c" ")" .
+        pair ::= "(" "b" "a" ")" . pair ::= "(" "b" "b" ")" . pair ::= "(" "b" "c" ")" .
+        pair ::= "(" "c" "a" ")" . pair ::= "(" "c" "b" ")" .
+        pair ::= "(" number "a" ")" . pair ::= "(" number "b" ")" .
+    };
+    let compiled = CompiledGrammar::new(&cfg);
+    // The digits, "a" and "b", "c", "(", ")" and ","
+    assert_eq!(compiled.classes(), 6);
+    let class = |terminal: u8| compiled.classify(&terminal).map(|(class, _)| class);
+    assert!((b'0'..=b'9').all(|digit| class(digit) == class(b'0')));
+    assert_eq!(class(b'a'), class(b'b'));
+    assert_ne!(class(b'a'), class(b'c'));
+    assert_eq!(class(b'x'), None);
+
+    for src in ["12,(ab),(ca)", "(10b),(ba),9", "(cc)", "(bb),(1c)", "12x"] {
+        let src = src.as_bytes();
+        assert_same_parse(&cfg, src, cfg_toy::try_parse_earley(&cfg, src, 256, ()), compiled.try_parse(src, 256, ()));
+    }
+}
@@ -0,0 +1,95 @@
+use cfg_toy::cst::{CstBuilder, Element};
+
+mod common;
+use common::logic_grammar;
+
+#[test]
+fn round_trip_source() {
+    let (cfg, _) = logic_grammar();
+    let gap = 260;
+    for src in [
+        "true",
+        "true and\n  \n false",
+        "not (not  true)  or\nfalse",
+        "(true or ab)",
+    ] {
+        let src = src.as_bytes();
+        let completions = cfg_toy::parse_earley(&cfg, src, 256, ());
+        let ast = cfg_toy::trace_to_ast(&cfg, src, &completions, &256);
+        let cst = CstBuilder::new(&cfg).trivia(gap).build(&ast);
+        assert_eq!(cst.text(src), src);
+
+        // Every byte is in exactly one token or piece of trivia
+        let mut covered = vec![0; src.len()];
+        for token in &cst.tokens {
+            (token.start..token.end).for_each(|i| covered[i] += 1);
+        }
+        for trivia in &cst.trivia {
+            assert_eq!(trivia.nt, gap);
+            (trivia.start..trivia.end).for_each(|i| covered[i] += 1);
+        }
+        assert!(covered.iter().all(|&n| n == 1), "{covered:?}");
+
+        // Trivia isn't in the tree
+        for element in &cst.elements {
+            if let Element::Node(node) = element {
+                assert_ne!(node.nt, gap);
+            }
+        }
+    }
+}
+
+// The text of each token, with its leading and trailing trivia
+fn tokens(cst: &cfg_toy::cst::Cst<'_, u32>, src: &[u8]) -> Vec<(String, String, String)> {
+    let text = |span: std::ops::Range<usize>| String::from_utf8(src[span].to_vec()).unwrap();
+    let trivia = |range: std::ops::Range<usize>| cst.trivia[range].iter().map(|t| text(t.start..t.end)).collect::<String>();
+    cst.tokens
+        .iter()
+        .map(|token| (trivia(token.leading.clone()), text(token.start..token.end), trivia(token.trailing.clone())))
+        .collect()
+}
+
+#[test]
+fn attach_trivia() {
+    let (cfg, _) = logic_grammar();
+    let src = b"true  and\nfalse";
+    let completions = cfg_toy::parse_earley(&cfg, src, 256, ());
+    let ast = cfg_toy::trace_to_ast(&cfg, src, &completions, &256);
+    let cst = CstBuilder::new(&cfg).trivia(260).trivia(259).build(&ast);
+    let owned = |(leading, text, trailing): (&str, &str, &str)| (leading.to_string(), text.to_string(), trailing.to_string());
+    assert_eq!(
+        tokens(&cst, src),
+        [("", "true", "  "), ("", "and", "\n"), ("", "false", "")].map(owned)
+    );
+    assert_eq!(cst.tokens[0].full_span(&cst.trivia), 0..6);
+
+    // The root's child is the `and_expr`, and the gaps inside it aren't nodes
+    let root = cst.children(0).collect::<Vec<_>>();
+    assert_eq!(root.len(), 1);
+    let Element::Node(and_expr) = &cst.elements[root[0]] else {
+        panic!("expected a node");
+    };
+    assert_eq!((and_expr.nt, and_expr.start, and_expr.end), (257, 0, 15));
+
+    // Trivia at the start leads the first token
+    let src = b" and  ";
+    let completions = cfg_toy::parse_earley(&cfg, src, 261, ());
+    let ast = cfg_toy::trace_to_ast(&cfg, src, &completions, &261);
+    let cst = CstBuilder::new(&cfg).trivia(260).build(&ast);
+    assert_eq!(tokens(&cst, src), [(" ", "and", "  ")].map(owned));
+    assert_eq!(cst.tokens[0].full_span(&cst.trivia), 0..6);
+}
+
+#[test]
+fn trivia_only() {
+    let (cfg, _) = logic_grammar();
+    let src = b"  ";
+    let completions = cfg_toy::parse_earley(&cfg, src, 260, ());
+    let ast = cfg_toy::trace_to_ast(&cfg, src, &completions, &260);
+    // The root itself is built as a node, it's the ws inside that's trivia
+    let cst = CstBuilder::new(&cfg).trivia(259).build(&ast);
+    assert_eq!(cst.text(src), src);
+    assert_eq!(cst.tokens.len(), 1);
+    assert_eq!(cst.tokens[0].start, 2);
+    assert_eq!(cst.tokens[0].leading, 0..2);
+}
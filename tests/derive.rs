@@ -0,0 +1,120 @@
+use cfg_toy::derive::{Grammar, Parser};
+
+#[derive(Debug, PartialEq, Grammar)]
+enum Expr {
+    #[grammar(_ " or " _)]
+    Or(Box<Expr>, AndExpr),
+    And(AndExpr),
+}
+#[derive(Debug, PartialEq, Grammar)]
+enum AndExpr {
+    #[grammar(left " and " right)]
+    And { right: Primary, left: Box<AndExpr> },
+    Primary(Primary),
+}
+#[derive(Debug, PartialEq, Grammar)]
+enum Primary {
+    #[grammar("not " _)]
+    Not(Box<Primary>),
+    #[grammar("(" _ ")")]
+    Paren(Box<Expr>),
+    #[grammar("true")]
+    True,
+    #[grammar("false")]
+    False,
+}
+
+#[derive(Debug, PartialEq, Grammar)]
+#[grammar("[" items "]")]
+struct List {
+    items: Vec<Item>,
+}
+#[derive(Debug, PartialEq, Grammar)]
+#[grammar(value separator)]
+struct Item {
+    value: Primary,
+    separator: Option<Comma>,
+}
+#[derive(Debug, PartialEq, Grammar)]
+#[grammar(",")]
+struct Comma;
+
+// A field type with a function type argument, whose `->` doesn't close the `<`
+#[derive(Debug, PartialEq)]
+struct Tagged<F, T>(T, std::marker::PhantomData<F>);
+impl<F: 'static, T: Grammar + 'static> Grammar for Tagged<F, T> {
+    fn add_rules(builder: &mut cfg_toy::derive::GrammarBuilder, nt: u32) {
+        T::add_rules(builder, nt)
+    }
+    fn from_tree(tree: cfg_toy::derive::Tree<'_, '_>) -> Self {
+        Tagged(T::from_tree(tree), std::marker::PhantomData)
+    }
+}
+#[derive(Debug, PartialEq, Grammar)]
+#[grammar(value "!" rest)]
+struct Shout {
+    value: Tagged<fn(u8) -> u8, Primary>,
+    rest: Option<Comma>,
+}
+
+#[test]
+fn parse_logic() {
+    let parser = Parser::<Expr>::new();
+    assert_eq!(parser.names(), ["Expr", "AndExpr", "Primary"]);
+    let expr = parser.parse(b"true or not (false and true)").unwrap();
+    assert_eq!(
+        expr,
+        Expr::Or(
+            Box::new(Expr::And(AndExpr::Primary(Primary::True))),
+            AndExpr::Primary(Primary::Not(Box::new(Primary::Paren(Box::new(Expr::And(
+                AndExpr::And {
+                    left: Box::new(AndExpr::Primary(Primary::False)),
+                    right: Primary::True,
+                }
+            ))))))
+        )
+    );
+}
+
+#[test]
+fn parse_repetition() {
+    let parser = Parser::<List>::new();
+    let list = parser.parse(b"[true,false,true]").unwrap();
+    let values = list.items.iter().map(|item| &item.value).collect::<Vec<_>>();
+    assert_eq!(values, [&Primary::True, &Primary::False, &Primary::True]);
+    assert_eq!(list.items[2].separator, None);
+    assert_eq!(parser.parse(b"[]").unwrap(), List { items: vec![] });
+}
+
+#[test]
+fn label_named_fields() {
+    let parser = Parser::<AndExpr>::new();
+    let and = &parser.cfg().rules[0];
+    assert_eq!(and.fields, [("left", 0), ("right", 6)]);
+
+    struct Right(Vec<u8>);
+    impl Grammar for Right {
+        fn add_rules(builder: &mut cfg_toy::derive::GrammarBuilder, nt: u32) {
+            AndExpr::add_rules(builder, nt)
+        }
+        fn from_tree(tree: cfg_toy::derive::Tree<'_, '_>) -> Self {
+            Right(tree.child_by_field("right").unwrap().text().to_vec())
+        }
+    }
+    let right = Parser::<Right>::new().parse(b"true and not false").unwrap();
+    assert_eq!(right.0, b"not false");
+}
+
+#[test]
+fn report_parse_errors() {
+    let parser = Parser::<Expr>::new();
+    assert_eq!(parser.parse(b"true or nope").unwrap_err().position, 10);
+    assert!(parser.parse(b"true or").is_err());
+}
+
+#[test]
+fn function_type_arguments() {
+    let shout = Parser::<Shout>::new().parse(b"true!,").unwrap();
+    assert_eq!(shout.value.0, Primary::True);
+    assert_eq!(shout.rest, Some(Comma));
+}
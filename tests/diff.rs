@@ -0,0 +1,81 @@
+use cfg_toy::diff::{Edit, diff};
+
+mod common;
+use common::{logic_grammar, parse};
+
+type Span = Option<(usize, usize)>;
+
+// The edits as (kind, old span, new span)
+fn edits(cfg: &cfg_toy::grammar::Cfg<u32>, old: &str, new: &str) -> Vec<(&'static str, Span, Span)> {
+    let (old, new) = (parse(cfg, old), parse(cfg, new));
+    let diff = diff(cfg, &old, &new);
+    diff.edits
+        .iter()
+        .map(|edit| match *edit {
+            Edit::Insert { new, .. } => ("insert", None, Some((new.start, new.end))),
+            Edit::Delete { old } => ("delete", Some((old.start, old.end)), None),
+            Edit::Move { old, new } => ("move", Some((old.start, old.end)), Some((new.start, new.end))),
+            Edit::Update { old, new } => ("update", Some((old.start, old.end)), Some((new.start, new.end))),
+        })
+        .collect()
+}
+
+#[test]
+fn identical() {
+    let (cfg, _) = logic_grammar();
+    let src = "not true and (false or true)";
+    let ast = parse(&cfg, src);
+    let diff = diff(&cfg, &ast, &parse(&cfg, src));
+    assert!(diff.edits.is_empty());
+    assert_eq!(diff.matches, (0..ast.len()).map(|i| (i, i)).collect::<Vec<_>>());
+}
+
+#[test]
+fn swap_operands() {
+    let (cfg, _) = logic_grammar();
+    assert_eq!(
+        edits(&cfg, "true or false", "false or true"),
+        [
+            ("move", Some((8, 13)), Some((0, 5))),
+            ("move", Some((0, 4)), Some((9, 13))),
+        ]
+    );
+}
+
+#[test]
+fn insert_and_delete() {
+    let (cfg, _) = logic_grammar();
+    let inserted = edits(&cfg, "true and false", "true and not false");
+    assert_eq!(
+        inserted,
+        [
+            ("insert", None, Some((9, 18))),
+            ("insert", None, Some((9, 13))),
+            ("insert", None, Some((12, 13))),
+            ("insert", None, Some((12, 13))),
+            // `false` is under the new `not`
+            ("move", Some((9, 14)), Some((13, 18))),
+        ]
+    );
+    let deleted = edits(&cfg, "true and not false", "true and false");
+    assert_eq!(
+        deleted,
+        [
+            ("move", Some((13, 18)), Some((9, 14))),
+            ("delete", Some((9, 18)), None),
+            ("delete", Some((9, 13)), None),
+            ("delete", Some((12, 13)), None),
+            ("delete", Some((12, 13)), None),
+        ]
+    );
+}
+
+#[test]
+fn update_rule() {
+    let (cfg, _) = logic_grammar();
+    let edits = edits(&cfg, "true and false", "true or false");
+    // The root went from `expr ::= and_expr` to `expr ::= and_expr or expr`
+    assert_eq!(edits[0], ("update", Some((0, 14)), Some((0, 13))));
+    assert!(edits.contains(&("delete", Some((4, 9)), None)));
+    assert!(edits.contains(&("insert", None, Some((4, 8)))));
+}
@@ -0,0 +1,89 @@
+#[test]
+fn ast_nodes_and_edges() {
+    let (cfg, names) = cfg_toy::cfg! {
+        pair item;
+        item ::= "a" .
+        item ::= "\"" .
+        pair ::= "(" item "," item ")" .
+    };
+    let src = br#"(a,")"#;
+    let completions = cfg_toy::parse_earley(&cfg, src, 256, ());
+    let ast = cfg_toy::trace_to_ast(&cfg, src, &completions, &256);
+    let dot = cfg_toy::dot::ast(&cfg, &names, src, &ast);
+    assert_eq!(
+        dot,
+        r#"digraph ast {
+  node [shape=box, fontname=monospace];
+  n0 [label="pair\n0..5\n\"(a,\\\")\""];
+  n0 -> n1;
+  n0 -> n2;
+  n1 [label="item\n1..2\n\"a\""];
+  n2 [label="item\n3..4\n\"\\\"\""];
+}
+"#
+    );
+}
+
+#[test]
+fn forest_shows_ambiguity() {
+    let (cfg, names) = cfg_toy::cfg! {
+        expr;
+        expr ::= expr "+" expr .
+        expr ::= "a" .
+    };
+    let src = b"a+a+a";
+    let completions = cfg_toy::parse_earley(&cfg, src, 256, ());
+    let dot = cfg_toy::dot::forest(&cfg, &names, src, &completions, 256);
+    // Both ways of splitting the root are children of its packed node
+    for edge in [
+        "p0_0_5 -> s256_0_1 [label=\"0\"]",
+        "p0_0_5 -> s256_0_3 [label=\"0\"]",
+        "p0_0_5 -> s256_2_5 [label=\"2\"]",
+        "p0_0_5 -> s256_4_5 [label=\"2\"]",
+    ] {
+        assert!(dot.contains(edge), "missing {edge} in\n{dot}");
+    }
+    assert!(dot.contains("s256_0_5 [label=\"expr 0..5\"]"));
+    // But spans that aren't part of a full parse aren't
+    assert!(!dot.contains("s256_1_"));
+}
+
+#[test]
+fn chart_shows_bypasses() {
+    let (cfg, names) = cfg_toy::cfg! {
+        list;
+        list ::= "a" list .
+        list ::= "a" .
+    };
+    let src = b"aaaa";
+    let completions = cfg_toy::parse_earley(&cfg, src, 256, ());
+    let dot = cfg_toy::dot::chart(&cfg, &names, &completions);
+    assert!(dot.contains("subgraph cluster_4"));
+    assert!(dot.contains(r#"w0 [label="list ::= 'a' • list  (0)\nwaits for list"];"#));
+    assert!(dot.contains(r#"c4_1 [label="list ::= 'a' •  (3)", style=rounded];"#));
+    // The right recursion is bypassed straight to the item for the whole list
+    assert!(dot.contains(r#"f0 [label="list ::= 'a' list •  (0)", style=dashed];"#));
+    assert!(dot.contains(r#"w2 -> f0 [style=dashed, label="bypass"];"#));
+}
+
+#[test]
+fn forest_keeps_every_empty_rule() {
+    let (cfg, names) = cfg_toy::cfg! {
+        s a;
+        s ::= a "x" a .
+        a ::= .
+        a ::= .
+    };
+    let src = b"x";
+    let completions = cfg_toy::parse_earley(&cfg, src, 256, ());
+    let dot = cfg_toy::dot::forest(&cfg, &names, src, &completions, 256);
+    // The empty rules are told apart by their index rather than by their parts
+    for edge in [
+        "s257_0_0 -> p1_0_0",
+        "s257_0_0 -> p2_0_0",
+        "s257_1_1 -> p1_1_1",
+        "s257_1_1 -> p2_1_1",
+    ] {
+        assert!(dot.contains(edge), "missing {edge} in\n{dot}");
+    }
+}
@@ -0,0 +1,35 @@
+use cfg_toy::visit::child_by_field;
+
+#[test]
+fn labelled_parts() {
+    let (cfg, _) = cfg_toy::cfg! {
+        object members member ws string value;
+
+        ws ::= .
+        ws ::= " " ws .
+        string ::= "\"" "k" "\"" .
+        value ::= "1" .
+        value ::= object .
+        member ::= ws key:string ws ":" ws value:value ws .
+        members ::= member .
+        members ::= first:member "," rest:members .
+        object ::= "{" members "}" .
+    };
+    let member = 258;
+    let rule = cfg.rules.iter().find(|rule| rule.for_nt == member).unwrap();
+    assert_eq!(rule.fields, [("key", 1), ("value", 5)]);
+    assert_eq!(rule.field("value"), Some(5));
+    assert_eq!(rule.field("missing"), None);
+
+    let src = br#"{ "k": 1, "k" : {"k":1} }"#;
+    let completions = cfg_toy::parse_earley(&cfg, src, 256, ());
+    let ast = cfg_toy::trace_to_ast(&cfg, src, &completions, &256);
+    let members = cfg_toy::visit::children(&ast).next().unwrap();
+    let first = child_by_field(members, "first").unwrap();
+    let key = child_by_field(first, "key").unwrap();
+    assert_eq!(&src[key[0].start..key[0].end], br#""k""#);
+    let rest = child_by_field(members, "rest").unwrap();
+    let value = child_by_field(cfg_toy::visit::children(rest).next().unwrap(), "value").unwrap();
+    assert_eq!(&src[value[0].start..value[0].end], br#"{"k":1}"#);
+    assert!(child_by_field(value, "key").is_none());
+}
@@ -0,0 +1,251 @@
+// Generated by cfg_toy::codegen, do not edit.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
+pub enum Expr {
+    /// `expr ::= and_expr or expr`
+    AndExprOrExpr(::std::boxed::Box<AndExpr>, ::std::boxed::Box<Or>, ::std::boxed::Box<Expr>),
+    /// `expr ::= and_expr`
+    AndExpr(::std::boxed::Box<AndExpr>),
+}
+
+impl<S> ::core::convert::TryFrom<&[::cfg_toy::Node<'_, S>]> for Expr {
+    type Error = ::cfg_toy::codegen::ConvertError;
+    fn try_from(ast: &[::cfg_toy::Node<'_, S>]) -> ::core::result::Result<Self, Self::Error> {
+        let Some(node) = ast.first() else {
+            return Err(::cfg_toy::codegen::ConvertError { rule: None, nonterminal: "expr" });
+        };
+        #[allow(unused_mut, unused_variables)]
+        let mut children = ::cfg_toy::visit::children(ast);
+        match (node.rule, node.children) {
+            (0, 3) => Ok(Self::AndExprOrExpr(::std::boxed::Box::new(::core::convert::TryFrom::try_from(children.next().unwrap())?), ::std::boxed::Box::new(::core::convert::TryFrom::try_from(children.next().unwrap())?), ::std::boxed::Box::new(::core::convert::TryFrom::try_from(children.next().unwrap())?))),
+            (1, 1) => Ok(Self::AndExpr(::std::boxed::Box::new(::core::convert::TryFrom::try_from(children.next().unwrap())?))),
+            (rule, _) => Err(::cfg_toy::codegen::ConvertError { rule: Some(rule), nonterminal: "expr" }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
+pub enum AndExpr {
+    /// `and_expr ::= primary and and_expr`
+    PrimaryAndAndExpr(::std::boxed::Box<Primary>, ::std::boxed::Box<And>, ::std::boxed::Box<AndExpr>),
+    /// `and_expr ::= primary`
+    Primary(::std::boxed::Box<Primary>),
+}
+
+impl<S> ::core::convert::TryFrom<&[::cfg_toy::Node<'_, S>]> for AndExpr {
+    type Error = ::cfg_toy::codegen::ConvertError;
+    fn try_from(ast: &[::cfg_toy::Node<'_, S>]) -> ::core::result::Result<Self, Self::Error> {
+        let Some(node) = ast.first() else {
+            return Err(::cfg_toy::codegen::ConvertError { rule: None, nonterminal: "and_expr" });
+        };
+        #[allow(unused_mut, unused_variables)]
+        let mut children = ::cfg_toy::visit::children(ast);
+        match (node.rule, node.children) {
+            (2, 3) => Ok(Self::PrimaryAndAndExpr(::std::boxed::Box::new(::core::convert::TryFrom::try_from(children.next().unwrap())?), ::std::boxed::Box::new(::core::convert::TryFrom::try_from(children.next().unwrap())?), ::std::boxed::Box::new(::core::convert::TryFrom::try_from(children.next().unwrap())?))),
+            (3, 1) => Ok(Self::Primary(::std::boxed::Box::new(::core::convert::TryFrom::try_from(children.next().unwrap())?))),
+            (rule, _) => Err(::cfg_toy::codegen::ConvertError { rule: Some(rule), nonterminal: "and_expr" }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
+pub enum Primary {
+    /// `primary ::= not primary`
+    NotPrimary(::std::boxed::Box<Not>, ::std::boxed::Box<Primary>),
+    /// `primary ::= "(" expr ")"`
+    Expr(::std::boxed::Box<Expr>),
+    /// `primary ::= "true"`
+    True,
+    /// `primary ::= "false"`
+    False,
+    /// `primary ::= ident`
+    Ident(::std::boxed::Box<Ident>),
+}
+
+impl<S> ::core::convert::TryFrom<&[::cfg_toy::Node<'_, S>]> for Primary {
+    type Error = ::cfg_toy::codegen::ConvertError;
+    fn try_from(ast: &[::cfg_toy::Node<'_, S>]) -> ::core::result::Result<Self, Self::Error> {
+        let Some(node) = ast.first() else {
+            return Err(::cfg_toy::codegen::ConvertError { rule: None, nonterminal: "primary" });
+        };
+        #[allow(unused_mut, unused_variables)]
+        let mut children = ::cfg_toy::visit::children(ast);
+        match (node.rule, node.children) {
+            (4, 2) => Ok(Self::NotPrimary(::std::boxed::Box::new(::core::convert::TryFrom::try_from(children.next().unwrap())?), ::std::boxed::Box::new(::core::convert::TryFrom::try_from(children.next().unwrap())?))),
+            (5, 1) => Ok(Self::Expr(::std::boxed::Box::new(::core::convert::TryFrom::try_from(children.next().unwrap())?))),
+            (6, 0) => Ok(Self::True),
+            (7, 0) => Ok(Self::False),
+            (8, 1) => Ok(Self::Ident(::std::boxed::Box::new(::core::convert::TryFrom::try_from(children.next().unwrap())?))),
+            (rule, _) => Err(::cfg_toy::codegen::ConvertError { rule: Some(rule), nonterminal: "primary" }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
+pub enum Ws {
+    /// `ws ::= " "`
+    Alt1,
+    /// `ws ::= "\n"`
+    Alt2,
+}
+
+impl<S> ::core::convert::TryFrom<&[::cfg_toy::Node<'_, S>]> for Ws {
+    type Error = ::cfg_toy::codegen::ConvertError;
+    fn try_from(ast: &[::cfg_toy::Node<'_, S>]) -> ::core::result::Result<Self, Self::Error> {
+        let Some(node) = ast.first() else {
+            return Err(::cfg_toy::codegen::ConvertError { rule: None, nonterminal: "ws" });
+        };
+        #[allow(unused_mut, unused_variables)]
+        let mut children = ::cfg_toy::visit::children(ast);
+        match (node.rule, node.children) {
+            (9, 0) => Ok(Self::Alt1),
+            (10, 0) => Ok(Self::Alt2),
+            (rule, _) => Err(::cfg_toy::codegen::ConvertError { rule: Some(rule), nonterminal: "ws" }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
+pub enum Gap {
+    /// `gap ::= ws`
+    Ws(::std::boxed::Box<Ws>),
+    /// `gap ::= ws gap`
+    WsGap(::std::boxed::Box<Ws>, ::std::boxed::Box<Gap>),
+}
+
+impl<S> ::core::convert::TryFrom<&[::cfg_toy::Node<'_, S>]> for Gap {
+    type Error = ::cfg_toy::codegen::ConvertError;
+    fn try_from(ast: &[::cfg_toy::Node<'_, S>]) -> ::core::result::Result<Self, Self::Error> {
+        let Some(node) = ast.first() else {
+            return Err(::cfg_toy::codegen::ConvertError { rule: None, nonterminal: "gap" });
+        };
+        #[allow(unused_mut, unused_variables)]
+        let mut children = ::cfg_toy::visit::children(ast);
+        match (node.rule, node.children) {
+            (11, 1) => Ok(Self::Ws(::std::boxed::Box::new(::core::convert::TryFrom::try_from(children.next().unwrap())?))),
+            (12, 2) => Ok(Self::WsGap(::std::boxed::Box::new(::core::convert::TryFrom::try_from(children.next().unwrap())?), ::std::boxed::Box::new(::core::convert::TryFrom::try_from(children.next().unwrap())?))),
+            (rule, _) => Err(::cfg_toy::codegen::ConvertError { rule: Some(rule), nonterminal: "gap" }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
+pub enum And {
+    /// `and ::= gap "and" gap`
+    GapAndGap(::std::boxed::Box<Gap>, ::std::boxed::Box<Gap>),
+}
+
+impl<S> ::core::convert::TryFrom<&[::cfg_toy::Node<'_, S>]> for And {
+    type Error = ::cfg_toy::codegen::ConvertError;
+    fn try_from(ast: &[::cfg_toy::Node<'_, S>]) -> ::core::result::Result<Self, Self::Error> {
+        let Some(node) = ast.first() else {
+            return Err(::cfg_toy::codegen::ConvertError { rule: None, nonterminal: "and" });
+        };
+        #[allow(unused_mut, unused_variables)]
+        let mut children = ::cfg_toy::visit::children(ast);
+        match (node.rule, node.children) {
+            (13, 2) => Ok(Self::GapAndGap(::std::boxed::Box::new(::core::convert::TryFrom::try_from(children.next().unwrap())?), ::std::boxed::Box::new(::core::convert::TryFrom::try_from(children.next().unwrap())?))),
+            (rule, _) => Err(::cfg_toy::codegen::ConvertError { rule: Some(rule), nonterminal: "and" }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
+pub enum Or {
+    /// `or ::= gap "or" gap`
+    GapOrGap(::std::boxed::Box<Gap>, ::std::boxed::Box<Gap>),
+}
+
+impl<S> ::core::convert::TryFrom<&[::cfg_toy::Node<'_, S>]> for Or {
+    type Error = ::cfg_toy::codegen::ConvertError;
+    fn try_from(ast: &[::cfg_toy::Node<'_, S>]) -> ::core::result::Result<Self, Self::Error> {
+        let Some(node) = ast.first() else {
+            return Err(::cfg_toy::codegen::ConvertError { rule: None, nonterminal: "or" });
+        };
+        #[allow(unused_mut, unused_variables)]
+        let mut children = ::cfg_toy::visit::children(ast);
+        match (node.rule, node.children) {
+            (14, 2) => Ok(Self::GapOrGap(::std::boxed::Box::new(::core::convert::TryFrom::try_from(children.next().unwrap())?), ::std::boxed::Box::new(::core::convert::TryFrom::try_from(children.next().unwrap())?))),
+            (rule, _) => Err(::cfg_toy::codegen::ConvertError { rule: Some(rule), nonterminal: "or" }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
+pub enum Not {
+    /// `not ::= "not" gap`
+    NotGap(::std::boxed::Box<Gap>),
+}
+
+impl<S> ::core::convert::TryFrom<&[::cfg_toy::Node<'_, S>]> for Not {
+    type Error = ::cfg_toy::codegen::ConvertError;
+    fn try_from(ast: &[::cfg_toy::Node<'_, S>]) -> ::core::result::Result<Self, Self::Error> {
+        let Some(node) = ast.first() else {
+            return Err(::cfg_toy::codegen::ConvertError { rule: None, nonterminal: "not" });
+        };
+        #[allow(unused_mut, unused_variables)]
+        let mut children = ::cfg_toy::visit::children(ast);
+        match (node.rule, node.children) {
+            (15, 1) => Ok(Self::NotGap(::std::boxed::Box::new(::core::convert::TryFrom::try_from(children.next().unwrap())?))),
+            (rule, _) => Err(::cfg_toy::codegen::ConvertError { rule: Some(rule), nonterminal: "not" }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
+pub enum Alpha {
+    /// `alpha ::= "a"`
+    A,
+    /// `alpha ::= "b"`
+    B,
+}
+
+impl<S> ::core::convert::TryFrom<&[::cfg_toy::Node<'_, S>]> for Alpha {
+    type Error = ::cfg_toy::codegen::ConvertError;
+    fn try_from(ast: &[::cfg_toy::Node<'_, S>]) -> ::core::result::Result<Self, Self::Error> {
+        let Some(node) = ast.first() else {
+            return Err(::cfg_toy::codegen::ConvertError { rule: None, nonterminal: "alpha" });
+        };
+        #[allow(unused_mut, unused_variables)]
+        let mut children = ::cfg_toy::visit::children(ast);
+        match (node.rule, node.children) {
+            (16, 0) => Ok(Self::A),
+            (17, 0) => Ok(Self::B),
+            (rule, _) => Err(::cfg_toy::codegen::ConvertError { rule: Some(rule), nonterminal: "alpha" }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
+pub enum Ident {
+    /// `ident ::= alpha ident`
+    AlphaIdent(::std::boxed::Box<Alpha>, ::std::boxed::Box<Ident>),
+    /// `ident ::= alpha`
+    Alpha(::std::boxed::Box<Alpha>),
+}
+
+impl<S> ::core::convert::TryFrom<&[::cfg_toy::Node<'_, S>]> for Ident {
+    type Error = ::cfg_toy::codegen::ConvertError;
+    fn try_from(ast: &[::cfg_toy::Node<'_, S>]) -> ::core::result::Result<Self, Self::Error> {
+        let Some(node) = ast.first() else {
+            return Err(::cfg_toy::codegen::ConvertError { rule: None, nonterminal: "ident" });
+        };
+        #[allow(unused_mut, unused_variables)]
+        let mut children = ::cfg_toy::visit::children(ast);
+        match (node.rule, node.children) {
+            (18, 2) => Ok(Self::AlphaIdent(::std::boxed::Box::new(::core::convert::TryFrom::try_from(children.next().unwrap())?), ::std::boxed::Box::new(::core::convert::TryFrom::try_from(children.next().unwrap())?))),
+            (19, 1) => Ok(Self::Alpha(::std::boxed::Box::new(::core::convert::TryFrom::try_from(children.next().unwrap())?))),
+            (rule, _) => Err(::cfg_toy::codegen::ConvertError { rule: Some(rule), nonterminal: "ident" }),
+        }
+    }
+}
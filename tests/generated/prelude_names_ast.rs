@@ -0,0 +1,95 @@
+// Generated by cfg_toy::codegen, do not edit.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
+pub enum Result {
+    /// `result ::= box "," try_from`
+    BoxTryFrom(::std::boxed::Box<Box>, ::std::boxed::Box<TryFrom>),
+    /// `result ::= option`
+    Option(::std::boxed::Box<Option>),
+}
+
+impl<S> ::core::convert::TryFrom<&[::cfg_toy::Node<'_, S>]> for Result {
+    type Error = ::cfg_toy::codegen::ConvertError;
+    fn try_from(ast: &[::cfg_toy::Node<'_, S>]) -> ::core::result::Result<Self, Self::Error> {
+        let Some(node) = ast.first() else {
+            return Err(::cfg_toy::codegen::ConvertError { rule: None, nonterminal: "result" });
+        };
+        #[allow(unused_mut, unused_variables)]
+        let mut children = ::cfg_toy::visit::children(ast);
+        match (node.rule, node.children) {
+            (0, 2) => Ok(Self::BoxTryFrom(::std::boxed::Box::new(::core::convert::TryFrom::try_from(children.next().unwrap())?), ::std::boxed::Box::new(::core::convert::TryFrom::try_from(children.next().unwrap())?))),
+            (1, 1) => Ok(Self::Option(::std::boxed::Box::new(::core::convert::TryFrom::try_from(children.next().unwrap())?))),
+            (rule, _) => Err(::cfg_toy::codegen::ConvertError { rule: Some(rule), nonterminal: "result" }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
+pub enum Box {
+    /// `box ::= "b"`
+    B,
+}
+
+impl<S> ::core::convert::TryFrom<&[::cfg_toy::Node<'_, S>]> for Box {
+    type Error = ::cfg_toy::codegen::ConvertError;
+    fn try_from(ast: &[::cfg_toy::Node<'_, S>]) -> ::core::result::Result<Self, Self::Error> {
+        let Some(node) = ast.first() else {
+            return Err(::cfg_toy::codegen::ConvertError { rule: None, nonterminal: "box" });
+        };
+        #[allow(unused_mut, unused_variables)]
+        let mut children = ::cfg_toy::visit::children(ast);
+        match (node.rule, node.children) {
+            (2, 0) => Ok(Self::B),
+            (rule, _) => Err(::cfg_toy::codegen::ConvertError { rule: Some(rule), nonterminal: "box" }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
+pub enum TryFrom {
+    /// `try_from ::= "t" result`
+    TResult(::std::boxed::Box<Result>),
+    /// `try_from ::= ε`
+    Empty,
+}
+
+impl<S> ::core::convert::TryFrom<&[::cfg_toy::Node<'_, S>]> for TryFrom {
+    type Error = ::cfg_toy::codegen::ConvertError;
+    fn try_from(ast: &[::cfg_toy::Node<'_, S>]) -> ::core::result::Result<Self, Self::Error> {
+        let Some(node) = ast.first() else {
+            return Err(::cfg_toy::codegen::ConvertError { rule: None, nonterminal: "try_from" });
+        };
+        #[allow(unused_mut, unused_variables)]
+        let mut children = ::cfg_toy::visit::children(ast);
+        match (node.rule, node.children) {
+            (3, 1) => Ok(Self::TResult(::std::boxed::Box::new(::core::convert::TryFrom::try_from(children.next().unwrap())?))),
+            (4, 0) => Ok(Self::Empty),
+            (rule, _) => Err(::cfg_toy::codegen::ConvertError { rule: Some(rule), nonterminal: "try_from" }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
+pub enum Option {
+    /// `option ::= box`
+    Box(::std::boxed::Box<Box>),
+}
+
+impl<S> ::core::convert::TryFrom<&[::cfg_toy::Node<'_, S>]> for Option {
+    type Error = ::cfg_toy::codegen::ConvertError;
+    fn try_from(ast: &[::cfg_toy::Node<'_, S>]) -> ::core::result::Result<Self, Self::Error> {
+        let Some(node) = ast.first() else {
+            return Err(::cfg_toy::codegen::ConvertError { rule: None, nonterminal: "option" });
+        };
+        #[allow(unused_mut, unused_variables)]
+        let mut children = ::cfg_toy::visit::children(ast);
+        match (node.rule, node.children) {
+            (5, 1) => Ok(Self::Box(::std::boxed::Box::new(::core::convert::TryFrom::try_from(children.next().unwrap())?))),
+            (rule, _) => Err(::cfg_toy::codegen::ConvertError { rule: Some(rule), nonterminal: "option" }),
+        }
+    }
+}
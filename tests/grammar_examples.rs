@@ -44,7 +44,6 @@ fn simple_logic() {
     );
     cfg_toy::parse_earley(&mycfg, "true then".as_bytes(), 256, ());
     let src = "true then".as_bytes();
-    let mut trace = vec![];
     let mycfg = mycfg.map(|&l| cfg_toy::LabelledSymbol {
         symbol: l,
         label: l
@@ -57,15 +56,18 @@ fn simple_logic() {
         label: state_names[0],
     };
     let src = cfg_toy::cast_buf(src);
-    let completions = cfg_toy::parse_earley(&mycfg, src, init_sym.symbol, &mut trace);
-    for &(start, end, state, rule) in &trace {
-        println!("{} {:?} {rule:?}", state_names[state as usize - 256], start..end);
+    let completions = cfg_toy::parse_earley(&mycfg, src, init_sym.symbol, ());
+    for end in 0..=src.len() {
+        for &(state, start, rule) in completions.completed_at(end) {
+            println!("{} {:?} {:?}", state_names[state as usize - 256], start..end, mycfg.rules[rule as usize].parts);
+        }
     }
-    trace.sort_by_key(|m| (m.1, m.2));
-    let ast = cfg_toy::trace_to_ast(&mycfg, src, &trace, &completions, &init_sym);
+    let ast = cfg_toy::trace_to_ast(&mycfg, src, &completions, &init_sym);
     // println!("{ast:?}");
     cfg_toy::print_ast(&ast, 0);
-    panic!();
+    assert_well_formed(&ast, src);
+    // "then" is only reachable through the ambiguous_1 rule
+    assert_eq!(format!("{:?}", ast[0].transition), "[and_expr, gap, ambiguous_1]");
 }
 
 /// S ::= A
@@ -113,15 +115,12 @@ fn aliased_rules() {
     }
     .0;
     let src = "aab".as_bytes();
-    let mut trace = vec![];
-    let completions = cfg_toy::parse_earley(&grammar, src, 256, &mut trace);
-    // for &(start, end, state) in &trace {
-    //     println!("{} {:?}", state_names[state as usize - 256], start..end);
-    // }
-    trace.sort_by_key(|m| (m.1, m.2, -(m.0 as isize)));
-    let ast = cfg_toy::trace_to_ast(&grammar, src, &trace, &completions, &256);
+    let completions = cfg_toy::parse_earley(&grammar, src, 256, ());
+    let ast = cfg_toy::trace_to_ast(&grammar, src, &completions, &256);
     cfg_toy::print_ast(&ast, 0);
-    panic!();
+    assert_well_formed(&ast, src);
+    assert_eq!(ast[0].transition, [b'a' as u32, 257, b'b' as u32]);
+    assert_eq!((ast[1].start, ast[1].end), (1, 2));
 }
 #[test]
 fn right_recursion() {
@@ -134,6 +133,133 @@ fn right_recursion() {
     .0;
     let src =
         br#"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"#;
-    cfg_toy::parse_earley(&cfg, src, 256, ());
-    panic!();
+    let completions = cfg_toy::parse_earley(&cfg, src, 256, ());
+    let ast = cfg_toy::trace_to_ast(&cfg, src, &completions, &256);
+    assert_well_formed(&ast, src);
+    // One node per "a", and the ε at the end
+    assert_eq!(ast.len(), src.len() + 1);
+}
+
+/// Check that every node's rule matches the source it spans, using its children for the nonterminals.
+fn assert_well_formed<S: cfg_toy::CfgSymbol>(ast: &[cfg_toy::Node<'_, S>], src: &[S::Terminal]) {
+    use std::borrow::Borrow;
+    assert_eq!(ast[0].transitive_children + 1, ast.len());
+    for (i, node) in ast.iter().enumerate() {
+        let mut cursor = node.start;
+        let mut child = i + 1;
+        for part in node.transition {
+            match part.as_part() {
+                Ok(terminal) => {
+                    assert!(&src[cursor] == terminal.borrow(), "terminal mismatch at {cursor}");
+                    cursor += 1;
+                }
+                Err(_) => {
+                    assert_eq!(ast[child].start, cursor, "child of node {i} is not contiguous");
+                    cursor = ast[child].end;
+                    child += 1 + ast[child].transitive_children;
+                }
+            }
+        }
+        assert_eq!(cursor, node.end, "node {i} doesn't cover its span");
+        assert_eq!(child, i + 1 + node.transitive_children);
+    }
+}
+#[test]
+fn long_right_recursion() {
+    let cfg = cfg_toy::cfg! {
+        list item;
+
+        list ::= item "," list .
+        list ::= item .
+        item ::= "a" item .
+        item ::= .
+    }
+    .0;
+    // Both a long item and a long list
+    let src = "a".repeat(100_000) + &",a".repeat(100_000);
+    let src = src.as_bytes();
+    let completions = cfg_toy::parse_earley(&cfg, src, 256, ());
+    // Every reduction path is followed once, so the chart only grows linearly
+    assert!(completions.completions.len() <= 5 * src.len());
+    assert!(completions.completed.len() <= 5 * src.len());
+    assert!(completions.forwarding_records.len() <= src.len());
+    let ast = cfg_toy::trace_to_ast(&cfg, src, &completions, &256);
+    assert_well_formed(&ast, src);
+}
+#[test]
+fn packed_states() {
+    // The states are sorted and deduplicated for every input symbol
+    assert_eq!(std::mem::size_of::<cfg_toy::recognizer::State>(), 12);
+    let cfg = cfg_toy::cfg! {
+        list item;
+
+        list ::= item "," list .
+        list ::= item .
+        item ::= "a" item .
+        item ::= .
+    }
+    .0;
+    let completions = cfg_toy::parse_earley(&cfg, b"aa,a", 256, ());
+    for c in &completions.completions {
+        // Each waiting item has matched the nonterminal it awaits
+        assert_eq!(c.state.parts(&cfg)[c.state.dot as usize - 1], c.awaited);
+    }
+}
+#[test]
+fn first_sets() {
+    let (cfg, names) = cfg_toy::cfg! {
+        expr primary ws not;
+
+        ws ::= .
+        ws ::= " " ws .
+        not ::= "not" ws .
+
+        expr ::= ws primary ws .
+        primary ::= not primary .
+        primary ::= "(" expr ")" .
+        primary ::= "true" .
+    };
+    let nt = |name| 256 + names.iter().position(|&n| n == name).unwrap() as u32;
+    let starts = |name| (0..=255u8).filter(|&b| cfg.nt_can_start_with(nt(name), &b)).collect::<Vec<_>>();
+    assert_eq!(starts("primary"), b"(nt");
+    // `ws` can be skipped
+    assert_eq!(starts("expr"), b" (nt");
+    assert_eq!(starts("ws"), b" ");
+    // Rules that can't start with the input symbol aren't predicted, so nothing waits on `not`
+    let completions = cfg_toy::parse_earley(&cfg, b" (true) ", 256, ());
+    assert!(completions.completions.iter().all(|c| c.awaited != nt("not")));
+    assert!(completions.completions.iter().any(|c| c.awaited == nt("expr")));
+    let ast = cfg_toy::trace_to_ast(&cfg, b" (true) ", &completions, &256);
+    assert_eq!(ast.len(), 10);
+}
+#[test]
+fn last_nonterminal_without_rules() {
+    // `never` has no rules and comes last, so it's past the end of the FIRST sets
+    let (cfg, _) = cfg_toy::cfg! {
+        s a never;
+
+        s ::= "x" a .
+        s ::= "x" never .
+        a ::= "y" .
+    };
+    assert!(!cfg.nt_can_start_with(258, &b'x'));
+    assert!(cfg_toy::try_parse_earley(&cfg, b"xy", 256, ()).is_ok());
+    assert!(cfg_toy::try_parse_earley(&cfg, b"x", 256, ()).is_err());
+}
+#[test]
+fn deep_rule_chain() {
+    use cfg_toy::grammar::{Cfg, Rule};
+    // Each level takes another round to predict and complete, since `a_i` is awaited
+    // by two rules it can't be skipped over as a deterministic reduction
+    let depth = 20_000;
+    let mut rules = vec![];
+    for i in 0..depth {
+        rules.push(Rule::new(256 + i, vec![256 + i + 1]));
+        rules.push(Rule::new(256 + i, vec![256 + i + 1, b'b' as u32]));
+    }
+    rules.push(Rule::new(256 + depth, vec![b'a' as u32]));
+    let cfg = Cfg::new(rules);
+    let completions = cfg_toy::parse_earley(&cfg, b"ab", 256, ());
+    let ast = cfg_toy::trace_to_ast(&cfg, b"ab", &completions, &256);
+    assert_eq!(ast.len(), depth as usize + 1);
 }
@@ -0,0 +1,54 @@
+use cfg_toy::compiled::CompiledGrammar;
+use cfg_toy::lr0::Lr0Automaton;
+
+mod common;
+use common::{assert_same_parse, json_grammar};
+
+#[test]
+fn same_as_compiled() {
+    let cfg = json_grammar();
+    let automaton = Lr0Automaton::new(&cfg, 256);
+    let compiled = CompiledGrammar::new(&cfg);
+    for src in [
+        r#"[{"a": 10.01, "b" :[ ]}, "ab ba", null,{ }]"#,
+        "  true ",
+        r#"{"a": [1, [0, [true]]], "": "", "b": {"a": 1.}}"#,
+        "[1, 2]",
+        r#"{"a" 1}"#,
+    ] {
+        let src = src.as_bytes();
+        assert_same_parse(&cfg, src, compiled.try_parse(src, 256, ()), automaton.try_parse(src, ()));
+    }
+}
+
+#[test]
+fn nullable_start() {
+    let (cfg, _) = cfg_toy::cfg! {
+        list item;
+
+        list ::= item "," list .
+        list ::= item .
+        item ::= "a" item .
+        item ::= .
+    };
+    let automaton = Lr0Automaton::new(&cfg, 256);
+    for src in ["", ",", "a,", "aa,a,,"] {
+        let completions = automaton.parse(src.as_bytes(), ());
+        let ast = cfg_toy::trace_to_ast(&cfg, src.as_bytes(), &completions, &256);
+        assert_eq!((ast[0].start, ast[0].end), (0, src.len()));
+    }
+    assert_eq!(automaton.try_parse(b"a,b", ()).err().map(|e| e.position), Some(2));
+}
+
+#[test]
+fn long_list() {
+    let cfg = json_grammar();
+    let automaton = Lr0Automaton::new(&cfg, 256);
+    // Each element can end the list, since `ws` is nullable
+    let src = format!("[{}1]", "1, ".repeat(20_000));
+    let src = src.as_bytes();
+    let completions = automaton.parse(src, ());
+    assert!(completions.completed.len() <= 10 * src.len());
+    let ast = cfg_toy::trace_to_ast(&cfg, src, &completions, &256);
+    assert_eq!((ast[0].start, ast[0].end), (0, src.len()));
+}
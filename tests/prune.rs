@@ -0,0 +1,57 @@
+use cfg_toy::completions::Completions;
+use cfg_toy::grammar::Cfg;
+use cfg_toy::lr0::Lr0Automaton;
+
+mod common;
+use common::json_grammar;
+
+fn shape(cfg: &Cfg<u32>, src: &[u8], completions: &Completions<'_, u32>) -> Vec<(usize, usize, usize, usize)> {
+    cfg_toy::trace_to_ast(cfg, src, completions, &256)
+        .iter()
+        .map(|node| (node.rule, node.start, node.end, node.transitive_children))
+        .collect()
+}
+
+#[test]
+fn same_trees() {
+    let cfg = json_grammar();
+    let automaton = Lr0Automaton::new(&cfg, 256);
+    for src in [
+        r#"[{"a": 10.01, "b" :[ ]}, "ab ba", null,{ }]"#,
+        "  true ",
+        r#"{"a": [1, [0, [true]]], "": "", "b": {"a": 1.0}}"#,
+    ] {
+        let src = src.as_bytes();
+        for mut completions in [cfg_toy::parse_earley(&cfg, src, 256, ()), automaton.parse(src, ())] {
+            let expected = shape(&cfg, src, &completions);
+            let (waiting, completed) = (completions.completions.len(), completions.completed.len());
+            completions.prune(src, 256);
+            assert!(completions.completions.len() < waiting && completions.completed.len() < completed);
+            assert_eq!(shape(&cfg, src, &completions), expected);
+
+            // Everything that's left is needed, so pruning again changes nothing
+            let (waiting, completed) = (completions.completions.clone(), completions.completed.clone());
+            completions.prune(src, 256);
+            assert_eq!((completions.completions, completions.completed), (waiting, completed));
+        }
+    }
+}
+
+#[test]
+fn unambiguous_chart_is_the_tree() {
+    let (cfg, _) = cfg_toy::cfg! {
+        list item;
+
+        list ::= item "," list .
+        list ::= item .
+        item ::= "a" item .
+        item ::= "b" .
+    };
+    let src = b"ab,b,aab";
+    let mut completions = cfg_toy::parse_earley(&cfg, src, 256, ());
+    completions.prune(src, 256);
+    let ast = cfg_toy::trace_to_ast(&cfg, src, &completions, &256);
+    // One recognized item for each node of the tree
+    let chart = (0..=src.len()).map(|end| completions.completed_ending_at(end).len()).sum::<usize>();
+    assert_eq!(chart, ast.len());
+}
@@ -0,0 +1,91 @@
+use cfg_toy::query::Query;
+
+fn json_grammar() -> (cfg_toy::grammar::Cfg<u32>, Vec<&'static str>) {
+    cfg_toy::cfg! {
+        element object members member ws string value number;
+
+        ws ::= .
+        ws ::= " " ws .
+        string ::= "\"" "k" "\"" .
+        number ::= "1" .
+        number ::= "2" .
+        value ::= number .
+        value ::= string .
+        value ::= object .
+        element ::= ws value ws .
+        member ::= ws key:string ws ":" value:element .
+        members ::= member .
+        members ::= member "," members .
+        object ::= "{" members "}" .
+        object ::= "{" ws "}" .
+    }
+}
+
+fn text(src: &[u8], capture: &cfg_toy::query::Capture) -> String {
+    String::from_utf8(src[capture.start..capture.end].to_vec()).unwrap()
+}
+
+#[test]
+fn capture_fields() {
+    let (cfg, names) = json_grammar();
+    let query = Query::new(
+        &cfg,
+        &names,
+        "; members with number values
+        (member key: (string) @k value: (element (value (number)) @v))",
+    )
+    .unwrap();
+    assert_eq!(query.capture_names(), ["k", "v"]);
+
+    let src = br#"{ "k": 1, "k" : {"k": "k"}, "k":2 }"#;
+    let completions = cfg_toy::parse_earley(&cfg, src, 256, ());
+    let ast = cfg_toy::trace_to_ast(&cfg, src, &completions, &256);
+    let matches = query.matches(&ast);
+    let captured = matches
+        .iter()
+        .map(|m| m.captures.iter().map(|c| (c.index, text(src, c))).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        captured,
+        [
+            [(0, r#""k""#.to_string()), (1, "1".to_string())],
+            [(0, r#""k""#.to_string()), (1, "2".to_string())],
+        ]
+    );
+    for capture in matches.iter().flat_map(|m| &m.captures) {
+        assert_eq!((ast[capture.node].start, ast[capture.node].end), (capture.start, capture.end));
+    }
+}
+
+#[test]
+fn match_children_in_order() {
+    let (cfg, names) = json_grammar();
+    let src = br#"{"k":{ }}"#;
+    let completions = cfg_toy::parse_earley(&cfg, src, 256, ());
+    let ast = cfg_toy::trace_to_ast(&cfg, src, &completions, &256);
+
+    // Children can be skipped, but not reordered
+    let query = Query::new(&cfg, &names, "(element (value) (ws) @after) (element (ws) @before (_) (ws))").unwrap();
+    let matches = query.matches(&ast);
+    assert_eq!(matches.iter().filter(|m| m.pattern == 0).count(), 2);
+    assert_eq!(matches.iter().filter(|m| m.pattern == 1).count(), 2);
+    let query = Query::new(&cfg, &names, "(element (value) (ws) (value))").unwrap();
+    assert!(query.matches(&ast).is_empty());
+
+    // Every object, through the wildcard
+    let query = Query::new(&cfg, &names, "(_ (object) @object)").unwrap();
+    let objects = query.matches(&ast).iter().map(|m| text(src, &m.captures[0])).collect::<Vec<_>>();
+    assert_eq!(objects, ["{\"k\":{ }}", "{ }"]);
+}
+
+#[test]
+fn compile_errors() {
+    let (cfg, names) = json_grammar();
+    let error = Query::new(&cfg, &names, "(member key: (strin))").unwrap_err();
+    assert_eq!(error.offset, 14);
+    assert_eq!(error.message, "there's no nonterminal named `strin`");
+    let error = Query::new(&cfg, &names, "(member name: (string))").unwrap_err();
+    assert_eq!(error.to_string(), "`member` has no field `name` at offset 8");
+    assert!(Query::new(&cfg, &names, "(member (string)").is_err());
+    assert!(Query::new(&cfg, &names, "(member) @").is_err());
+}
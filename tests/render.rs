@@ -0,0 +1,84 @@
+use cfg_toy::render::TreeRenderer;
+
+mod common;
+use common::logic_grammar;
+
+#[test]
+fn render_tree() {
+    let (cfg, names) = logic_grammar();
+    let src = b"true or  false";
+    let completions = cfg_toy::parse_earley(&cfg, src, 256, ());
+    let ast = cfg_toy::trace_to_ast(&cfg, src, &completions, &256);
+    let rendered = TreeRenderer::new(&cfg, &names).render_to_string(&ast);
+    assert_eq!(
+        rendered,
+        r#"expr ::= and_expr or expr  0..14
+├── and_expr ::= primary  0..4
+│   └── primary ::= "true"  0..4
+├── or ::= gap "or" gap  4..9
+│   ├── gap ::= ws  4..5
+│   │   └── ws ::= " "  4..5
+│   └── gap ::= ws gap  7..9
+│       ├── ws ::= " "  7..8
+│       └── gap ::= ws  8..9
+│           └── ws ::= " "  8..9
+└── expr ::= and_expr  9..14
+    └── and_expr ::= primary  9..14
+        └── primary ::= "false"  9..14
+"#
+    );
+    let rendered = TreeRenderer::new(&cfg, &names)
+        .source(src, 10)
+        .elide_single_children(true)
+        .render_to_string(&ast);
+    assert_eq!(
+        rendered,
+        r#"expr ::= and_expr or expr  0..14  "true or  f…"
+├── and_expr > primary ::= "true"  0..4  "true"
+├── or ::= gap "or" gap  4..9  " or  "
+│   ├── gap > ws ::= " "  4..5  " "
+│   └── gap ::= ws gap  7..9  "  "
+│       ├── ws ::= " "  7..8  " "
+│       └── gap > ws ::= " "  8..9  " "
+└── expr > and_expr > primary ::= "false"  9..14  "false"
+"#
+    );
+}
+
+#[test]
+fn collapse_lists() {
+    let (cfg, names) = logic_grammar();
+    let src = b"true and (false and ab) and false";
+    let completions = cfg_toy::parse_earley(&cfg, src, 256, ());
+    let ast = cfg_toy::trace_to_ast(&cfg, src, &completions, &256);
+    let rendered = TreeRenderer::new(&cfg, &names)
+        .box_drawing(false)
+        .collapse_lists(true)
+        .elide_single_children(true)
+        .render_to_string(&ast);
+    assert_eq!(
+        rendered,
+        r#"expr > and_expr ::= primary and and_expr  0..33
+    primary ::= "true"  0..4
+    and ::= gap "and" gap  4..9
+        gap > ws ::= " "  4..5
+        gap > ws ::= " "  8..9
+    primary ::= "(" expr ")"  9..23
+        expr > and_expr ::= primary and and_expr  10..22
+            primary ::= "false"  10..15
+            and ::= gap "and" gap  15..20
+                gap > ws ::= " "  15..16
+                gap > ws ::= " "  19..20
+            primary > ident ::= alpha ident  20..22
+                alpha ::= "a"  20..21
+                alpha ::= "b"  21..22
+    and ::= gap "and" gap  23..28
+        gap > ws ::= " "  23..24
+        gap > ws ::= " "  27..28
+    primary ::= "false"  28..33
+"#
+    );
+    let mut io = vec![];
+    TreeRenderer::new(&cfg, &names).render_io(&ast, &mut io).unwrap();
+    assert_eq!(String::from_utf8(io).unwrap(), TreeRenderer::new(&cfg, &names).render_to_string(&ast));
+}
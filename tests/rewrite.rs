@@ -0,0 +1,80 @@
+use cfg_toy::Node;
+use cfg_toy::rewrite::Rewrite;
+use cfg_toy::unparse::Unparser;
+
+mod common;
+use common::{logic_grammar, parse};
+
+#[test]
+fn double_negation() {
+    let (cfg, names) = logic_grammar();
+    let rewrite = Rewrite::new(
+        &cfg,
+        &names,
+        "; not not X => X
+        (primary (not) (primary (not) (_) @x)) -> @x",
+    )
+    .unwrap();
+    let mut ast = parse(&cfg, "not not not not true or not not not false");
+    assert_eq!(rewrite.apply(&mut ast), 3);
+    assert_eq!(Unparser::new(&cfg).unparse(&ast).unwrap(), "true or not false");
+    assert_eq!(ast[0].transitive_children + 1, ast.len());
+    let shape = |ast: &[Node<'_, u32>]| ast.iter().map(|n| (n.rule, n.children, n.transitive_children)).collect::<Vec<_>>();
+    assert_eq!(shape(&ast), shape(&parse(&cfg, "true or not false")));
+}
+
+#[test]
+fn build_nodes() {
+    let (cfg, names) = logic_grammar();
+    let rewrite = Rewrite::new(
+        &cfg,
+        &names,
+        r#"
+        ; swap the operands of `or`
+        (expr (and_expr) @a (or) @or (expr (and_expr) @b)) -> (expr @b @or (expr @a))
+        ; and replace literals
+        (primary "false") -> (primary "(" (expr (and_expr (primary "true"))) ")")
+        "#,
+    )
+    .unwrap();
+    let mut ast = parse(&cfg, "true  or false and true");
+    assert_eq!(rewrite.apply(&mut ast), 2);
+    assert_eq!(Unparser::new(&cfg).unparse(&ast).unwrap(), "(true) and true  or true");
+}
+
+#[test]
+fn errors() {
+    let (cfg, names) = logic_grammar();
+    let error = |source: &str| Rewrite::new(&cfg, &names, source).err().map(|e| (e.offset, e.message));
+    assert_eq!(
+        error("(primary (not) (_) @x) -> (expr @x)"),
+        Some((26, "`expr` has no rule like this".to_string()))
+    );
+    assert_eq!(
+        error("(primary (not) (_) @x) -> (and_expr @x)"),
+        Some((26, "the template makes a `and_expr`, but the pattern matches a `primary`".to_string()))
+    );
+    assert_eq!(
+        error("(primary (not) (_) @x) -> @y"),
+        Some((27, "`@y` isn't captured by the pattern".to_string()))
+    );
+    assert_eq!(
+        error("(primary) -> (primary)"),
+        Some((13, "`primary` has more than one rule like this, write out its terminals".to_string()))
+    );
+    assert_eq!(error("(_) @x -> @x"), Some((0, "the pattern needs to say which nonterminal it matches".to_string())));
+    assert_eq!(error("(expr (xor)) -> (expr)"), Some((7, "there's no nonterminal named `xor`".to_string())));
+    assert_eq!(error("(primary \"maybe\") -> @x"), Some((0, "`primary` has no rule like this".to_string())));
+}
+
+#[test]
+fn long_input() {
+    let (cfg, names) = logic_grammar();
+    let rewrite = Rewrite::new(&cfg, &names, r#"(primary "false") -> (primary "(" (expr (and_expr (primary "true"))) ")")"#).unwrap();
+    let src = ["false"; 2000].join(" and ");
+    let mut ast = parse(&cfg, &src);
+    assert_eq!(rewrite.apply(&mut ast), 2000);
+    let unparsed = Unparser::new(&cfg).unparse(&ast).unwrap();
+    assert_eq!(unparsed, ["(true)"; 2000].join(" and "));
+    assert_eq!(ast[0].transitive_children + 1, ast.len());
+}
@@ -0,0 +1,74 @@
+use cfg_toy::grammar::Shape;
+use cfg_toy::serialize::{SExpr, to_json, to_sexpr};
+
+mod common;
+use common::logic_grammar;
+
+// The `and`s and `or`s are left out, so the trees only have the expressions
+fn grammar() -> (cfg_toy::grammar::Cfg<u32>, Vec<&'static str>) {
+    let (mut cfg, names) = logic_grammar();
+    cfg.set_shape(261, Shape::Silent);
+    cfg.set_shape(262, Shape::Silent);
+    (cfg, names)
+}
+
+#[test]
+fn write_json() {
+    let (cfg, names) = grammar();
+    let src = b"true or false";
+    let completions = cfg_toy::parse_earley(&cfg, src, 256, ());
+    let ast = cfg_toy::trace_to_ast(&cfg, src, &completions, &256);
+    assert_eq!(
+        to_json(&cfg, &names, &ast),
+        r#"{"type": "expr", "span": [0, 13], "children": [{"type": "and_expr", "span": [0, 4], "children": [{"type": "primary", "span": [0, 4], "children": []}]}, {"type": "expr", "span": [8, 13], "children": [{"type": "and_expr", "span": [8, 13], "children": [{"type": "primary", "span": [8, 13], "children": []}]}]}]}"#
+    );
+}
+
+#[test]
+fn sexpr_round_trip() {
+    let (cfg, names) = grammar();
+    let src = b"(true and false) or true";
+    let completions = cfg_toy::parse_earley(&cfg, src, 256, ());
+    let ast = cfg_toy::trace_to_ast(&cfg, src, &completions, &256);
+    let written = to_sexpr(&cfg, &names, &ast);
+    assert_eq!(
+        written,
+        "(expr (and_expr (primary (expr (and_expr (primary) (and_expr (primary)))))) (expr (and_expr (primary))))"
+    );
+    let read = SExpr::parse(&written).unwrap();
+    assert_eq!(read, SExpr::from_ast(&cfg, &names, &ast));
+    assert_eq!(read.to_string(), written);
+
+    // Expected trees can be written more loosely
+    let expected = SExpr::parse(
+        "(expr
+            (and_expr (primary (expr (and_expr primary (and_expr primary)))))
+            ; the right hand side of the `or`
+            (expr (and_expr primary)))",
+    )
+    .unwrap();
+    assert_eq!(expected, read);
+    let different = SExpr::parse("(expr (and_expr primary) (expr (and_expr primary)))").unwrap();
+    assert_ne!(different, read);
+}
+
+#[test]
+fn sexpr_errors() {
+    assert_eq!(SExpr::parse("(expr (primary)").unwrap_err().message, "unclosed `(`");
+    assert_eq!(SExpr::parse("(expr))").unwrap_err().offset, 6);
+    assert!(SExpr::parse("()").is_err());
+    assert!(SExpr::parse("").is_err());
+}
+
+#[test]
+fn deep_sexpr() {
+    let depth = 200_000;
+    let src = "(a ".repeat(depth) + &")".repeat(depth);
+    let read = SExpr::parse(&src).unwrap();
+    let copy = read.clone();
+    assert_eq!(read, copy);
+    assert!(read.to_string() == "(a ".repeat(depth - 1) + "(a" + &")".repeat(depth));
+    let shallower = SExpr::parse(&("(a ".repeat(depth - 1) + &")".repeat(depth - 1))).unwrap();
+    assert_ne!(read, shallower);
+    assert_eq!(format!("{:?}", SExpr::parse("(a b (c))").unwrap()), "SExpr((a (b) (c)))");
+}
@@ -0,0 +1,124 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use cfg_toy::compiled::{CompiledGrammar, CompiledSession};
+use cfg_toy::recognizer::ParserSession;
+
+// Counts the allocations made on the current thread, so the other tests don't get in the way
+struct CountingAlloc;
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        unsafe { System.alloc(layout) }
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+const LINES: [&str; 4] = [
+    "GET /index.html 200",
+    "POST /api/items 201",
+    "GET /a/b/c/d 404",
+    "PUT /x 500",
+];
+
+fn log_grammar() -> cfg_toy::grammar::Cfg<u32> {
+    cfg_toy::cfg! {
+        line method path segment name status digit letter;
+
+        line ::= method " " path " " status .
+        method ::= "GET" .
+        method ::= "POST" .
+        method ::= "PUT" .
+        path ::= segment path .
+        path ::= segment .
+        segment ::= "/" name .
+        segment ::= "/" .
+        name ::= letter name .
+        name ::= letter .
+        letter ::= "a" . letter ::= "b" . letter ::= "c" . letter ::= "d" . letter ::= "e" .
+        letter ::= "h" . letter ::= "i" . letter ::= "l" . letter ::= "m" . letter ::= "n" .
+        letter ::= "p" . letter ::= "s" . letter ::= "t" . letter ::= "x" . letter ::= "." .
+        status ::= digit digit digit .
+        digit ::= "0" . digit ::= "1" . digit ::= "2" . digit ::= "4" . digit ::= "5" .
+    }
+    .0
+}
+
+#[test]
+fn reused_session_doesnt_allocate() {
+    let cfg = log_grammar();
+    let mut session = ParserSession::new(&cfg);
+    // The first round grows the buffers to fit
+    for line in LINES {
+        session.parse(line.as_bytes(), 256, ());
+    }
+    for line in LINES {
+        let src = line.as_bytes();
+        let fresh = allocations(|| drop(cfg_toy::parse_earley(&cfg, src, 256, ())));
+        assert!(fresh > 0);
+        assert_eq!(allocations(|| {
+            session.parse(src, 256, ());
+        }), 0);
+    }
+    // Errors leave the session usable
+    assert_eq!(session.try_parse(b"GET /a 20", 256, ()).err().map(|e| e.position), Some(9));
+    assert_eq!(session.try_parse(b"GET a", 256, ()).err().map(|e| e.position), Some(4));
+    assert_eq!(allocations(|| {
+        session.parse(LINES[0].as_bytes(), 256, ());
+    }), 0);
+}
+
+#[test]
+fn same_chart_as_parse_earley() {
+    let cfg = log_grammar();
+    let mut session = ParserSession::new(&cfg);
+    for line in LINES.iter().chain(&LINES) {
+        let src = line.as_bytes();
+        let expected = cfg_toy::parse_earley(&cfg, src, 256, ());
+        let completions = session.parse(src, 256, ());
+        for end in 0..=src.len() {
+            assert_eq!(completions.completed_ending_at(end), expected.completed_ending_at(end));
+        }
+        assert_eq!(completions.completions, expected.completions);
+    }
+}
+
+#[test]
+fn reused_compiled_session_doesnt_allocate() {
+    let cfg = log_grammar();
+    let compiled = CompiledGrammar::new(&cfg);
+    let mut session = CompiledSession::new(&compiled);
+    for line in LINES {
+        session.parse(line.as_bytes(), 256, ());
+    }
+    for line in LINES {
+        let src = line.as_bytes();
+        let expected = compiled.parse(src, 256, ());
+        let completions = session.parse(src, 256, ());
+        assert_eq!(completions.completed_ending_at(src.len()), expected.completed_ending_at(src.len()));
+        assert_eq!(allocations(|| {
+            session.parse(src, 256, ());
+        }), 0);
+    }
+    assert_eq!(session.try_parse(b"GET /a 20", 256, ()).err().map(|e| e.position), Some(9));
+    assert_eq!(allocations(|| {
+        session.parse(LINES[0].as_bytes(), 256, ());
+    }), 0);
+}
@@ -0,0 +1,120 @@
+use cfg_toy::Node;
+use cfg_toy::grammar::Shape;
+
+fn shaped_grammar() -> (cfg_toy::grammar::Cfg<u32>, Vec<&'static str>) {
+    cfg_toy::cfg! {
+        expr and_expr primary #[atomic] ident alpha #[silent] gap ws #[inline] and_tail;
+
+        ws ::= " " .
+        gap ::= ws.
+        gap ::= ws gap.
+
+        alpha ::= "a".
+        alpha ::= "b".
+        ident ::= alpha ident.
+        ident ::= alpha.
+
+        and_tail ::= gap "and" gap and_expr.
+
+        expr ::= and_expr.
+        and_expr ::= primary and_tail.
+        and_expr ::= primary.
+        primary ::= "(" expr ")".
+        primary ::= ident.
+    }
+}
+
+// (name, span, children) in pre-order
+fn describe<'a>(
+    ast: &[Node<'_, u32>],
+    cfg: &cfg_toy::grammar::Cfg<u32>,
+    names: &[&'a str],
+) -> Vec<(&'a str, usize, usize, usize)> {
+    ast.iter()
+        .map(|node| {
+            let nt = cfg.rules[node.rule].for_nt;
+            (
+                names[nt as usize - 256],
+                node.start,
+                node.end,
+                node.children,
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn shape_from_grammar() {
+    let (cfg, names) = shaped_grammar();
+    assert_eq!(cfg.shape(259), Shape::Atomic);
+    assert_eq!(cfg.shape(261), Shape::Silent);
+    assert_eq!(cfg.shape(262), Shape::Normal);
+    assert_eq!(cfg.shape(263), Shape::Inlined);
+
+    let src = b"ab and (b and a)";
+    let completions = cfg_toy::parse_earley(&cfg, src, 256, ());
+    let ast = cfg_toy::trace_to_ast(&cfg, src, &completions, &256);
+    assert_eq!(
+        describe(&ast, &cfg, &names),
+        [
+            ("expr", 0, 16, 1),
+            ("and_expr", 0, 16, 2),
+            ("primary", 0, 2, 1),
+            ("ident", 0, 2, 0),
+            // The and_tail's and_expr is spliced into its parent, without the gaps
+            ("and_expr", 7, 16, 1),
+            ("primary", 7, 16, 1),
+            ("expr", 8, 15, 1),
+            ("and_expr", 8, 15, 2),
+            ("primary", 8, 9, 1),
+            ("ident", 8, 9, 0),
+            ("and_expr", 14, 15, 1),
+            ("primary", 14, 15, 1),
+            ("ident", 14, 15, 0),
+        ]
+    );
+    // The subtrees are still consistent, so the walkers work on them
+    let leaves = cfg_toy::visit::fold(&ast, |node: &Node<'_, u32>, children: Vec<usize>| {
+        children
+            .into_iter()
+            .sum::<usize>()
+            .max((node.children == 0) as usize)
+    });
+    assert_eq!(leaves, 3);
+    assert_eq!(ast[0].transitive_children, ast.len() - 1);
+}
+
+#[test]
+fn unshaped_grammar_is_unchanged() {
+    let (mut cfg, names) = shaped_grammar();
+    cfg.nt_shape.clear();
+    let src = b"a and b";
+    let completions = cfg_toy::parse_earley(&cfg, src, 256, ());
+    let ast = cfg_toy::trace_to_ast(&cfg, src, &completions, &256);
+    let described = describe(&ast, &cfg, &names);
+    assert!(described.iter().any(|&(name, ..)| name == "gap"));
+    assert!(described.iter().any(|&(name, ..)| name == "and_tail"));
+    assert!(described.iter().any(|&(name, ..)| name == "alpha"));
+}
+
+#[test]
+fn consumers_of_shaped_trees() {
+    let (cfg, _) = shaped_grammar();
+    let src = b"ab and (b and a)";
+    let completions = cfg_toy::parse_earley(&cfg, src, 256, ());
+    let ast = cfg_toy::trace_to_ast(&cfg, src, &completions, &256);
+    cfg_toy::print_ast(&ast, 0);
+
+    // The text around the children of reshaped nodes is kept in tokens
+    let cst = cfg_toy::cst::CstBuilder::new(&cfg).build(&ast);
+    assert_eq!(cst.text(src), src);
+    let ident = cst.tokens.iter().find(|token| token.start == 0).unwrap();
+    assert_eq!(ident.end, 2);
+
+    // The actions can't be given a value for each nonterminal
+    let actions = cfg_toy::actions::Actions::<()> {
+        by_rule: cfg.rules.iter().map(|_| None).collect(),
+    };
+    let err = cfg_toy::evaluate(&cfg, &ast, &actions).unwrap_err();
+    assert!(cfg.rule_is_reshaped(ast[err.node].rule));
+}
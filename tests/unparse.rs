@@ -0,0 +1,114 @@
+use cfg_toy::unparse::{Layout, Unparser};
+
+mod common;
+use common::{logic_grammar, parse};
+
+const GAP: u32 = 260;
+
+// primary ::= "(" expr ")"
+fn parens(cfg: &cfg_toy::grammar::Cfg<u32>) -> usize {
+    cfg.rules.iter().position(|rule| rule.parts == [b'(' as u32, 256, b')' as u32]).unwrap()
+}
+
+#[test]
+fn reproduce_source() {
+    let (cfg, _) = logic_grammar();
+    let src = "(true and\n false)  or not  true";
+    assert_eq!(Unparser::new(&cfg).unparse(&parse(&cfg, src)).unwrap(), src);
+}
+
+#[test]
+fn format_layout() {
+    let (cfg, _) = logic_grammar();
+    let ast = parse(&cfg, "true   or\n\nfalse and  not   true");
+    let unparser = Unparser::new(&cfg).layout(GAP, Layout::Space);
+    assert_eq!(unparser.unparse(&ast).unwrap(), "true or false and not true");
+}
+
+#[test]
+fn minimal_parentheses() {
+    let (cfg, _) = logic_grammar();
+    let unparser = Unparser::new(&cfg).precedence(&[256, 257, 258], parens(&cfg));
+    for (src, expected) in [
+        ("((true)) and (true or false)", "true and (true or false)"),
+        ("not (not (false))", "not not false"),
+        ("(true and false) or (((false)))", "true and false or false"),
+        ("(not (true or false))", "not (true or false)"),
+    ] {
+        assert_eq!(unparser.unparse(&parse(&cfg, src)).unwrap(), expected);
+    }
+}
+
+#[test]
+fn parenthesize_modified_tree() {
+    let (cfg, _) = logic_grammar();
+    let mut ast = parse(&cfg, "not true");
+    let or = parse(&cfg, "true or false");
+    // Swap the `true` for the whole `or` expression
+    let (last, _) = ast.iter().enumerate().next_back().unwrap();
+    assert_eq!(ast[last].transition, [b't' as u32, b'r' as u32, b'u' as u32, b'e' as u32]);
+    ast.truncate(last);
+    for (i, node) in ast.iter_mut().enumerate() {
+        // Only the ancestors contain it
+        if i + node.transitive_children >= last {
+            node.transitive_children += or.len() - 1;
+        }
+    }
+    ast.extend(or.iter().cloned());
+
+    assert_eq!(Unparser::new(&cfg).unparse(&ast).unwrap(), "not true or false");
+    let unparser = Unparser::new(&cfg).precedence(&[256, 257, 258], parens(&cfg));
+    assert_eq!(unparser.unparse(&ast).unwrap(), "not (true or false)");
+}
+
+#[test]
+fn indent_blocks() {
+    let (cfg, _) = cfg_toy::cfg! {
+        block stmts stmt gap ws;
+
+        ws ::= " " .
+        ws ::= "\n" .
+        gap ::= ws .
+        gap ::= ws gap .
+        block ::= "{" gap stmts gap "}" .
+        stmts ::= stmt .
+        stmts ::= stmt gap stmts .
+        stmt ::= "x" ";" .
+        stmt ::= block .
+    };
+    let src = "{ x; { x;  { x; } } x; }";
+    let completions = cfg_toy::parse_earley(&cfg, src.as_bytes(), 256, ());
+    let ast = cfg_toy::trace_to_ast(&cfg, src.as_bytes(), &completions, &256);
+    let unparser = Unparser::new(&cfg).layout(259, Layout::Newline).indent(256).indent_with("  ");
+    assert_eq!(unparser.unparse(&ast).unwrap(), "{\n  x;\n  {\n    x;\n    {\n      x;\n    }\n  }\n  x;\n}");
+}
+
+#[test]
+fn labelled_symbols() {
+    let cfg = logic_grammar().0.map(|&symbol| cfg_toy::LabelledSymbol { symbol, label: "" });
+    let src = cfg_toy::cast_buf(b"not (true  or false)");
+    let completions = cfg_toy::parse_earley(&cfg, src, 256, ());
+    let root = cfg_toy::LabelledSymbol { symbol: 256, label: "" };
+    let ast = cfg_toy::trace_to_ast(&cfg, src, &completions, &root);
+    let unparser = Unparser::new(&cfg).layout(GAP, Layout::Space);
+    assert_eq!(unparser.unparse(&ast).unwrap(), "not (true or false)");
+}
+
+#[test]
+fn reshaped_tree() {
+    let (cfg, _) = cfg_toy::cfg! {
+        list #[atomic] item #[silent] gap;
+
+        gap ::= " " .
+        item ::= "x" item .
+        item ::= "x" .
+        list ::= item gap list .
+        list ::= item .
+    };
+    let src = b"xx x";
+    let completions = cfg_toy::parse_earley(&cfg, src, 256, ());
+    let ast = cfg_toy::trace_to_ast(&cfg, src, &completions, &256);
+    // Neither the silent gap nor the inside of the atomic item is in the tree
+    let err = Unparser::new(&cfg).unparse(&ast).unwrap_err();
+    assert_eq!(err.node, 0);
+}
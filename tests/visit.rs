@@ -0,0 +1,75 @@
+use cfg_toy::Node;
+use cfg_toy::visit::{Visitor, fold, walk};
+
+mod common;
+use common::logic_grammar;
+
+#[test]
+fn fold_evaluates_logic() {
+    let (cfg, _) = logic_grammar();
+    let rule = |nt: u32, idx: usize| &cfg.rules.iter().filter(|r| r.for_nt == nt).nth(idx).unwrap().parts[..];
+    let (expr, and_expr, primary) = (256, 257, 258);
+    for (src, expected) in [
+        ("true or false and not false", true),
+        ("not (true or false) or false", false),
+        ("(true and not false) and not not true", true),
+    ] {
+        let completions = cfg_toy::parse_earley(&cfg, src.as_bytes(), 256, ());
+        let ast = cfg_toy::trace_to_ast(&cfg, src.as_bytes(), &completions, &256);
+        let value = fold(&ast, |node: &Node<'_, u32>, children: Vec<bool>| {
+            let t = node.transition;
+            if t == rule(expr, 0) {
+                children[0] || children[2]
+            } else if t == rule(and_expr, 0) {
+                children[0] && children[2]
+            } else if t == rule(primary, 0) {
+                !children[1]
+            } else if t == rule(primary, 2) {
+                true
+            } else {
+                // Everything else passes through its first child
+                children.first().copied().unwrap_or(false)
+            }
+        });
+        assert_eq!(value, expected, "{src}");
+    }
+}
+
+#[test]
+fn walk_deep_tree() {
+    let cfg = cfg_toy::cfg! {
+        a;
+
+        a ::= "a" a .
+        a ::= .
+    }
+    .0;
+    let src = vec![b'a'; 5000];
+    let completions = cfg_toy::parse_earley(&cfg, &src, 256, ());
+    let ast = cfg_toy::trace_to_ast(&cfg, &src, &completions, &256);
+
+    struct Depth {
+        current: usize,
+        max: usize,
+        exits: usize,
+    }
+    impl<'c> Visitor<'c, u32> for Depth {
+        fn enter(&mut self, _node: &Node<'c, u32>) {
+            self.current += 1;
+            self.max = self.max.max(self.current);
+        }
+        fn exit(&mut self, _node: &Node<'c, u32>) {
+            self.current -= 1;
+            self.exits += 1;
+        }
+    }
+    let mut depth = Depth { current: 0, max: 0, exits: 0 };
+    walk(&ast, &mut depth);
+    assert_eq!((depth.current, depth.max, depth.exits), (0, src.len() + 1, ast.len()));
+
+    let len = fold(&ast, |node: &Node<'_, u32>, children: Vec<usize>| {
+        assert_eq!(children.len(), node.children);
+        children.iter().sum::<usize>() + node.transition.len().min(1)
+    });
+    assert_eq!(len, src.len());
+}